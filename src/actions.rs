@@ -12,17 +12,135 @@ pub enum Action {
     PauseSound,
     SelectNextTrack,
     SelectPreviousTrack,
+    OpenSource,
+    SelectNextSource,
+    SelectPreviousSource,
+    SwitchFocus,
+    Search,
+    RunSearch,
+    CloseSearch,
+    OpenResult,
+    SelectNextResult,
+    SelectPreviousResult,
+    NextSearchTab,
+    NextSearchPage,
+    PreviousSearchPage,
+    OpenAlbum,
+    OpenArtist,
+    GoBack,
+    OpenLibrary,
+    OpenLibraryItem,
+    SelectNextLibraryItem,
+    SelectPreviousLibraryItem,
+    NextLibraryTab,
+    NextLibraryPage,
+    PreviousLibraryPage,
+    CloseLibrary,
+    MakeAvailableOffline,
+    LikeTrack,
+    DislikeTrack,
+    AddToPlaylist,
+    PickPlaylist,
+    SelectNextPlaylist,
+    SelectPreviousPlaylist,
+    CancelPicker,
+    SeekForward,
+    SeekBackward,
+    JumpForward,
+    JumpBackward,
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+    NextSong,
+    PreviousSong,
+    PlayNext,
+    AddToQueue,
+    OpenQueue,
+    PlayQueued,
+    SelectNextQueued,
+    SelectPreviousQueued,
+    RemoveQueued,
+    MoveQueuedUp,
+    MoveQueuedDown,
+    ClearQueue,
+    CloseQueue,
+    ToggleShuffle,
+    CycleRepeat,
+    CrossfadeLonger,
+    CrossfadeShorter,
+    CycleNormalization,
+    PreAmpUp,
+    PreAmpDown,
 }
 
 impl Action {
     /// All available actions
     pub fn iterator() -> Iter<'static, Action> {
-        static ACTIONS: [Action; 5] = [
+        static ACTIONS: [Action; 64] = [
             Action::Quit,
             Action::PlaySound,
             Action::PauseSound,
             Action::SelectNextTrack,
             Action::SelectPreviousTrack,
+            Action::OpenSource,
+            Action::SelectNextSource,
+            Action::SelectPreviousSource,
+            Action::SwitchFocus,
+            Action::Search,
+            Action::RunSearch,
+            Action::CloseSearch,
+            Action::OpenResult,
+            Action::SelectNextResult,
+            Action::SelectPreviousResult,
+            Action::NextSearchTab,
+            Action::NextSearchPage,
+            Action::PreviousSearchPage,
+            Action::OpenAlbum,
+            Action::OpenArtist,
+            Action::GoBack,
+            Action::OpenLibrary,
+            Action::OpenLibraryItem,
+            Action::SelectNextLibraryItem,
+            Action::SelectPreviousLibraryItem,
+            Action::NextLibraryTab,
+            Action::NextLibraryPage,
+            Action::PreviousLibraryPage,
+            Action::CloseLibrary,
+            Action::MakeAvailableOffline,
+            Action::LikeTrack,
+            Action::DislikeTrack,
+            Action::AddToPlaylist,
+            Action::PickPlaylist,
+            Action::SelectNextPlaylist,
+            Action::SelectPreviousPlaylist,
+            Action::CancelPicker,
+            Action::SeekForward,
+            Action::SeekBackward,
+            Action::JumpForward,
+            Action::JumpBackward,
+            Action::VolumeUp,
+            Action::VolumeDown,
+            Action::ToggleMute,
+            Action::NextSong,
+            Action::PreviousSong,
+            Action::PlayNext,
+            Action::AddToQueue,
+            Action::OpenQueue,
+            Action::PlayQueued,
+            Action::SelectNextQueued,
+            Action::SelectPreviousQueued,
+            Action::RemoveQueued,
+            Action::MoveQueuedUp,
+            Action::MoveQueuedDown,
+            Action::ClearQueue,
+            Action::CloseQueue,
+            Action::ToggleShuffle,
+            Action::CycleRepeat,
+            Action::CrossfadeLonger,
+            Action::CrossfadeShorter,
+            Action::CycleNormalization,
+            Action::PreAmpUp,
+            Action::PreAmpDown,
         ];
         ACTIONS.iter()
     }
@@ -35,6 +153,65 @@ impl Action {
             Action::PauseSound => &[Key::Char('p')],
             Action::SelectNextTrack => &[Key::Down],
             Action::SelectPreviousTrack => &[Key::Up],
+            Action::OpenSource => &[Key::Enter],
+            Action::SelectNextSource => &[Key::Down],
+            Action::SelectPreviousSource => &[Key::Up],
+            Action::SwitchFocus => &[Key::Tab],
+            Action::Search => &[Key::Char('/')],
+            Action::RunSearch => &[Key::Enter],
+            Action::CloseSearch => &[Key::Esc],
+            Action::OpenResult => &[Key::Enter],
+            Action::SelectNextResult => &[Key::Down],
+            Action::SelectPreviousResult => &[Key::Up],
+            Action::NextSearchTab => &[Key::Tab],
+            Action::NextSearchPage => &[Key::PageDown],
+            Action::PreviousSearchPage => &[Key::PageUp],
+            Action::OpenAlbum => &[Key::Char('a')],
+            Action::OpenArtist => &[Key::Char('r')],
+            Action::GoBack => &[Key::Backspace],
+            Action::OpenLibrary => &[Key::Char('l')],
+            Action::OpenLibraryItem => &[Key::Enter],
+            Action::SelectNextLibraryItem => &[Key::Down],
+            Action::SelectPreviousLibraryItem => &[Key::Up],
+            Action::NextLibraryTab => &[Key::Tab],
+            Action::NextLibraryPage => &[Key::PageDown],
+            Action::PreviousLibraryPage => &[Key::PageUp],
+            Action::CloseLibrary => &[Key::Esc],
+            Action::MakeAvailableOffline => &[Key::Char('o')],
+            Action::LikeTrack => &[Key::Char('f')],
+            Action::DislikeTrack => &[Key::Char('d')],
+            Action::AddToPlaylist => &[Key::Char('A')],
+            Action::PickPlaylist => &[Key::Enter],
+            Action::SelectNextPlaylist => &[Key::Down],
+            Action::SelectPreviousPlaylist => &[Key::Up],
+            Action::CancelPicker => &[Key::Esc],
+            Action::SeekForward => &[Key::Right],
+            Action::SeekBackward => &[Key::Left],
+            Action::JumpForward => &[Key::Char('>')],
+            Action::JumpBackward => &[Key::Char('<')],
+            Action::VolumeUp => &[Key::Char('+')],
+            Action::VolumeDown => &[Key::Char('-')],
+            Action::ToggleMute => &[Key::Char('m')],
+            Action::NextSong => &[Key::Char('n')],
+            Action::PreviousSong => &[Key::Char('b')],
+            Action::PlayNext => &[Key::Char('N')],
+            Action::AddToQueue => &[Key::Char('e')],
+            Action::OpenQueue => &[Key::Char('u')],
+            Action::PlayQueued => &[Key::Enter],
+            Action::SelectNextQueued => &[Key::Down],
+            Action::SelectPreviousQueued => &[Key::Up],
+            Action::RemoveQueued => &[Key::Delete],
+            Action::MoveQueuedUp => &[Key::Char('K')],
+            Action::MoveQueuedDown => &[Key::Char('J')],
+            Action::ClearQueue => &[Key::Char('c')],
+            Action::CloseQueue => &[Key::Esc],
+            Action::ToggleShuffle => &[Key::Char('s')],
+            Action::CycleRepeat => &[Key::Char('R')],
+            Action::CrossfadeLonger => &[Key::Char(']')],
+            Action::CrossfadeShorter => &[Key::Char('[')],
+            Action::CycleNormalization => &[Key::Char('g')],
+            Action::PreAmpUp => &[Key::Char(')')],
+            Action::PreAmpDown => &[Key::Char('(')],
         }
     }
 }
@@ -48,6 +225,65 @@ impl Display for Action {
             Action::PauseSound => "PauseSound",
             Action::SelectNextTrack => "SelectNextTrack",
             Action::SelectPreviousTrack => "SelectPreviousTrack",
+            Action::OpenSource => "OpenSource",
+            Action::SelectNextSource => "SelectNextSource",
+            Action::SelectPreviousSource => "SelectPreviousSource",
+            Action::SwitchFocus => "SwitchFocus",
+            Action::Search => "Search",
+            Action::RunSearch => "RunSearch",
+            Action::CloseSearch => "CloseSearch",
+            Action::OpenResult => "OpenResult",
+            Action::SelectNextResult => "SelectNextResult",
+            Action::SelectPreviousResult => "SelectPreviousResult",
+            Action::NextSearchTab => "NextSearchTab",
+            Action::NextSearchPage => "NextSearchPage",
+            Action::PreviousSearchPage => "PreviousSearchPage",
+            Action::OpenAlbum => "OpenAlbum",
+            Action::OpenArtist => "OpenArtist",
+            Action::GoBack => "GoBack",
+            Action::OpenLibrary => "OpenLibrary",
+            Action::OpenLibraryItem => "OpenLibraryItem",
+            Action::SelectNextLibraryItem => "SelectNextLibraryItem",
+            Action::SelectPreviousLibraryItem => "SelectPreviousLibraryItem",
+            Action::NextLibraryTab => "NextLibraryTab",
+            Action::NextLibraryPage => "NextLibraryPage",
+            Action::PreviousLibraryPage => "PreviousLibraryPage",
+            Action::CloseLibrary => "CloseLibrary",
+            Action::MakeAvailableOffline => "MakeAvailableOffline",
+            Action::LikeTrack => "LikeTrack",
+            Action::DislikeTrack => "DislikeTrack",
+            Action::AddToPlaylist => "AddToPlaylist",
+            Action::PickPlaylist => "PickPlaylist",
+            Action::SelectNextPlaylist => "SelectNextPlaylist",
+            Action::SelectPreviousPlaylist => "SelectPreviousPlaylist",
+            Action::CancelPicker => "CancelPicker",
+            Action::SeekForward => "SeekForward",
+            Action::SeekBackward => "SeekBackward",
+            Action::JumpForward => "JumpForward",
+            Action::JumpBackward => "JumpBackward",
+            Action::VolumeUp => "VolumeUp",
+            Action::VolumeDown => "VolumeDown",
+            Action::ToggleMute => "ToggleMute",
+            Action::NextSong => "NextSong",
+            Action::PreviousSong => "PreviousSong",
+            Action::PlayNext => "PlayNext",
+            Action::AddToQueue => "AddToQueue",
+            Action::OpenQueue => "OpenQueue",
+            Action::PlayQueued => "PlayQueued",
+            Action::SelectNextQueued => "SelectNextQueued",
+            Action::SelectPreviousQueued => "SelectPreviousQueued",
+            Action::RemoveQueued => "RemoveQueued",
+            Action::MoveQueuedUp => "MoveQueuedUp",
+            Action::MoveQueuedDown => "MoveQueuedDown",
+            Action::ClearQueue => "ClearQueue",
+            Action::CloseQueue => "CloseQueue",
+            Action::ToggleShuffle => "ToggleShuffle",
+            Action::CycleRepeat => "CycleRepeat",
+            Action::CrossfadeLonger => "CrossfadeLonger",
+            Action::CrossfadeShorter => "CrossfadeShorter",
+            Action::CycleNormalization => "CycleNormalization",
+            Action::PreAmpUp => "PreAmpUp",
+            Action::PreAmpDown => "PreAmpDown",
         };
         write!(f, "{}", str)
     }
@@ -111,3 +347,23 @@ impl From<Vec<Action>> for Actions {
         Self(actions)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn like_actions_are_found_by_key() {
+        let actions: Actions = vec![Action::Quit, Action::LikeTrack, Action::DislikeTrack].into();
+
+        assert_eq!(actions.find(Key::Char('f')), Some(&Action::LikeTrack));
+        assert_eq!(actions.find(Key::Char('d')), Some(&Action::DislikeTrack));
+        assert_eq!(actions.find(Key::Char('x')), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Conflict key <Enter>")]
+    fn actions_with_same_key_conflict() {
+        let _: Actions = vec![Action::PlaySound, Action::OpenSource].into();
+    }
+}
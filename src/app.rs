@@ -1,18 +1,46 @@
 use crate::actions::Action;
 use crate::actions::Actions;
+use crate::catalog::{CatalogItem, CatalogTab};
+use crate::config::{self, Config};
 use crate::inputs::key::Key;
+use crate::io::download::{DownloadId, Progress, ProgressReports};
 use crate::io::IoEvent;
+use crate::library::LibraryView;
+use crate::queue::Queue;
+use crate::search::SearchView;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use tokio::sync::mpsc::error::TrySendError;
 use tui::widgets::ListState;
 
-use log::{debug, error, warn};
-use yandex_rust_music::{Client, Player, Status, Track};
+use log::{debug, error, info, warn};
+use yandex_rust_music::{
+    Cache, Client, Error, Feedback, Fetcher, GeneratedPlaylist, GeneratedPlaylistType, Media,
+    MusicBackend, Normalization, OfflineBackend, OfflineStore, Player, Playlist, Result, Station,
+    StationId, StationTracks, Status, Tags, Track,
+};
 
-#[derive(Clone)]
+/// Step of the seek with arrow keys
+const SEEK_STEP: Duration = Duration::from_secs(5);
+/// Step of the long seek
+const JUMP_STEP: Duration = Duration::from_secs(30);
+/// Step of the perceived volume
+const VOLUME_STEP: f32 = 0.05;
+/// Step of the crossfade duration
+const CROSSFADE_STEP: Duration = Duration::from_secs(1);
+/// Step of the pre-amp of normalized tracks in dB
+const PRE_AMP_STEP: f32 = 1.0;
+/// Tracks of the offline collections downloaded at once
+const OFFLINE_DOWNLOADS: usize = 2;
+
+#[derive(Clone, Default)]
 pub enum AppState {
+    #[default]
     Init,
     Initialized {
         duration: Duration,
@@ -56,28 +84,53 @@ impl AppState {
     }
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::Init
+#[derive(Clone)]
+pub struct DisplayedList<T> {
+    pub items: Vec<T>,
+    pub state: ListState,
+}
+
+pub type DisplayedTracks = DisplayedList<Track>;
+pub type DisplayedSources = DisplayedList<Source>;
+
+/// What the tracks are played from
+#[derive(Clone)]
+pub enum Source {
+    Playlist(GeneratedPlaylist),
+    Station(Station),
+}
+
+impl Source {
+    pub fn title(&self) -> &str {
+        match self {
+            Source::Playlist(playlist) => &playlist.title,
+            Source::Station(station) => &station.name,
+        }
     }
 }
 
-#[derive(Default, Clone)]
-pub struct DisplayedTracks {
-    pub tracks: Vec<Track>,
-    pub state: ListState,
+impl<T> Default for DisplayedList<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            state: ListState::default(),
+        }
+    }
 }
 
-impl DisplayedTracks {
-    pub fn set_tracks(&mut self, tracks: Vec<Track>) {
-        self.tracks = tracks;
+impl<T> DisplayedList<T> {
+    pub fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
         self.state = ListState::default();
     }
 
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
-                if i >= self.tracks.len() - 1 {
+                if i >= self.items.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -89,10 +142,13 @@ impl DisplayedTracks {
     }
 
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.tracks.len() - 1
+                    self.items.len() - 1
                 } else {
                     i - 1
                 }
@@ -105,7 +161,161 @@ impl DisplayedTracks {
     pub fn unselect(&mut self) {
         self.state.select(None);
     }
+
+    pub fn selected_item(&self) -> Option<&T> {
+        self.state.selected().and_then(|i| self.items.get(i))
+    }
+}
+
+/// Tracks list left for an album or an artist, restored when going back
+struct VisitedTracks {
+    title: String,
+    tracks: DisplayedTracks,
+    radio: Option<Radio>,
+}
+
+/// Station whose tracks are listed, more of them are fetched as they are played
+#[derive(Clone)]
+struct Radio {
+    station: Station,
+    batch_id: Option<String>,
+}
+
+/// Tracks of a source, fetched before they are shown
+struct OpenedSource {
+    title: String,
+    tracks: Vec<Track>,
+    radio: Option<Radio>,
+}
+
+impl OpenedSource {
+    /// A station is opened with its first batch of tracks
+    fn fetch(backend: &dyn MusicBackend, source: Source) -> Result<Self> {
+        match source {
+            Source::Playlist(playlist) => {
+                let tracks = backend.generated_playlist_tracks(&playlist)?;
+                debug!("Opened {}...", playlist.title);
+                Ok(Self {
+                    title: playlist.title,
+                    tracks,
+                    radio: None,
+                })
+            }
+            Source::Station(station) => {
+                let batch = backend.station_tracks(&station.id, None)?;
+                debug!("Started {} radio...", station.name);
+                Ok(Self {
+                    title: station.name.clone(),
+                    tracks: batch.tracks,
+                    radio: Some(Radio {
+                        station,
+                        batch_id: Some(batch.batch_id),
+                    }),
+                })
+            }
+        }
+    }
+}
+
+/// What the app starts with, it is fetched on the worker without the app
+pub struct Startup {
+    sources: Vec<Source>,
+    selected: usize,
+    // The likes are not known if they cannot be loaded
+    liked: Option<Vec<String>>,
+    opened: OpenedSource,
+}
+
+impl Startup {
+    /// The playlist of the day is opened if there is one
+    pub fn fetch(backend: &dyn MusicBackend) -> Result<Self> {
+        let mut sources: Vec<Source> = backend
+            .generated_playlists()?
+            .into_iter()
+            .map(Source::Playlist)
+            .collect();
+        debug!("Added {} generated playlists...", sources.len());
+        match backend.stations() {
+            Ok(stations) => sources.extend(stations.into_iter().map(Source::Station)),
+            Err(err) => warn!("Cannot load radio stations: {}", err),
+        }
+        let selected = sources
+            .iter()
+            .position(|source| {
+                matches!(source, Source::Playlist(p) if p.playlist_type == GeneratedPlaylistType::PlaylistOfTheDay)
+            })
+            .unwrap_or(0);
+        let liked = backend
+            .liked_track_ids()
+            .map_err(|err| warn!("Cannot load liked tracks: {}", err))
+            .ok();
+        let source = sources.get(selected).ok_or(Error::EmptyQueue)?.clone();
+        let opened = OpenedSource::fetch(backend, source)?;
+        Ok(Self {
+            sources,
+            selected,
+            liked,
+            opened,
+        })
+    }
+}
+
+/// What the downloaded track is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Purpose {
+    Play,
+    Preload,
+    Offline,
+}
+
+/// Track downloaded on a worker task
+pub struct Download {
+    pub track: Track,
+    purpose: Purpose,
+    pub progress: Progress,
+    // Audio of the offline download once it can be played
+    media: Option<Media>,
+    // The offline download is played as soon as it can be
+    wanted: bool,
+}
+
+/// Work the app waits for, several of them run at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Initialize,
+    SwitchSong,
+    PreloadSong,
+    Download(DownloadId),
+    TopUpRadio,
+    MakeLikedOffline,
+}
+
+impl Operation {
+    /// Operation the io event starts, None for the events reporting on the running ones
+    pub fn of(event: &IoEvent) -> Option<Self> {
+        match event {
+            IoEvent::Initialize => Some(Operation::Initialize),
+            IoEvent::SongIsOver => Some(Operation::SwitchSong),
+            IoEvent::PreloadNextSong => Some(Operation::PreloadSong),
+            IoEvent::TopUpRadio { .. } => Some(Operation::TopUpRadio),
+            IoEvent::MakeLikedOffline => Some(Operation::MakeLikedOffline),
+            _ => None,
+        }
+    }
+}
+
+/// Part of the screen receiving the keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Sources,
+    Tracks,
+    SearchInput,
+    SearchResults,
+    Library,
+    PlaylistPicker,
+    Queue,
 }
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AppReturn {
     Exit,
@@ -115,158 +325,2419 @@ pub enum AppReturn {
 pub struct App {
     io_tx: tokio::sync::mpsc::Sender<IoEvent>,
     actions: Actions,
-    is_loading: bool,
+    // Operations in the order they are started
+    in_flight: Vec<Operation>,
+    // Events the full channel did not take yet, they are sent on the next ticks
+    undelivered: VecDeque<IoEvent>,
+    // Latest progress of the downloads, it is taken on the ticks
+    progress: ProgressReports,
     state: AppState,
-    client: Client,
+    backend: Arc<dyn MusicBackend>,
     player: Player,
-    current_playlist: Vec<Track>,
+    pub sources: DisplayedSources,
     pub displayed_tracks: DisplayedTracks,
-    cur_track_idx: usize,
+    pub tracks_title: String,
+    // Station of the displayed tracks
+    tracks_radio: Option<Radio>,
+    visited: Vec<VisitedTracks>,
+    pub search: SearchView,
+    pub library: LibraryView,
+    pub playlist_picker: DisplayedList<Playlist>,
+    // Track to add to the playlist chosen in the picker
+    picked_track: Option<Track>,
+    pub queue: Queue,
+    // Selected upcoming track in the queue view
+    pub queue_state: ListState,
+    // Track loaded into the player, None once it is over
+    playing: Option<Track>,
+    // Next track appended to the player after the playing one
+    preloaded: Option<Track>,
+    preload_requested: bool,
+    // Downloads of the playing and the next tracks until their files are complete
+    downloads: BTreeMap<DownloadId, Download>,
+    next_download: DownloadId,
+    // Collections made available offline, None in the offline mode
+    offline: Option<OfflineStore>,
+    // The files of the playing and preloaded tracks are not evicted from it
+    cache: Option<Arc<Mutex<Cache>>>,
+    // Tracks of the offline collections waiting for their download
+    offline_queue: VecDeque<Track>,
+    liked: HashSet<String>,
+    // Station the queue is filled from
+    radio: Option<Radio>,
+    focus: Focus,
+    // The config is saved only when it was loaded from a file
+    config: Config,
+    config_path: Option<PathBuf>,
 }
 
 impl App {
-    fn create_client() -> Client {
-        let mut token_file = File::open("token").unwrap();
+    fn create_client() -> eyre::Result<Client> {
+        let mut token_file = File::open("token")?;
         let mut token = String::new();
-        let _ = token_file.read_to_string(&mut token);
+        token_file.read_to_string(&mut token)?;
         let _ = token.pop();
 
-        Client::new(&token)
+        Ok(Client::new(&token)?)
+    }
+
+    /// The collections made available offline are played without the network if `offline`
+    /// is set here or in the config
+    pub fn new(io_tx: tokio::sync::mpsc::Sender<IoEvent>, offline: bool) -> eyre::Result<Self> {
+        let config = Config::default_path().and_then(|path| match Config::load(&path) {
+            Ok(config) => Some((config, path)),
+            Err(err) => {
+                warn!("Cannot load the config {}: {}", path.display(), err);
+                None
+            }
+        });
+        let settings = config
+            .as_ref()
+            .map_or_else(Config::default, |(config, _)| config.clone());
+        let cache = settings.open_cache();
+        let (backend, store, cache): (Box<dyn MusicBackend>, _, _) = if offline || settings.offline
+        {
+            let cache = Arc::new(Mutex::new(cache?));
+            let store = config::open_offline_store(cache.clone())?;
+            info!("Playing the collections made available offline");
+            let backend = OfflineBackend::new(store, settings.quality.clone());
+            (Box::new(backend), None, Some(cache))
+        } else {
+            let mut client = Self::create_client()?;
+            match cache {
+                Ok(cache) => client.set_cache(cache),
+                Err(err) => warn!("Cannot open the cache of tracks: {}", err),
+            }
+            client.set_quality_preference(settings.quality.clone());
+            let cache = client.cache();
+            let store = cache.clone().and_then(|cache| {
+                config::open_offline_store(cache)
+                    .map_err(|err| warn!("Cannot open the offline collections: {}", err))
+                    .ok()
+            });
+            (Box::new(client), store, cache)
+        };
+        let player = Player::new()?;
+        let mut app = Self::with_backend(io_tx, backend, player);
+        app.offline = store;
+        app.cache = cache;
+        if let Some(dir) = config::xdg_dir("XDG_CACHE_HOME", ".cache") {
+            let path = dir.join("loudness.json");
+            if let Err(err) = app.player.use_loudness_cache(path.clone()) {
+                warn!("Cannot load the loudness {}: {}", path.display(), err);
+            }
+        }
+        if let Some((config, path)) = config {
+            app.restore_config(config, path);
+        }
+        Ok(app)
+    }
+
+    /// Apply the saved settings, the changed ones are saved back to the path
+    fn restore_config(&mut self, config: Config, path: PathBuf) {
+        self.player.set_volume(config.volume);
+        if config.muted {
+            self.player.mute();
+        }
+        self.player
+            .set_crossfade(Duration::from_secs(config.crossfade_secs));
+        self.player.set_normalization(config.normalization);
+        self.player.set_pre_amp(config.pre_amp_db);
+        self.config = config;
+        self.config_path = Some(path);
+    }
+
+    fn save_config(&mut self) {
+        self.config.volume = self.player.volume();
+        self.config.muted = self.player.is_muted();
+        self.config.crossfade_secs = self.player.crossfade().as_secs();
+        self.config.normalization = self.player.normalization();
+        self.config.pre_amp_db = self.player.pre_amp();
+        if let Some(path) = &self.config_path {
+            if let Err(err) = self.config.save(path) {
+                warn!("Cannot save the config {}: {}", path.display(), err);
+            }
+        }
     }
 
-    pub fn new(io_tx: tokio::sync::mpsc::Sender<IoEvent>) -> Self {
+    pub fn with_backend(
+        io_tx: tokio::sync::mpsc::Sender<IoEvent>,
+        backend: Box<dyn MusicBackend>,
+        player: Player,
+    ) -> Self {
         let actions = vec![Action::Quit].into();
-        let is_loading = false;
         let state = AppState::default();
 
-        let client = Self::create_client();
-        let player = Player::default();
         Self {
             io_tx,
             actions,
-            is_loading,
+            in_flight: Vec::new(),
+            undelivered: VecDeque::new(),
+            progress: ProgressReports::default(),
             state,
-            client,
+            backend: Arc::from(backend),
             player,
-            current_playlist: Vec::<Track>::new(),
+            sources: DisplayedSources::default(),
             displayed_tracks: DisplayedTracks::default(),
-            cur_track_idx: 0,
+            tracks_title: String::new(),
+            tracks_radio: None,
+            visited: Vec::new(),
+            search: SearchView::default(),
+            library: LibraryView::default(),
+            playlist_picker: DisplayedList::default(),
+            picked_track: None,
+            queue: Queue::default(),
+            queue_state: ListState::default(),
+            playing: None,
+            preloaded: None,
+            preload_requested: false,
+            downloads: BTreeMap::new(),
+            next_download: 0,
+            offline: None,
+            cache: None,
+            offline_queue: VecDeque::new(),
+            liked: HashSet::new(),
+            radio: None,
+            focus: Focus::Tracks,
+            config: Config::default(),
+            config_path: None,
         }
     }
 
-    pub fn initialized(&mut self) {
-        self.actions = vec![
-            Action::Quit,
-            Action::PlaySound,
-            Action::PauseSound,
-            Action::SelectNextTrack,
-            Action::SelectPreviousTrack,
-        ]
-        .into();
-        self.current_playlist = self.client.playlist_of_the_day();
-        debug!("Added playlist of the day...");
-        self.displayed_tracks
-            .set_tracks(self.current_playlist.clone());
+    /// Fetch what the app starts with and play it, the worker fetches it without the app
+    pub fn initialized(&mut self) -> Result<()> {
+        let startup = Startup::fetch(self.backend.as_ref())?;
+        self.started(startup)
+    }
+
+    /// Show the fetched sources and play the tracks of the selected one
+    pub fn started(&mut self, startup: Startup) -> Result<()> {
+        self.sources.set_items(startup.sources);
+        self.sources.state.select(Some(startup.selected));
+        if let Some(liked) = startup.liked {
+            self.liked = liked.into_iter().collect();
+        }
+        self.show_source(startup.opened);
+        self.set_focus(Focus::Tracks);
+        self.play_selected()
+    }
+
+    fn open_source(&mut self) -> Result<()> {
+        let source = self
+            .sources
+            .selected_item()
+            .ok_or(Error::EmptyQueue)?
+            .clone();
+        let opened = OpenedSource::fetch(self.backend.as_ref(), source)?;
+        self.show_source(opened);
+        Ok(())
+    }
+
+    fn show_source(&mut self, opened: OpenedSource) {
+        self.visited.clear();
+        self.tracks_radio = opened.radio;
+        self.show_tracks(opened.title, opened.tracks);
+    }
+
+    /// Ask the worker for the next tracks of the station once the queue is about to end
+    fn top_up_radio(&mut self) {
+        let radio = match &self.radio {
+            Some(radio) => radio,
+            None => return,
+        };
+        if self.queue.upcoming().len() > 1 || self.in_flight.contains(&Operation::TopUpRadio) {
+            return;
+        }
+        let event = IoEvent::TopUpRadio {
+            station: radio.station.id.clone(),
+            last_track_id: self.queue.last().map(|track| track.id().to_string()),
+        };
+        self.dispatch(event);
+    }
+
+    /// Queue the fetched tracks unless another station is played by now
+    pub fn radio_topped_up(&mut self, station: &StationId, batch: StationTracks) {
+        match &mut self.radio {
+            Some(radio) if radio.station.id == *station => {
+                debug!("Queued {} more tracks of the radio", batch.tracks.len());
+                radio.batch_id = Some(batch.batch_id);
+                self.queue.add(batch.tracks);
+            }
+            _ => debug!("The radio {} is not played anymore", station),
+        }
+    }
+
+    /// Play the displayed tracks from the selected one, they replace the queued tracks
+    fn play_selected(&mut self) -> Result<()> {
+        let selected = self
+            .displayed_tracks
+            .state
+            .selected()
+            .ok_or(Error::EmptyQueue)?;
+        self.skip_playing();
+        let same_station = match (&self.radio, &self.tracks_radio) {
+            (Some(playing), Some(listed)) => playing.station.id == listed.station.id,
+            _ => false,
+        };
+        if !same_station {
+            self.radio = self.tracks_radio.clone();
+            self.send_feedback(Feedback::RadioStarted);
+        }
+        self.queue
+            .play_from(self.displayed_tracks.items.clone(), selected);
+        self.song_switched()
+    }
+
+    /// Play the next queued track, the playing one is skipped
+    fn play_next_queued(&mut self) -> Result<()> {
+        if !self.queue.has_next() {
+            return Err(Error::EmptyQueue);
+        }
+        self.skip_playing();
+        self.queue.next();
+        self.song_switched()
+    }
+
+    /// Play the last played track again, the playing one is restarted if there is none
+    fn play_previous(&mut self) -> Result<()> {
+        if self.queue.history().is_empty() {
+            self.player.seek(Duration::ZERO)?;
+            self.state.update_duration(Duration::ZERO);
+            return Ok(());
+        }
+        self.skip_playing();
+        self.queue.previous();
+        self.song_switched()
+    }
+
+    /// Jump to the track selected in the queue view
+    fn play_queued(&mut self) -> Result<()> {
+        let selected = self.queue_state.selected().ok_or(Error::EmptyQueue)?;
+        self.skip_playing();
+        self.queue.skip_to(selected).ok_or(Error::EmptyQueue)?;
+        self.select_queued(Some(0));
+        self.song_switched()
+    }
+
+    /// Select an upcoming track in the queue view, the index is kept within the queue
+    fn select_queued(&mut self, index: Option<usize>) {
+        let count = self.queue.upcoming().len();
+        let index = index.filter(|_| count > 0).map(|i| i.min(count - 1));
+        self.queue_state.select(index);
+    }
+
+    /// Tell the station what happened to pick next tracks, only in the radio mode.
+    /// The worker sends it in the order of the events
+    fn send_feedback(&mut self, feedback: Feedback) {
+        if let Some(radio) = &self.radio {
+            let event = IoEvent::RadioFeedback {
+                station: radio.station.clone(),
+                batch_id: radio.batch_id.clone(),
+                feedback,
+            };
+            self.dispatch(event);
+        }
+    }
+
+    /// The playing track is switched before it is over
+    fn skip_playing(&mut self) {
+        if let Some(track) = &self.playing {
+            let feedback = Feedback::Skip {
+                track_id: track.id().to_string(),
+                played: self.state.duration().copied().unwrap_or_default(),
+            };
+            self.send_feedback(feedback);
+        }
+    }
+
+    fn show_tracks(&mut self, title: String, tracks: Vec<Track>) {
+        self.tracks_title = title;
+        self.displayed_tracks.set_items(tracks);
         self.displayed_tracks.next();
-        self.prepare_next_song();
-        let _status = self.player.play();
     }
 
-    pub fn song_switched(&mut self) {
-        self.prepare_next_song();
-        let _status = self.player.play();
+    /// Show other tracks, the current ones are restored by `go_back`
+    fn navigate_to(&mut self, title: String, tracks: Vec<Track>) {
+        self.visited.push(VisitedTracks {
+            title: self.tracks_title.clone(),
+            tracks: self.displayed_tracks.clone(),
+            radio: self.tracks_radio.take(),
+        });
+        self.show_tracks(title, tracks);
+    }
+
+    /// Return to the tracks shown before the last navigation, false if there is none
+    fn go_back(&mut self) -> bool {
+        match self.visited.pop() {
+            Some(visited) => {
+                self.tracks_title = visited.title;
+                self.displayed_tracks = visited.tracks;
+                self.tracks_radio = visited.radio;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn open_album(&mut self, album_id: &str) -> Result<()> {
+        let album = self.backend.album(album_id)?;
+        let title = match album.album.year {
+            Some(year) => format!("Album: {} ({})", album.album.title, year),
+            None => format!("Album: {}", album.album.title),
+        };
+        self.navigate_to(title, album.tracks());
+        Ok(())
+    }
+
+    fn open_artist(&mut self, artist_id: &str) -> Result<()> {
+        let artist = self.backend.artist(artist_id)?;
+        let title = format!("Artist: {}", artist.artist.name);
+        self.navigate_to(title, artist.popular_tracks);
+        Ok(())
     }
 
-    fn prepare_next_song(&mut self) {
-        let sel_track_idx = self.displayed_tracks.state.selected().unwrap();
+    fn run_search(&mut self) -> Result<()> {
+        if self.search.query.is_empty() {
+            return Ok(());
+        }
+        let result =
+            self.backend
+                .search(&self.search.query, self.search.tab.kind(), self.search.page)?;
+        self.search.set_result(result);
         debug!(
-            "Play next song current {} {}, next {} {}",
-            self.cur_track_idx,
-            self.displayed_tracks.tracks[self.cur_track_idx].title(),
-            sel_track_idx,
-            self.displayed_tracks.tracks[sel_track_idx].title()
+            "Found {} {} for '{}'",
+            self.search.total, self.search.tab, self.search.query
         );
-        self.cur_track_idx = sel_track_idx;
-        self.player.stop();
-        let track_ref = &self.displayed_tracks.tracks[sel_track_idx];
-        let track_path = track_ref.download();
-        self.player.append(&track_path);
-        let total_duration = track_ref.total_duration().unwrap();
-        self.state = AppState::initialized(&total_duration);
+        Ok(())
     }
 
-    pub async fn update_on_tick(&mut self) -> AppReturn {
-        // debug!("Tick...");
-        if let Ok(status) = self.player.status() {
-            if status == Status::Empty {
-                if let Some(sel_track_idx) = self.displayed_tracks.state.selected() {
-                    if sel_track_idx == self.cur_track_idx {
-                        self.displayed_tracks.next();
-                        debug!("Send SongIsOver");
-                        self.dispatch(IoEvent::SongIsOver).await;
-                    }
-                }
-            } else {
-                self.state.update_duration(status.elapsed());
+    fn open_result(&mut self) -> Result<()> {
+        let item = self
+            .search
+            .results
+            .selected_item()
+            .ok_or(Error::EmptyQueue)?
+            .clone();
+        let title = format!("Search: {}", self.search.query);
+        self.open_item(item, title, self.search.tracks())
+    }
+
+    fn load_library(&mut self) -> Result<()> {
+        match self.library.tab {
+            CatalogTab::Tracks => {
+                let tracks = self.backend.liked_tracks(self.library.page)?;
+                self.library.set_tracks(tracks);
+            }
+            CatalogTab::Albums => {
+                let albums = self.backend.liked_albums()?;
+                self.library
+                    .set_items(albums.into_iter().map(CatalogItem::Album).collect());
+            }
+            CatalogTab::Artists => {
+                let artists = self.backend.liked_artists()?;
+                self.library
+                    .set_items(artists.into_iter().map(CatalogItem::Artist).collect());
+            }
+            CatalogTab::Playlists => {
+                let playlists = self.backend.user_playlists()?;
+                self.library
+                    .set_items(playlists.into_iter().map(CatalogItem::Playlist).collect());
             }
         }
-        AppReturn::Continue
+        debug!("Loaded {} liked {}", self.library.total, self.library.tab);
+        Ok(())
+    }
+
+    fn open_library_item(&mut self) -> Result<()> {
+        let item = self
+            .library
+            .items
+            .selected_item()
+            .ok_or(Error::EmptyQueue)?
+            .clone();
+        self.open_item(item, String::from("Liked tracks"), self.library.tracks())
     }
 
-    pub async fn dispatch(&mut self, action: IoEvent) {
-        self.is_loading = true;
-        if let Err(e) = self.io_tx.send(action).await {
-            self.is_loading = false;
-            error!("Error from dispatch {}", e);
+    /// Play a track among its neighbours or show the tracks of an album, artist or playlist
+    fn open_item(&mut self, item: CatalogItem, title: String, tracks: Vec<Track>) -> Result<()> {
+        match item {
+            CatalogItem::Track(track) => {
+                let track_idx = tracks.iter().position(|t| t.id() == track.id());
+                self.navigate_to(title, tracks);
+                self.displayed_tracks.state.select(track_idx);
+                self.set_focus(Focus::Tracks);
+                return self.play_selected();
+            }
+            CatalogItem::Album(album) => self.open_album(&album.id)?,
+            CatalogItem::Artist(artist) => self.open_artist(&artist.id)?,
+            CatalogItem::Playlist(playlist) => {
+                let playlist = self.backend.playlist(playlist.owner.uid, playlist.kind)?;
+                self.navigate_to(playlist.playlist.title, playlist.tracks);
+            }
         }
+        self.set_focus(Focus::Tracks);
+        Ok(())
     }
 
-    pub async fn do_action(&mut self, key: Key) -> AppReturn {
-        if let Some(action) = self.actions.find(key) {
-            debug!("Run action [{:?}]", action);
-            match action {
-                Action::Quit => AppReturn::Exit,
-                Action::PlaySound => {
-                    self.prepare_next_song();
-                    let _status = self.player.play();
-                    AppReturn::Continue
-                }
-                Action::PauseSound => {
-                    let _status = self.player.pause();
-                    AppReturn::Continue
-                }
-                Action::SelectNextTrack => {
-                    self.displayed_tracks.next();
-                    AppReturn::Continue
+    /// Ask for a playlist to add the selected track to
+    fn open_playlist_picker(&mut self) -> Result<()> {
+        let track = self
+            .displayed_tracks
+            .selected_item()
+            .ok_or(Error::EmptyQueue)?
+            .clone();
+        self.playlist_picker
+            .set_items(self.backend.user_playlists()?);
+        self.playlist_picker.next();
+        self.picked_track = Some(track);
+        self.set_focus(Focus::PlaylistPicker);
+        Ok(())
+    }
+
+    fn add_to_picked_playlist(&mut self) -> Result<()> {
+        let kind = self
+            .playlist_picker
+            .selected_item()
+            .ok_or_else(|| Error::NotFound(String::from("The user has no playlists")))?
+            .kind;
+        let track = self.picked_track.take().ok_or(Error::EmptyQueue)?;
+        let playlist = self
+            .backend
+            .add_to_playlist(kind, std::slice::from_ref(&track))?;
+        info!("Added {} to {}", track.title(), playlist.title);
+        Ok(())
+    }
+
+    /// Like the playing track or remove the like
+    fn toggle_like(&mut self) -> Result<()> {
+        let track = self.playing.clone().ok_or(Error::EmptyQueue)?;
+        if self.is_liked(&track) {
+            self.backend.unlike_track(&track)?;
+            self.liked.remove(track.id());
+            info!("Removed the like from {}", track.title());
+        } else {
+            self.backend.like_track(&track)?;
+            self.liked.insert(track.id().to_string());
+            info!("Liked {}", track.title());
+            self.send_feedback(Feedback::Like {
+                track_id: track.id().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Dislike the playing track and skip it, as the official client does
+    fn dislike(&mut self) -> Result<()> {
+        let track = self.playing.clone().ok_or(Error::EmptyQueue)?;
+        self.backend.dislike_track(&track)?;
+        self.liked.remove(track.id());
+        info!("Disliked {}", track.title());
+        match self.play_next_queued() {
+            // Nothing is queued after the disliked track, it is not played any further
+            Err(Error::EmptyQueue) => {
+                self.skip_playing();
+                self.player.stop()?;
+                self.playing = None;
+                self.preloaded = None;
+                self.preload_requested = false;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Type into the search box, returns false if the key is not text editing
+    fn edit_query(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => self.search.query.push(c),
+            Key::Backspace => {
+                self.search.query.pop();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.focus = focus;
+        self.actions = match focus {
+            Focus::Sources => vec![
+                Action::Quit,
+                Action::OpenSource,
+                Action::PauseSound,
+                Action::LikeTrack,
+                Action::DislikeTrack,
+                Action::SelectNextSource,
+                Action::SelectPreviousSource,
+                Action::NextSong,
+                Action::PreviousSong,
+                Action::ToggleShuffle,
+                Action::CycleRepeat,
+                Action::SeekForward,
+                Action::SeekBackward,
+                Action::JumpForward,
+                Action::JumpBackward,
+                Action::VolumeUp,
+                Action::VolumeDown,
+                Action::ToggleMute,
+                Action::CrossfadeLonger,
+                Action::CrossfadeShorter,
+                Action::CycleNormalization,
+                Action::PreAmpUp,
+                Action::PreAmpDown,
+                Action::SwitchFocus,
+                Action::Search,
+                Action::OpenLibrary,
+                Action::OpenQueue,
+            ],
+            Focus::Tracks => vec![
+                Action::Quit,
+                Action::PlaySound,
+                Action::PauseSound,
+                Action::LikeTrack,
+                Action::DislikeTrack,
+                Action::SelectNextTrack,
+                Action::SelectPreviousTrack,
+                Action::NextSong,
+                Action::PreviousSong,
+                Action::PlayNext,
+                Action::AddToQueue,
+                Action::ToggleShuffle,
+                Action::CycleRepeat,
+                Action::SeekForward,
+                Action::SeekBackward,
+                Action::JumpForward,
+                Action::JumpBackward,
+                Action::VolumeUp,
+                Action::VolumeDown,
+                Action::ToggleMute,
+                Action::CrossfadeLonger,
+                Action::CrossfadeShorter,
+                Action::CycleNormalization,
+                Action::PreAmpUp,
+                Action::PreAmpDown,
+                Action::OpenAlbum,
+                Action::OpenArtist,
+                Action::GoBack,
+                Action::AddToPlaylist,
+                Action::SwitchFocus,
+                Action::Search,
+                Action::OpenLibrary,
+                Action::OpenQueue,
+            ],
+            Focus::SearchInput => vec![Action::RunSearch, Action::CloseSearch],
+            Focus::SearchResults => vec![
+                Action::Quit,
+                Action::OpenResult,
+                Action::PauseSound,
+                Action::LikeTrack,
+                Action::DislikeTrack,
+                Action::SelectNextResult,
+                Action::SelectPreviousResult,
+                Action::NextSearchTab,
+                Action::NextSearchPage,
+                Action::PreviousSearchPage,
+                Action::Search,
+                Action::CloseSearch,
+            ],
+            Focus::Library => vec![
+                Action::Quit,
+                Action::OpenLibraryItem,
+                Action::PauseSound,
+                Action::LikeTrack,
+                Action::DislikeTrack,
+                Action::SelectNextLibraryItem,
+                Action::SelectPreviousLibraryItem,
+                Action::NextLibraryTab,
+                Action::NextLibraryPage,
+                Action::PreviousLibraryPage,
+                Action::MakeAvailableOffline,
+                Action::Search,
+                Action::CloseLibrary,
+            ],
+            Focus::PlaylistPicker => vec![
+                Action::PickPlaylist,
+                Action::SelectNextPlaylist,
+                Action::SelectPreviousPlaylist,
+                Action::CancelPicker,
+            ],
+            Focus::Queue => vec![
+                Action::Quit,
+                Action::PlayQueued,
+                Action::PauseSound,
+                Action::LikeTrack,
+                Action::DislikeTrack,
+                Action::NextSong,
+                Action::PreviousSong,
+                Action::ToggleShuffle,
+                Action::CycleRepeat,
+                Action::SelectNextQueued,
+                Action::SelectPreviousQueued,
+                Action::RemoveQueued,
+                Action::MoveQueuedUp,
+                Action::MoveQueuedDown,
+                Action::ClearQueue,
+                Action::CloseQueue,
+            ],
+        }
+        .into();
+    }
+
+    /// Play the current track of the queue, it is downloaded first unless it is on disk
+    pub fn song_switched(&mut self) -> Result<()> {
+        self.player.stop()?;
+        self.playing = None;
+        self.preloaded = None;
+        self.preload_requested = false;
+        // The files of the stopped tracks are not needed anymore, unlike the offline ones
+        self.cancel_downloads(|download| download.purpose != Purpose::Offline);
+        for download in self.downloads.values_mut() {
+            download.wanted = false;
+        }
+        let track = self.queue.current().ok_or(Error::EmptyQueue)?.clone();
+        let total_duration = track.total_duration().unwrap_or_default();
+        self.state = AppState::initialized(&total_duration);
+        if let Some(path) = self.backend.local_file(&track) {
+            return self.start_song(track, Media::File(path));
+        }
+        // The track of an offline collection is not downloaded twice
+        let offline = self
+            .downloads
+            .values_mut()
+            .find(|download| download.track.id() == track.id());
+        match offline {
+            Some(Download {
+                media: Some(media), ..
+            }) => {
+                let media = media.clone();
+                self.start_song(track, media)
+            }
+            Some(download) => {
+                download.wanted = true;
+                Ok(())
+            }
+            None => {
+                debug!("Download next song {}", track.title());
+                self.start_download(track, Purpose::Play);
+                Ok(())
+            }
+        }
+    }
+
+    fn start_song(&mut self, track: Track, media: Media) -> Result<()> {
+        debug!("Play next song {}", track.title());
+        self.player.append_track(&track, media)?;
+        self.keep_playing_files();
+        self.playing = Some(track);
+        self.song_started();
+        self.player.play()
+    }
+
+    /// Keep the files of the queued sounds in the cache, they are opened again to seek
+    fn keep_playing_files(&self) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().set_in_use(self.player.files());
+        }
+    }
+
+    fn song_started(&mut self) {
+        if let Some(track) = &self.playing {
+            let track_id = track.id().to_string();
+            self.send_feedback(Feedback::TrackStarted { track_id });
+        }
+        self.top_up_radio();
+    }
+
+    /// Backend for the requests the worker makes without the app
+    pub fn backend(&self) -> Arc<dyn MusicBackend> {
+        Arc::clone(&self.backend)
+    }
+
+    /// Audio of the tracks for the downloads running without the app
+    pub fn fetcher(&self) -> Fetcher {
+        self.backend.fetcher()
+    }
+
+    /// Where the downloads running without the app report how far they are
+    pub fn progress_reports(&self) -> ProgressReports {
+        Arc::clone(&self.progress)
+    }
+
+    pub fn downloads(&self) -> impl Iterator<Item = &Download> {
+        self.downloads.values()
+    }
+
+    pub fn download(&self, id: DownloadId) -> Option<&Download> {
+        self.downloads.get(&id)
+    }
+
+    /// Tracks of the offline collections waiting for their download
+    pub fn offline_queued(&self) -> usize {
+        self.offline_queue.len()
+    }
+
+    /// Progress of the track if it is being downloaded
+    pub fn download_of(&self, track: &Track) -> Option<&Progress> {
+        self.downloads
+            .values()
+            .find(|download| download.track.id() == track.id())
+            .map(|download| &download.progress)
+    }
+
+    fn start_download(&mut self, track: Track, purpose: Purpose) {
+        let id = self.next_download;
+        self.next_download += 1;
+        let download = Download {
+            track: track.clone(),
+            purpose,
+            progress: Progress::default(),
+            media: None,
+            wanted: false,
+        };
+        self.downloads.insert(id, download);
+        self.in_flight.push(Operation::Download(id));
+        let track = Box::new(track);
+        self.dispatch(IoEvent::Download { id, track });
+    }
+
+    fn remove_download(&mut self, id: DownloadId) -> Option<Download> {
+        self.finished(Operation::Download(id));
+        let download = self.downloads.remove(&id);
+        if download
+            .as_ref()
+            .is_some_and(|download| download.purpose == Purpose::Offline)
+        {
+            self.download_offline();
+        }
+        download
+    }
+
+    fn cancel_download(&mut self, id: DownloadId) {
+        if self.remove_download(id).is_some() {
+            self.dispatch(IoEvent::CancelDownload(id));
+        }
+    }
+
+    /// Cancel the matching downloads, return how many of them there were
+    fn cancel_downloads(&mut self, matches: impl Fn(&Download) -> bool) -> usize {
+        let ids: Vec<DownloadId> = self
+            .downloads
+            .iter()
+            .filter(|(_, download)| matches(download))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            self.cancel_download(*id);
+        }
+        ids.len()
+    }
+
+    /// Apply the latest progress the downloads reported since the last tick
+    fn take_progress(&mut self) {
+        let reports: Vec<(DownloadId, Progress)> = self.progress.lock().unwrap().drain().collect();
+        for (id, progress) in reports {
+            self.download_progress(id, progress);
+        }
+    }
+
+    pub fn download_progress(&mut self, id: DownloadId, progress: Progress) {
+        if let Some(download) = self.downloads.get_mut(&id) {
+            download.progress = progress;
+            if progress.is_complete() {
+                self.remove_download(id);
+            }
+        }
+    }
+
+    /// The downloaded track can be played, it is dropped if another one is wanted meanwhile
+    pub fn download_ready(&mut self, id: DownloadId, media: Media) -> Result<()> {
+        let (track, purpose, wanted) = match self.downloads.get_mut(&id) {
+            Some(download) => {
+                let extension = media.path().extension().and_then(|ext| ext.to_str());
+                if !Tags::can_tag(extension.unwrap_or_default()) {
+                    info!(
+                        "{} is kept without tags, its codec is not tagged",
+                        download.track.title()
+                    );
                 }
-                Action::SelectPreviousTrack => {
-                    self.displayed_tracks.previous();
-                    AppReturn::Continue
+                if download.purpose == Purpose::Offline {
+                    download.media = Some(media.clone());
                 }
+                (download.track.clone(), download.purpose, download.wanted)
+            }
+            None => return Ok(()),
+        };
+        if media.is_complete() {
+            self.remove_download(id);
+        }
+        let is_current = self.queue.current().map(Track::id) == Some(track.id());
+        let is_next = self.queue.peek_next().map(Track::id) == Some(track.id());
+        match purpose {
+            Purpose::Play if is_current && self.playing.is_none() => self.start_song(track, media),
+            Purpose::Preload
+                if is_next
+                    && self.playing.is_some()
+                    && self.preloaded.is_none()
+                    && self.player.status()? != Status::Empty =>
+            {
+                self.preload(track, media)
+            }
+            // The track is saved for the offline collection whether it is played or not
+            Purpose::Offline if wanted && is_current && self.playing.is_none() => {
+                self.start_song(track, media)
+            }
+            Purpose::Offline => Ok(()),
+            _ => {
+                debug!("{} is not needed anymore", track.title());
+                self.cancel_download(id);
+                Ok(())
             }
-        } else {
-            warn!("No action associated to {}", key);
-            AppReturn::Continue
         }
     }
 
-    pub fn actions(&self) -> &Actions {
-        &self.actions
+    pub fn download_failed(&mut self, id: DownloadId, error: &str) {
+        if let Some(download) = self.remove_download(id) {
+            match download.purpose {
+                Purpose::Play => error!("Cannot download {}: {}", download.track.title(), error),
+                Purpose::Preload => warn!("Cannot preload {}: {}", download.track.title(), error),
+                Purpose::Offline => warn!(
+                    "Cannot make {} available offline: {}",
+                    download.track.title(),
+                    error
+                ),
+            }
+        }
     }
 
-    pub fn state(&self) -> &AppState {
-        &self.state
+    /// Save the selected album or playlist of the library, or all of the liked tracks on
+    /// their tab, and download their tracks to listen to them offline
+    fn make_available_offline(&mut self) -> Result<()> {
+        self.offline_store()?;
+        let item = self.library.items.selected_item().cloned();
+        let (title, tracks) = match (self.library.tab, item) {
+            (CatalogTab::Tracks, _) => {
+                // There may be thousands of them, the worker fetches them without the app
+                if !self.in_flight.contains(&Operation::MakeLikedOffline) {
+                    self.dispatch(IoEvent::MakeLikedOffline);
+                }
+                return Ok(());
+            }
+            (_, Some(CatalogItem::Album(album))) => {
+                let album = self.backend.album(&album.id)?;
+                let title = album.album.title.clone();
+                let tracks = album.tracks();
+                self.offline_store()?.add_album(album)?;
+                (title, tracks)
+            }
+            (_, Some(CatalogItem::Playlist(playlist))) => {
+                let playlist = self.backend.playlist(playlist.owner.uid, playlist.kind)?;
+                let title = playlist.playlist.title.clone();
+                let tracks = playlist.tracks.clone();
+                self.offline_store()?.add_playlist(playlist)?;
+                (title, tracks)
+            }
+            _ => {
+                return Err(Error::NotFound(String::from(
+                    "Only albums, playlists and liked tracks are made available offline",
+                )))
+            }
+        };
+        self.queue_offline(&title, tracks);
+        Ok(())
     }
 
-    pub fn is_loading(&self) -> bool {
-        self.is_loading
+    /// Save the fetched liked tracks and download them to listen to them offline
+    pub fn liked_tracks_fetched(&mut self, tracks: Vec<Track>) -> Result<()> {
+        self.offline_store()?.set_liked(tracks.clone())?;
+        self.queue_offline("liked tracks", tracks);
+        Ok(())
     }
 
-    pub fn loaded(&mut self) {
-        self.is_loading = false;
+    fn queue_offline(&mut self, title: &str, tracks: Vec<Track>) {
+        info!(
+            "Making {} tracks of {} available offline",
+            tracks.len(),
+            title
+        );
+        self.offline_queue.extend(tracks);
+        self.download_offline();
     }
 
-    pub fn current_playlist(&self) -> &Vec<Track> {
-        &self.current_playlist
+    fn offline_store(&mut self) -> Result<&mut OfflineStore> {
+        self.offline.as_mut().ok_or_else(|| {
+            Error::NotFound(String::from(
+                "Collections are made available offline only with the cache and the network",
+            ))
+        })
+    }
+
+    /// Download the queued tracks of the offline collections, a few of them at once
+    fn download_offline(&mut self) {
+        let mut running = self
+            .downloads
+            .values()
+            .filter(|download| download.purpose == Purpose::Offline)
+            .count();
+        while running < OFFLINE_DOWNLOADS {
+            let track = match self.offline_queue.pop_front() {
+                Some(track) => track,
+                None => return,
+            };
+            if self.backend.local_file(&track).is_none() && self.download_of(&track).is_none() {
+                self.start_download(track, Purpose::Offline);
+                running += 1;
+            }
+        }
+    }
+
+    /// Move within the playing track, the new position is shown without waiting for a tick
+    fn seek(&mut self, seek: impl FnOnce(&mut Player) -> Result<()>) {
+        if let Err(err) = seek(&mut self.player) {
+            warn!("Cannot seek: {}", err);
+            return;
+        }
+        if let Ok(elapsed) = self.player.status().and_then(Status::elapsed) {
+            self.state.update_duration(elapsed);
+        }
+    }
+
+    /// Append the next queued track to the player to play it right after the current one
+    pub fn preload_next_song(&mut self) -> Result<()> {
+        let preloading = self
+            .downloads
+            .values()
+            .any(|download| download.purpose == Purpose::Preload);
+        if self.playing.is_none() || self.preloaded.is_some() || preloading {
+            return Ok(());
+        }
+        let track = match self.queue.peek_next() {
+            Some(track) => track.clone(),
+            None => return Ok(()),
+        };
+        // Once the playing track is over the next one is loaded the usual way, as is
+        // the one downloaded for the offline collections meanwhile
+        if self.player.status()? == Status::Empty || self.download_of(&track).is_some() {
+            return Ok(());
+        }
+        match self.backend.local_file(&track) {
+            Some(path) => self.preload(track, Media::File(path)),
+            None => {
+                self.start_download(track, Purpose::Preload);
+                Ok(())
+            }
+        }
+    }
+
+    fn preload(&mut self, track: Track, media: Media) -> Result<()> {
+        // Tracks of an album are often meant to flow into each other
+        let same_album = track.album_id.is_some()
+            && track.album_id
+                == self
+                    .playing
+                    .as_ref()
+                    .and_then(|playing| playing.album_id.clone());
+        if same_album {
+            self.player.append_track_gapless(&track, media)?;
+        } else {
+            self.player.append_track(&track, media)?;
+        }
+        self.keep_playing_files();
+        debug!("Preloaded {}", track.title());
+        self.preloaded = Some(track);
+        Ok(())
+    }
+
+    /// Ask to preload the next queued track, the preloaded one is dropped if the queue is changed
+    fn request_preload(&mut self) {
+        let next_id = self.queue.peek_next().map(|track| track.id().to_string());
+        if let Some(preloaded) = &self.preloaded {
+            if Some(preloaded.id()) == next_id.as_deref() {
+                return;
+            }
+            debug!(
+                "The queue is changed, drop the preloaded {}",
+                preloaded.title()
+            );
+            if let Err(err) = self.player.drop_queued() {
+                warn!("Cannot drop the preloaded song: {}", err);
+            }
+            self.preloaded = None;
+            self.preload_requested = false;
+        }
+        // The track downloaded to be preloaded is not the next one anymore
+        let stale = self.cancel_downloads(|download| {
+            download.purpose == Purpose::Preload && Some(download.track.id()) != next_id.as_deref()
+        });
+        if stale > 0 {
+            self.preload_requested = false;
+        }
+        if self.playing.is_some() && next_id.is_some() && !self.preload_requested {
+            self.preload_requested = true;
+            self.dispatch(IoEvent::PreloadNextSong);
+        }
+    }
+
+    /// The player went on to the preloaded track, it is now the current one of the queue
+    fn preloaded_song_started(&mut self) {
+        if let Some(track) = self.playing.take() {
+            let feedback = Feedback::TrackFinished {
+                track_id: track.id().to_string(),
+                played: track.total_duration().unwrap_or_default(),
+            };
+            self.send_feedback(feedback);
+        }
+        let preloaded = self.preloaded.take();
+        self.preload_requested = false;
+        for download in self.downloads.values_mut() {
+            if download.purpose == Purpose::Preload {
+                download.purpose = Purpose::Play;
+            }
+        }
+        let queued_id = self.queue.advance().map(|track| track.id().to_string());
+        match preloaded {
+            Some(track) if queued_id.as_deref() == Some(track.id()) => {
+                debug!("Play preloaded song {}", track.title());
+                let total_duration = track.total_duration().unwrap_or_default();
+                self.state = AppState::initialized(&total_duration);
+                self.playing = Some(track);
+                self.song_started();
+            }
+            _ => {
+                warn!("The preloaded song is not the queued one");
+                if let Err(err) = self.song_switched() {
+                    error!("Cannot play the song: {}", err);
+                }
+            }
+        }
+    }
+
+    pub fn update_on_tick(&mut self) -> AppReturn {
+        self.deliver();
+        self.take_progress();
+        if self.preloaded.is_some() && self.player.queued() == 1 {
+            self.preloaded_song_started();
+        }
+        if let Ok(status) = self.player.status() {
+            if status == Status::Empty {
+                if let Some(track) = self.playing.take() {
+                    let feedback = Feedback::TrackFinished {
+                        track_id: track.id().to_string(),
+                        played: track.total_duration().unwrap_or_default(),
+                    };
+                    self.send_feedback(feedback);
+                    if self.queue.advance().is_some() {
+                        debug!("Send SongIsOver");
+                        self.dispatch(IoEvent::SongIsOver);
+                    } else {
+                        info!("The queue is over");
+                    }
+                }
+            } else if let Ok(elapsed) = status.elapsed() {
+                self.state.update_duration(elapsed);
+            }
+        }
+        self.request_preload();
+        AppReturn::Continue
+    }
+
+    /// Send the event to the worker without waiting for the channel, the app is locked
+    /// meanwhile. If it is full the event is sent on the next ticks, after the ones that
+    /// wait already
+    pub fn dispatch(&mut self, event: IoEvent) {
+        if let Some(operation) = Operation::of(&event) {
+            self.in_flight.push(operation);
+        }
+        self.undelivered.push_back(event);
+        self.deliver();
+        if !self.undelivered.is_empty() {
+            warn!("The worker is busy, {} events wait", self.undelivered.len());
+        }
+    }
+
+    /// Send the events that wait for room in the channel
+    fn deliver(&mut self) {
+        while let Some(event) = self.undelivered.pop_front() {
+            match self.io_tx.try_send(event) {
+                Ok(()) => {}
+                Err(TrySendError::Full(event)) => {
+                    self.undelivered.push_front(event);
+                    return;
+                }
+                Err(TrySendError::Closed(event)) => {
+                    error!("Cannot dispatch the event, the worker is gone");
+                    if let Some(operation) = Operation::of(&event) {
+                        self.finished(operation);
+                    }
+                    // The download never starts, the next offline ones would not either
+                    if let IoEvent::Download { id, .. } = event {
+                        self.finished(Operation::Download(id));
+                        self.downloads.remove(&id);
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn do_action(&mut self, key: Key) -> AppReturn {
+        if self.focus == Focus::SearchInput && self.edit_query(key) {
+            return AppReturn::Continue;
+        }
+        if let Some(action) = self.actions.find(key) {
+            debug!("Run action [{:?}]", action);
+            match action {
+                Action::Quit => AppReturn::Exit,
+                Action::PlaySound => {
+                    if let Err(err) = self.play_selected() {
+                        error!("Cannot play the song: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::NextSong => {
+                    if let Err(err) = self.play_next_queued() {
+                        warn!("Cannot play the next song: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::PreviousSong => {
+                    if let Err(err) = self.play_previous() {
+                        warn!("Cannot play the previous song: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::PlayNext => {
+                    if let Some(track) = self.displayed_tracks.selected_item().cloned() {
+                        info!("{} is played next", track.title());
+                        self.queue.play_next(track);
+                    }
+                    AppReturn::Continue
+                }
+                Action::AddToQueue => {
+                    if let Some(track) = self.displayed_tracks.selected_item().cloned() {
+                        info!("{} is added to the queue", track.title());
+                        self.queue.add(Some(track));
+                    }
+                    AppReturn::Continue
+                }
+                Action::OpenQueue => {
+                    self.select_queued(Some(0));
+                    self.set_focus(Focus::Queue);
+                    AppReturn::Continue
+                }
+                Action::PlayQueued => {
+                    if let Err(err) = self.play_queued() {
+                        error!("Cannot play the queued song: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::SelectNextQueued => {
+                    let selected = self.queue_state.selected();
+                    self.select_queued(Some(selected.map_or(0, |i| i + 1)));
+                    AppReturn::Continue
+                }
+                Action::SelectPreviousQueued => {
+                    let selected = self.queue_state.selected();
+                    self.select_queued(Some(selected.map_or(0, |i| i.saturating_sub(1))));
+                    AppReturn::Continue
+                }
+                Action::RemoveQueued => {
+                    if let Some(selected) = self.queue_state.selected() {
+                        self.queue.remove(selected);
+                        self.select_queued(Some(selected));
+                    }
+                    AppReturn::Continue
+                }
+                Action::MoveQueuedUp => {
+                    if let Some(selected) = self.queue_state.selected() {
+                        if self.queue.move_up(selected) {
+                            self.select_queued(Some(selected - 1));
+                        }
+                    }
+                    AppReturn::Continue
+                }
+                Action::MoveQueuedDown => {
+                    if let Some(selected) = self.queue_state.selected() {
+                        if self.queue.move_down(selected) {
+                            self.select_queued(Some(selected + 1));
+                        }
+                    }
+                    AppReturn::Continue
+                }
+                Action::ClearQueue => {
+                    self.queue.clear();
+                    self.select_queued(None);
+                    AppReturn::Continue
+                }
+                Action::CloseQueue => {
+                    self.set_focus(Focus::Tracks);
+                    AppReturn::Continue
+                }
+                Action::ToggleShuffle => {
+                    self.queue.set_shuffle(!self.queue.is_shuffled());
+                    self.select_queued(self.queue_state.selected());
+                    AppReturn::Continue
+                }
+                Action::CycleRepeat => {
+                    self.queue.set_repeat(self.queue.repeat().next());
+                    AppReturn::Continue
+                }
+                Action::PauseSound => {
+                    if let Err(err) = self.player.pause() {
+                        warn!("Cannot pause: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::SeekForward => {
+                    self.seek(|player| player.seek_forward(SEEK_STEP));
+                    AppReturn::Continue
+                }
+                Action::SeekBackward => {
+                    self.seek(|player| player.seek_backward(SEEK_STEP));
+                    AppReturn::Continue
+                }
+                Action::JumpForward => {
+                    self.seek(|player| player.seek_forward(JUMP_STEP));
+                    AppReturn::Continue
+                }
+                Action::JumpBackward => {
+                    self.seek(|player| player.seek_backward(JUMP_STEP));
+                    AppReturn::Continue
+                }
+                Action::VolumeUp => {
+                    self.player.set_volume(self.player.volume() + VOLUME_STEP);
+                    self.player.unmute();
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::VolumeDown => {
+                    self.player.set_volume(self.player.volume() - VOLUME_STEP);
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::CrossfadeLonger => {
+                    self.player
+                        .set_crossfade(self.player.crossfade() + CROSSFADE_STEP);
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::CrossfadeShorter => {
+                    self.player
+                        .set_crossfade(self.player.crossfade().saturating_sub(CROSSFADE_STEP));
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::CycleNormalization => {
+                    let normalization = self.player.normalization().next();
+                    self.player.set_normalization(normalization);
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::PreAmpUp => {
+                    self.player
+                        .set_pre_amp(self.player.pre_amp() + PRE_AMP_STEP);
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::PreAmpDown => {
+                    self.player
+                        .set_pre_amp(self.player.pre_amp() - PRE_AMP_STEP);
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::ToggleMute => {
+                    if self.player.is_muted() {
+                        self.player.unmute();
+                    } else {
+                        self.player.mute();
+                    }
+                    self.save_config();
+                    AppReturn::Continue
+                }
+                Action::SelectNextTrack => {
+                    self.displayed_tracks.next();
+                    AppReturn::Continue
+                }
+                Action::SelectPreviousTrack => {
+                    self.displayed_tracks.previous();
+                    AppReturn::Continue
+                }
+                Action::OpenSource => {
+                    match self.open_source() {
+                        Ok(()) => self.set_focus(Focus::Tracks),
+                        Err(err) => error!("Cannot open the playlist: {}", err),
+                    }
+                    AppReturn::Continue
+                }
+                Action::SelectNextSource => {
+                    self.sources.next();
+                    AppReturn::Continue
+                }
+                Action::SelectPreviousSource => {
+                    self.sources.previous();
+                    AppReturn::Continue
+                }
+                Action::SwitchFocus => {
+                    let focus = match self.focus {
+                        Focus::Sources => Focus::Tracks,
+                        _ => Focus::Sources,
+                    };
+                    self.set_focus(focus);
+                    AppReturn::Continue
+                }
+                Action::Search => {
+                    self.set_focus(Focus::SearchInput);
+                    AppReturn::Continue
+                }
+                Action::RunSearch => {
+                    self.search.page = 0;
+                    match self.run_search() {
+                        Ok(()) => self.set_focus(Focus::SearchResults),
+                        Err(err) => error!("Cannot search: {}", err),
+                    }
+                    AppReturn::Continue
+                }
+                Action::CloseSearch => {
+                    self.set_focus(Focus::Tracks);
+                    AppReturn::Continue
+                }
+                Action::OpenResult => {
+                    if let Err(err) = self.open_result() {
+                        error!("Cannot open the result: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::SelectNextResult => {
+                    self.search.results.next();
+                    AppReturn::Continue
+                }
+                Action::SelectPreviousResult => {
+                    self.search.results.previous();
+                    AppReturn::Continue
+                }
+                Action::NextSearchTab => {
+                    self.search.tab = self.search.tab.next();
+                    self.search.page = 0;
+                    if let Err(err) = self.run_search() {
+                        error!("Cannot search: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::NextSearchPage => {
+                    if self.search.has_next_page() {
+                        self.search.page += 1;
+                        if let Err(err) = self.run_search() {
+                            error!("Cannot search: {}", err);
+                        }
+                    }
+                    AppReturn::Continue
+                }
+                Action::PreviousSearchPage => {
+                    if self.search.page > 0 {
+                        self.search.page -= 1;
+                        if let Err(err) = self.run_search() {
+                            error!("Cannot search: {}", err);
+                        }
+                    }
+                    AppReturn::Continue
+                }
+                Action::OpenAlbum => {
+                    let album_id = self
+                        .displayed_tracks
+                        .selected_item()
+                        .and_then(|track| track.album_id.clone());
+                    match album_id {
+                        Some(album_id) => {
+                            if let Err(err) = self.open_album(&album_id) {
+                                error!("Cannot open the album: {}", err);
+                            }
+                        }
+                        None => warn!("The track has no album"),
+                    }
+                    AppReturn::Continue
+                }
+                Action::OpenArtist => {
+                    let artist_id = self
+                        .displayed_tracks
+                        .selected_item()
+                        .and_then(|track| track.artists.first())
+                        .map(|artist| artist.id.clone());
+                    match artist_id {
+                        Some(artist_id) => {
+                            if let Err(err) = self.open_artist(&artist_id) {
+                                error!("Cannot open the artist: {}", err);
+                            }
+                        }
+                        None => warn!("The track has no artist"),
+                    }
+                    AppReturn::Continue
+                }
+                Action::GoBack => {
+                    if !self.go_back() {
+                        debug!("Nothing to go back to");
+                    }
+                    AppReturn::Continue
+                }
+                Action::OpenLibrary => {
+                    match self.load_library() {
+                        Ok(()) => self.set_focus(Focus::Library),
+                        Err(err) => error!("Cannot load the library: {}", err),
+                    }
+                    AppReturn::Continue
+                }
+                Action::OpenLibraryItem => {
+                    if let Err(err) = self.open_library_item() {
+                        error!("Cannot open the library item: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::MakeAvailableOffline => {
+                    if let Err(err) = self.make_available_offline() {
+                        error!("Cannot make it available offline: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::SelectNextLibraryItem => {
+                    self.library.items.next();
+                    AppReturn::Continue
+                }
+                Action::SelectPreviousLibraryItem => {
+                    self.library.items.previous();
+                    AppReturn::Continue
+                }
+                Action::NextLibraryTab => {
+                    self.library.tab = self.library.tab.next();
+                    self.library.page = 0;
+                    if let Err(err) = self.load_library() {
+                        error!("Cannot load the library: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::NextLibraryPage => {
+                    if self.library.has_next_page() {
+                        self.library.page += 1;
+                        if let Err(err) = self.load_library() {
+                            error!("Cannot load the library: {}", err);
+                        }
+                    }
+                    AppReturn::Continue
+                }
+                Action::PreviousLibraryPage => {
+                    if self.library.page > 0 {
+                        self.library.page -= 1;
+                        if let Err(err) = self.load_library() {
+                            error!("Cannot load the library: {}", err);
+                        }
+                    }
+                    AppReturn::Continue
+                }
+                Action::CloseLibrary => {
+                    self.set_focus(Focus::Tracks);
+                    AppReturn::Continue
+                }
+                Action::LikeTrack => {
+                    if let Err(err) = self.toggle_like() {
+                        error!("Cannot like the track: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::DislikeTrack => {
+                    if let Err(err) = self.dislike() {
+                        error!("Cannot dislike the track: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::AddToPlaylist => {
+                    if let Err(err) = self.open_playlist_picker() {
+                        error!("Cannot list the playlists: {}", err);
+                    }
+                    AppReturn::Continue
+                }
+                Action::PickPlaylist => {
+                    if let Err(err) = self.add_to_picked_playlist() {
+                        error!("Cannot add the track to the playlist: {}", err);
+                    }
+                    self.set_focus(Focus::Tracks);
+                    AppReturn::Continue
+                }
+                Action::SelectNextPlaylist => {
+                    self.playlist_picker.next();
+                    AppReturn::Continue
+                }
+                Action::SelectPreviousPlaylist => {
+                    self.playlist_picker.previous();
+                    AppReturn::Continue
+                }
+                Action::CancelPicker => {
+                    self.picked_track = None;
+                    self.set_focus(Focus::Tracks);
+                    AppReturn::Continue
+                }
+            }
+        } else {
+            warn!("No action associated to {}", key);
+            AppReturn::Continue
+        }
+    }
+
+    pub fn actions(&self) -> &Actions {
+        &self.actions
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub fn in_flight(&self) -> &[Operation] {
+        &self.in_flight
+    }
+
+    /// The operation is over, the earliest one of the same kind if there are several
+    pub fn finished(&mut self, operation: Operation) {
+        if let Some(index) = self.in_flight.iter().position(|op| *op == operation) {
+            self.in_flight.remove(index);
+        }
+    }
+
+    /// Perceived volume from 0 to 1
+    pub fn volume(&self) -> f32 {
+        self.player.volume()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.player.is_muted()
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        self.player.crossfade()
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.player.normalization()
+    }
+
+    pub fn pre_amp(&self) -> f32 {
+        self.player.pre_amp()
+    }
+
+    /// Displayed tracks, they are queued when one of them is played
+    pub fn current_playlist(&self) -> &Vec<Track> {
+        &self.displayed_tracks.items
+    }
+
+    pub fn focus(&self) -> Focus {
+        self.focus
+    }
+
+    /// The track in the player, even if it is not listed anymore
+    pub fn playing(&self) -> Option<&Track> {
+        self.playing.as_ref()
+    }
+
+    pub fn is_liked(&self, track: &Track) -> bool {
+        self.liked.contains(track.id())
+    }
+
+    pub fn is_searching(&self) -> bool {
+        matches!(self.focus, Focus::SearchInput | Focus::SearchResults)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MB;
+    use crate::queue::Repeat;
+    use std::fs;
+    use std::path::PathBuf;
+    use yandex_rust_music::testing::TempDir;
+    use yandex_rust_music::{Cache, FakeBackend, Quality, QualityPreference};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn create_app() -> (App, tokio::sync::mpsc::Receiver<IoEvent>) {
+        app_with_backend(create_backend())
+    }
+
+    fn app_with_backend(backend: FakeBackend) -> (App, tokio::sync::mpsc::Receiver<IoEvent>) {
+        let (io_tx, io_rx) = tokio::sync::mpsc::channel(100);
+        let app = App::with_backend(io_tx, Box::new(backend), Player::headless());
+        (app, io_rx)
+    }
+
+    fn create_backend() -> FakeBackend {
+        let mut backend = FakeBackend::new();
+        backend.add_playlist(GeneratedPlaylistType::MissedLikes, "Missed likes");
+        backend.add_track("Missed", Duration::from_secs(1), fixture("sine-660.wav"));
+        backend.add_playlist(
+            GeneratedPlaylistType::PlaylistOfTheDay,
+            "Playlist of the day",
+        );
+        backend.add_album("Sines", "Oscillator");
+        backend.add_track("First", Duration::from_secs(1), fixture("sine-440.wav"));
+        backend.add_track("Second", Duration::from_secs(1), fixture("sine-660.wav"));
+        backend.add_album("Single", "Oscillator");
+        backend.add_track("Third", Duration::from_secs(1), fixture("sine-440.wav"));
+        backend.add_liked_track("Second");
+        backend.add_user_playlist("Road trip", &["Third", "First"]);
+        backend
+    }
+
+    /// Make the requests like the worker does, the other events are returned
+    fn handle_backend_event(app: &mut App, event: IoEvent) -> Option<IoEvent> {
+        match event {
+            IoEvent::MakeLikedOffline => {
+                let backend = app.backend();
+                let ids = backend.liked_track_ids().unwrap();
+                app.liked_tracks_fetched(backend.tracks(&ids).unwrap())
+                    .unwrap();
+                app.finished(Operation::MakeLikedOffline);
+                None
+            }
+            IoEvent::RadioFeedback {
+                station,
+                batch_id,
+                feedback,
+            } => {
+                let backend = app.backend();
+                backend
+                    .station_feedback(&station, batch_id.as_deref(), &feedback)
+                    .unwrap();
+                None
+            }
+            IoEvent::TopUpRadio {
+                station,
+                last_track_id,
+            } => {
+                let batch = app
+                    .backend()
+                    .station_tracks(&station, last_track_id.as_deref())
+                    .unwrap();
+                app.radio_topped_up(&station, batch);
+                app.finished(Operation::TopUpRadio);
+                None
+            }
+            event => Some(event),
+        }
+    }
+
+    /// Tick until the song is over, the requests to preload the next one are not handled
+    async fn wait_for_song_end(
+        app: &mut App,
+        io_rx: &mut tokio::sync::mpsc::Receiver<IoEvent>,
+    ) -> bool {
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            app.update_on_tick();
+            while let Ok(event) = io_rx.try_recv() {
+                if matches!(handle_backend_event(app, event), Some(IoEvent::SongIsOver)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn app_can_be_initialized() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+
+        assert!(app.state().is_initialized());
+        assert_eq!(app.state().total_duration(), Some(&Duration::from_secs(1)));
+        assert_eq!(app.sources.items.len(), 3);
+        assert_eq!(app.sources.items[2].title(), "My Wave");
+        assert_eq!(app.sources.state.selected(), Some(1));
+        assert_eq!(app.current_playlist().len(), 3);
+        assert_eq!(app.tracks_title, "Playlist of the day");
+        assert_eq!(app.displayed_tracks.state.selected(), Some(0));
+        assert_eq!(app.focus(), Focus::Tracks);
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+    }
+
+    #[test]
+    fn app_reports_empty_playlist() {
+        let (io_tx, _io_rx) = tokio::sync::mpsc::channel(100);
+        let mut app = App::with_backend(io_tx, Box::new(FakeBackend::new()), Player::headless());
+
+        assert!(matches!(app.initialized(), Err(Error::EmptyQueue)));
+        assert!(!app.state().is_initialized());
+    }
+
+    #[tokio::test]
+    async fn app_can_open_another_source() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+
+        app.do_action(Key::Tab).await;
+        assert_eq!(app.focus(), Focus::Sources);
+        app.do_action(Key::Up).await;
+        app.do_action(Key::Enter).await;
+
+        assert_eq!(app.focus(), Focus::Tracks);
+        assert_eq!(app.current_playlist().len(), 1);
+        assert_eq!(app.current_playlist()[0].title(), "Missed");
+    }
+
+    #[tokio::test]
+    async fn app_can_play_found_track() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+
+        app.do_action(Key::Char('/')).await;
+        assert_eq!(app.focus(), Focus::SearchInput);
+        for c in "sec".chars() {
+            app.do_action(Key::Char(c)).await;
+        }
+        app.do_action(Key::Enter).await;
+
+        assert_eq!(app.focus(), Focus::SearchResults);
+        assert_eq!(app.search.query, "sec");
+        assert_eq!(app.search.results.items.len(), 1);
+
+        app.do_action(Key::Enter).await;
+
+        assert_eq!(app.focus(), Focus::Tracks);
+        assert_eq!(app.current_playlist()[0].title(), "Second");
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+    }
+
+    #[tokio::test]
+    async fn app_can_go_to_album_and_artist_and_back() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        app.do_action(Key::Down).await;
+
+        app.do_action(Key::Char('a')).await;
+        assert_eq!(app.tracks_title, "Album: Sines");
+        let titles: Vec<_> = app.current_playlist().iter().map(|t| t.title()).collect();
+        assert_eq!(titles, vec!["First", "Second"]);
+
+        app.do_action(Key::Char('r')).await;
+        assert_eq!(app.tracks_title, "Artist: Oscillator");
+        assert_eq!(app.current_playlist().len(), 3);
+
+        app.do_action(Key::Backspace).await;
+        assert_eq!(app.tracks_title, "Album: Sines");
+        app.do_action(Key::Backspace).await;
+        assert_eq!(app.tracks_title, "Playlist of the day");
+        assert_eq!(app.displayed_tracks.state.selected(), Some(1));
+
+        app.do_action(Key::Backspace).await;
+        assert_eq!(app.tracks_title, "Playlist of the day");
+    }
+
+    #[tokio::test]
+    async fn app_can_play_from_library() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+
+        app.do_action(Key::Char('l')).await;
+        assert_eq!(app.focus(), Focus::Library);
+        assert_eq!(app.library.tab, CatalogTab::Tracks);
+        assert_eq!(app.library.tracks()[0].title(), "Second");
+
+        for _ in 0..3 {
+            app.do_action(Key::Tab).await;
+        }
+        assert_eq!(app.library.tab, CatalogTab::Playlists);
+        assert_eq!(app.library.items.items.len(), 1);
+
+        app.do_action(Key::Enter).await;
+        assert_eq!(app.focus(), Focus::Tracks);
+        assert_eq!(app.tracks_title, "Road trip");
+        let titles: Vec<_> = app.current_playlist().iter().map(|t| t.title()).collect();
+        assert_eq!(titles, vec!["Third", "First"]);
+
+        app.do_action(Key::Backspace).await;
+        assert_eq!(app.tracks_title, "Playlist of the day");
+    }
+
+    #[tokio::test]
+    async fn app_can_like_playing_track() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        let first = app.playing().unwrap().clone();
+        assert_eq!(first.title(), "First");
+        assert!(!app.is_liked(&first));
+
+        app.do_action(Key::Char('f')).await;
+        assert!(app.is_liked(&first));
+
+        app.do_action(Key::Char('l')).await;
+        let liked: Vec<_> = app.library.tracks().iter().map(|t| t.title()).collect();
+        assert_eq!(liked, vec!["First", "Second"]);
+
+        app.do_action(Key::Char('f')).await;
+        assert!(!app.is_liked(&first));
+    }
+
+    #[tokio::test]
+    async fn app_can_add_track_to_playlist() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        app.do_action(Key::Down).await;
+
+        app.do_action(Key::Char('A')).await;
+        assert_eq!(app.focus(), Focus::PlaylistPicker);
+        assert_eq!(app.playlist_picker.items[0].title, "Road trip");
+        app.do_action(Key::Enter).await;
+        assert_eq!(app.focus(), Focus::Tracks);
+
+        app.do_action(Key::Char('l')).await;
+        for _ in 0..3 {
+            app.do_action(Key::Tab).await;
+        }
+        app.do_action(Key::Enter).await;
+        let titles: Vec<_> = app.current_playlist().iter().map(|t| t.title()).collect();
+        assert_eq!(titles, vec!["Third", "First", "Second"]);
+    }
+
+    #[tokio::test]
+    async fn app_skips_disliked_track() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        app.do_action(Key::Down).await;
+        app.do_action(Key::Enter).await;
+        let second = app.playing().unwrap().clone();
+        assert!(app.is_liked(&second));
+
+        app.do_action(Key::Char('d')).await;
+
+        assert!(!app.is_liked(&second));
+        assert_eq!(app.playing().unwrap().title(), "Third");
+        assert_eq!(app.queue.history().len(), 2);
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+    }
+
+    #[tokio::test]
+    async fn app_stops_disliked_last_track() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        app.do_action(Key::Down).await;
+        app.do_action(Key::Down).await;
+        app.do_action(Key::Enter).await;
+        assert_eq!(app.playing().unwrap().title(), "Third");
+
+        app.do_action(Key::Char('d')).await;
+
+        assert!(app.playing().is_none());
+        assert!(matches!(app.player.status(), Ok(Status::Empty)));
+    }
+
+    #[test]
+    fn app_has_unique_keys_in_every_focus() {
+        let (mut app, _io_rx) = create_app();
+        for focus in [
+            Focus::Sources,
+            Focus::Tracks,
+            Focus::SearchInput,
+            Focus::SearchResults,
+            Focus::Library,
+            Focus::PlaylistPicker,
+            Focus::Queue,
+        ] {
+            app.set_focus(focus);
+        }
+    }
+
+    #[test]
+    fn app_plays_queued_song_when_switched() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+
+        app.displayed_tracks.previous();
+        app.queue.next();
+        app.song_switched().unwrap();
+
+        assert_eq!(app.playing().unwrap().title(), "Second");
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+    }
+
+    #[tokio::test]
+    async fn app_switches_to_next_song_when_song_is_over() {
+        let (mut app, mut io_rx) = create_app();
+        app.initialized().unwrap();
+
+        app.update_on_tick();
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::PreloadNextSong)));
+        assert_eq!(app.displayed_tracks.state.selected(), Some(0));
+
+        assert!(wait_for_song_end(&mut app, &mut io_rx).await);
+        assert_eq!(app.queue.current().unwrap().title(), "Second");
+        assert_eq!(app.displayed_tracks.state.selected(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn app_can_seek_playing_track() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+
+        app.do_action(Key::Left).await;
+        assert!(app.state().duration().unwrap() < &Duration::from_millis(100));
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+
+        app.player.pause().unwrap();
+        app.player.seek(Duration::from_millis(200)).unwrap();
+        app.do_action(Key::Left).await;
+        assert_eq!(app.state().duration(), Some(&Duration::ZERO));
+        app.do_action(Key::Char('<')).await;
+        assert_eq!(app.state().duration(), Some(&Duration::ZERO));
+        assert!(matches!(app.player.status(), Ok(Status::Paused(..))));
+    }
+
+    #[tokio::test]
+    async fn app_sends_downloads_once_channel_has_room() {
+        let (io_tx, mut io_rx) = tokio::sync::mpsc::channel(1);
+        let (mut playing, _) = create_app();
+        playing.initialized().unwrap();
+        let tracks = playing.displayed_tracks.items.clone();
+        // Nothing is played, so the ticks do not wait to preload the next track
+        let mut app = App::with_backend(io_tx, Box::new(create_backend()), Player::headless());
+        app.start_download(tracks[0].clone(), Purpose::Offline);
+        app.start_download(tracks[1].clone(), Purpose::Offline);
+        app.cancel_download(0);
+        assert_eq!(app.downloads.len(), 1);
+        assert_eq!(app.undelivered.len(), 2);
+
+        assert!(matches!(
+            io_rx.try_recv(),
+            Ok(IoEvent::Download { id: 0, .. })
+        ));
+        app.update_on_tick();
+        assert!(matches!(
+            io_rx.try_recv(),
+            Ok(IoEvent::Download { id: 1, .. })
+        ));
+        app.update_on_tick();
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::CancelDownload(0))));
+        assert!(app.undelivered.is_empty());
+
+        // A download the worker cannot get is not shown as running
+        drop(io_rx);
+        app.start_download(tracks[0].clone(), Purpose::Play);
+        assert!(!app.downloads.contains_key(&2));
+        assert!(!app.in_flight().contains(&Operation::Download(2)));
+    }
+
+    #[test]
+    fn app_keeps_playing_while_channel_is_full() {
+        let (io_tx, mut io_rx) = tokio::sync::mpsc::channel(1);
+        io_tx.try_send(IoEvent::Initialize).unwrap();
+        let mut app = App::with_backend(io_tx, Box::new(create_backend()), Player::headless());
+        app.initialized().unwrap();
+        assert!(app.playing().is_some());
+
+        app.update_on_tick();
+        assert!(app.in_flight().contains(&Operation::PreloadSong));
+        assert_eq!(app.undelivered.len(), 1);
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::Initialize)));
+        app.update_on_tick();
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::PreloadNextSong)));
+
+        // Only the latest progress of a download is applied
+        let track = app.queue.peek_next().unwrap().clone();
+        app.start_download(track, Purpose::Offline);
+        let id = app.next_download - 1;
+        let reports = app.progress_reports();
+        for downloaded in [10, 20] {
+            let progress = Progress {
+                downloaded,
+                total: Some(100),
+                rate: 10,
+            };
+            reports.lock().unwrap().insert(id, progress);
+        }
+        app.update_on_tick();
+        assert_eq!(app.download(id).unwrap().progress.downloaded, 20);
+        assert!(reports.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn app_seeks_no_further_than_track_end() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        app.player.pause().unwrap();
+
+        app.do_action(Key::Right).await;
+        assert_eq!(app.state().duration(), Some(&Duration::from_secs(1)));
+        app.do_action(Key::Char('>')).await;
+        assert!(app.state().duration() <= app.state().total_duration());
+    }
+
+    #[tokio::test]
+    async fn app_saves_volume_changes() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        let dir = TempDir::new("volume");
+        let path = dir.path().join("config.json");
+        let config = Config {
+            volume: 0.5,
+            muted: true,
+            ..Config::default()
+        };
+        app.restore_config(config, path.clone());
+        assert!(app.is_muted());
+
+        app.do_action(Key::Char('+')).await;
+        assert!(!app.is_muted());
+        assert!((app.volume() - 0.55).abs() < 1e-6);
+        app.do_action(Key::Char('m')).await;
+
+        let saved = Config::load(&path).unwrap();
+        assert!(saved.muted);
+        assert!((saved.volume - 0.55).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn app_saves_crossfade_changes() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        let dir = TempDir::new("crossfade");
+        let path = dir.path().join("config.json");
+        let config = Config {
+            crossfade_secs: 11,
+            ..Config::default()
+        };
+        app.restore_config(config, path.clone());
+        assert_eq!(app.crossfade(), Duration::from_secs(11));
+
+        app.do_action(Key::Char(']')).await;
+        app.do_action(Key::Char(']')).await;
+        assert_eq!(app.crossfade(), Duration::from_secs(12));
+        app.do_action(Key::Char('[')).await;
+
+        let saved = Config::load(&path).unwrap();
+        assert_eq!(saved.crossfade_secs, 11);
+    }
+
+    #[tokio::test]
+    async fn app_saves_normalization_changes() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        let dir = TempDir::new("normalize");
+        let path = dir.path().join("config.json");
+        let config = Config {
+            normalization: Normalization::Album,
+            pre_amp_db: -3.0,
+            ..Config::default()
+        };
+        app.restore_config(config, path.clone());
+        assert_eq!(app.normalization(), Normalization::Album);
+        assert_eq!(app.pre_amp(), -3.0);
+
+        app.do_action(Key::Char('g')).await;
+        assert_eq!(app.normalization(), Normalization::Off);
+        app.do_action(Key::Char('g')).await;
+        app.do_action(Key::Char(')')).await;
+
+        let saved = Config::load(&path).unwrap();
+        assert_eq!(saved.normalization, Normalization::Track);
+        assert_eq!(saved.pre_amp_db, -2.0);
+    }
+
+    #[tokio::test]
+    async fn app_can_edit_queue_without_changing_list() {
+        let (mut app, _io_rx) = create_app();
+        app.initialized().unwrap();
+        let titles =
+            |app: &App| -> Vec<String> { app.queue.upcoming().iter().map(|t| t.title()).collect() };
+
+        app.do_action(Key::Up).await;
+        app.do_action(Key::Char('N')).await;
+        app.do_action(Key::Down).await;
+        app.do_action(Key::Char('e')).await;
+        assert_eq!(titles(&app), ["Third", "Second", "Third", "First"]);
+        assert_eq!(app.current_playlist().len(), 3);
+
+        app.do_action(Key::Char('u')).await;
+        assert_eq!(app.focus(), Focus::Queue);
+        app.do_action(Key::Char('J')).await;
+        assert_eq!(app.queue_state.selected(), Some(1));
+        app.do_action(Key::Delete).await;
+        assert_eq!(titles(&app), ["Second", "Third", "First"]);
+
+        app.do_action(Key::Enter).await;
+        assert_eq!(app.playing().unwrap().title(), "Third");
+        app.do_action(Key::Char('b')).await;
+        assert_eq!(app.playing().unwrap().title(), "Second");
+        assert_eq!(titles(&app), ["Third", "First"]);
+
+        app.do_action(Key::Char('c')).await;
+        app.do_action(Key::Char('n')).await;
+        assert!(app.queue.upcoming().is_empty());
+        assert_eq!(app.playing().unwrap().title(), "Second");
+    }
+
+    #[tokio::test]
+    async fn app_plays_preloaded_song_without_gap() {
+        let (mut app, mut io_rx) = create_app();
+        app.initialized().unwrap();
+        app.update_on_tick();
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::PreloadNextSong)));
+        assert_eq!(app.in_flight(), [Operation::PreloadSong]);
+        app.preload_next_song().unwrap();
+        app.finished(Operation::PreloadSong);
+        assert_eq!(app.player.queued(), 2);
+        assert!(app.in_flight().is_empty());
+
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            app.update_on_tick();
+            if app.playing().unwrap().title() == "Second" {
+                break;
+            }
+        }
+
+        assert_eq!(app.playing().unwrap().title(), "Second");
+        assert_eq!(app.queue.current().unwrap().title(), "Second");
+        assert!(app.state().duration().unwrap() < &Duration::from_millis(300));
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+        // The next song is switched without the IO handler
+        while let Ok(event) = io_rx.try_recv() {
+            assert!(!matches!(event, IoEvent::SongIsOver));
+        }
+    }
+
+    fn next_download(io_rx: &mut tokio::sync::mpsc::Receiver<IoEvent>) -> (DownloadId, Track) {
+        loop {
+            match io_rx.try_recv() {
+                Ok(IoEvent::Download { id, track }) => return (id, *track),
+                Ok(_) => continue,
+                Err(err) => panic!("No download is requested: {}", err),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn app_plays_song_once_it_is_downloaded() {
+        let mut backend = create_backend();
+        backend.set_download_delay(Duration::from_secs(1));
+        let (mut app, mut io_rx) = app_with_backend(backend);
+        app.initialized().unwrap();
+        assert_eq!(app.player.status().unwrap(), Status::Empty);
+        let (first_id, first) = next_download(&mut io_rx);
+        assert_eq!(first.title(), "First");
+        assert_eq!(app.downloads().count(), 1);
+
+        // Skipping the song cancels its download
+        app.do_action(Key::Char('n')).await;
+        let mut cancelled = false;
+        while let Ok(event) = io_rx.try_recv() {
+            cancelled |= matches!(event, IoEvent::CancelDownload(id) if id == first_id);
+        }
+        assert!(cancelled);
+        app.play_previous().unwrap();
+        let (id, track) = next_download(&mut io_rx);
+        assert_eq!(track.title(), "First");
+
+        // The file of the cancelled download is not played
+        let media = Media::File(fixture("sine-440.wav").to_string_lossy().into_owned());
+        app.download_ready(first_id, media.clone()).unwrap();
+        assert_eq!(app.player.status().unwrap(), Status::Empty);
+
+        let progress = Progress {
+            downloaded: 100,
+            total: Some(1000),
+            rate: 100,
+        };
+        app.download_progress(id, progress);
+        assert_eq!(app.download_of(&track), Some(&progress));
+        assert_eq!(app.in_flight(), [Operation::Download(id)]);
+        app.download_ready(id, media).unwrap();
+        assert_eq!(app.playing().unwrap().title(), "First");
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+        assert_eq!(app.downloads().count(), 0);
+        assert!(app.in_flight().is_empty());
+    }
+
+    #[tokio::test]
+    async fn app_preloads_downloaded_song() {
+        let (mut app, mut io_rx) = create_app();
+        app.initialized().unwrap();
+        app.backend = {
+            let mut backend = create_backend();
+            backend.set_download_delay(Duration::from_secs(1));
+            Arc::new(backend)
+        };
+        app.preload_next_song().unwrap();
+        let (id, track) = next_download(&mut io_rx);
+        assert_eq!(track.title(), "Second");
+        // It is downloaded once
+        app.preload_next_song().unwrap();
+        assert!(io_rx.try_recv().is_err());
+
+        app.download_failed(id, "Connection reset");
+        assert_eq!(app.downloads().count(), 0);
+        app.preload_next_song().unwrap();
+        let (id, _) = next_download(&mut io_rx);
+        let media = Media::File(fixture("sine-660.wav").to_string_lossy().into_owned());
+        app.download_ready(id, media).unwrap();
+        assert_eq!(app.player.queued(), 2);
+    }
+
+    fn open_offline_store(name: &str) -> (TempDir, Arc<Mutex<Cache>>, OfflineStore) {
+        let dir = TempDir::new(&format!("app-{}", name));
+        let cache = Arc::new(Mutex::new(
+            Cache::open(dir.path().join("tracks"), MB).unwrap(),
+        ));
+        let store =
+            OfflineStore::open(dir.path().join("offline.json"), Arc::clone(&cache)).unwrap();
+        (dir, cache, store)
+    }
+
+    #[tokio::test]
+    async fn app_makes_collections_available_offline() {
+        let mut backend = create_backend();
+        backend.set_download_delay(Duration::from_secs(1));
+        let (mut app, mut io_rx) = app_with_backend(backend);
+        let (_dir, cache, store) = open_offline_store("collections");
+        app.offline = Some(store);
+        app.set_focus(Focus::Tracks);
+
+        app.do_action(Key::Char('l')).await;
+        for _ in 0..3 {
+            app.do_action(Key::Tab).await;
+        }
+        app.do_action(Key::Char('o')).await;
+        let (third_id, third) = next_download(&mut io_rx);
+        let (_, first) = next_download(&mut io_rx);
+        assert_eq!([third.title(), first.title()], ["Third", "First"]);
+        assert!(cache.lock().unwrap().is_pinned(third.id()));
+
+        // The liked tracks wait for one of the downloads to end
+        app.do_action(Key::Tab).await;
+        app.do_action(Key::Char('o')).await;
+        assert!(app.in_flight().contains(&Operation::MakeLikedOffline));
+        let event = io_rx.try_recv().unwrap();
+        assert!(handle_backend_event(&mut app, event).is_none());
+        assert!(io_rx.try_recv().is_err());
+        assert_eq!(app.offline_queued(), 1);
+        app.download_failed(third_id, "Connection reset");
+        let (second_id, second) = next_download(&mut io_rx);
+        assert_eq!(second.title(), "Second");
+        assert_eq!(app.offline_queued(), 0);
+
+        // Playing the track neither cancels nor repeats its download
+        app.queue.play_from(vec![second], 0);
+        app.song_switched().unwrap();
+        assert!(io_rx.try_recv().is_err());
+        let media = Media::File(fixture("sine-660.wav").to_string_lossy().into_owned());
+        app.download_ready(second_id, media).unwrap();
+        assert_eq!(app.playing().unwrap().title(), "Second");
+        assert_eq!(app.downloads().count(), 1);
+    }
+
+    #[test]
+    fn app_plays_offline_collections_without_network() {
+        let backend = create_backend();
+        let album = backend.album("album-Sines").unwrap();
+        let tracks = album.tracks();
+        let (_dir, cache, mut store) = open_offline_store("playback");
+        store.add_album(album).unwrap();
+        {
+            let mut cache = cache.lock().unwrap();
+            let quality = Quality {
+                codec: String::from("flac"),
+                bitrate: 0,
+            };
+            fs::copy(
+                fixture("sine-440.flac"),
+                cache.path(tracks[0].id(), &quality),
+            )
+            .unwrap();
+            cache.insert(tracks[0].id(), &quality).unwrap();
+        }
+        let backend = OfflineBackend::new(store, QualityPreference::default());
+        let (io_tx, _io_rx) = tokio::sync::mpsc::channel(100);
+        let mut app = App::with_backend(io_tx, Box::new(backend), Player::headless());
+        app.initialized().unwrap();
+
+        assert_eq!(app.sources.items.len(), 1);
+        assert_eq!(app.tracks_title, "Album: Sines");
+        assert_eq!(app.playing().unwrap().title(), "First");
+        assert!(matches!(app.player.status(), Ok(Status::Playing(..))));
+        // Tracks that are not downloaded are not fetched
+        let fetch = app.fetcher();
+        assert!(matches!(fetch(&tracks[1]), Err(Error::Offline(_))));
+    }
+
+    #[tokio::test]
+    async fn app_repeats_song_when_song_is_over() {
+        let (mut app, mut io_rx) = create_app();
+        app.initialized().unwrap();
+        app.do_action(Key::Char('s')).await;
+        app.do_action(Key::Char('R')).await;
+        app.do_action(Key::Char('R')).await;
+        assert!(app.queue.is_shuffled());
+        assert_eq!(app.queue.repeat(), Repeat::One);
+
+        assert!(wait_for_song_end(&mut app, &mut io_rx).await);
+        assert_eq!(app.queue.current().unwrap().title(), "First");
+        assert!(app.queue.history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn app_keeps_radio_queue_filled() {
+        let backend = create_backend();
+        let feedback = backend.feedback();
+        let (mut app, mut io_rx) = app_with_backend(backend);
+        app.initialized().unwrap();
+
+        app.do_action(Key::Tab).await;
+        app.do_action(Key::Down).await;
+        app.do_action(Key::Enter).await;
+        assert_eq!(app.tracks_title, "My Wave");
+        assert_eq!(app.current_playlist().len(), 2);
+        assert!(feedback.lock().unwrap().is_empty());
+
+        app.do_action(Key::Enter).await;
+        let playing = app.playing().unwrap().id().to_string();
+        assert_eq!(app.queue.upcoming().len(), 1);
+        assert!(app.in_flight().contains(&Operation::TopUpRadio));
+        while let Ok(event) = io_rx.try_recv() {
+            handle_backend_event(&mut app, event);
+        }
+        assert_eq!(app.queue.upcoming().len(), 3);
+        assert_eq!(app.current_playlist().len(), 2);
+        assert_eq!(
+            feedback.lock().unwrap()[..],
+            [
+                Feedback::RadioStarted,
+                Feedback::TrackStarted {
+                    track_id: playing.clone()
+                }
+            ]
+        );
+
+        assert!(wait_for_song_end(&mut app, &mut io_rx).await);
+        assert_eq!(app.queue.history().len(), 2);
+        assert!(feedback.lock().unwrap().iter().any(
+            |f| matches!(f, Feedback::TrackFinished { track_id, .. } if *track_id == playing)
+        ));
+    }
+
+    #[tokio::test]
+    async fn app_drops_radio_tracks_once_radio_is_stopped() {
+        let (mut app, mut io_rx) = create_app();
+        app.initialized().unwrap();
+        app.do_action(Key::Tab).await;
+        app.do_action(Key::Down).await;
+        app.do_action(Key::Enter).await;
+        app.do_action(Key::Enter).await;
+        let top_up = std::iter::from_fn(|| io_rx.try_recv().ok())
+            .find(|event| matches!(event, IoEvent::TopUpRadio { .. }))
+            .unwrap();
+
+        app.do_action(Key::Tab).await;
+        app.do_action(Key::Up).await;
+        app.do_action(Key::Enter).await;
+        app.do_action(Key::Enter).await;
+        assert_eq!(app.tracks_title, "Playlist of the day");
+        let upcoming = app.queue.upcoming().len();
+        handle_backend_event(&mut app, top_up);
+        assert_eq!(app.queue.upcoming().len(), upcoming);
     }
 }
@@ -0,0 +1,79 @@
+use std::fmt::{self, Display};
+
+use yandex_rust_music::{Album, Artist, Playlist, SearchKind, Track};
+
+/// Tabs of the search results and of the library, one per kind of catalog items
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CatalogTab {
+    #[default]
+    Tracks,
+    Albums,
+    Artists,
+    Playlists,
+}
+
+impl CatalogTab {
+    pub fn all() -> [CatalogTab; 4] {
+        [
+            CatalogTab::Tracks,
+            CatalogTab::Albums,
+            CatalogTab::Artists,
+            CatalogTab::Playlists,
+        ]
+    }
+
+    pub fn index(self) -> usize {
+        Self::all().iter().position(|tab| *tab == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        let tabs = Self::all();
+        tabs[(self.index() + 1) % tabs.len()]
+    }
+
+    pub fn kind(self) -> SearchKind {
+        match self {
+            CatalogTab::Tracks => SearchKind::Track,
+            CatalogTab::Albums => SearchKind::Album,
+            CatalogTab::Artists => SearchKind::Artist,
+            CatalogTab::Playlists => SearchKind::Playlist,
+        }
+    }
+}
+
+impl Display for CatalogTab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            CatalogTab::Tracks => "Tracks",
+            CatalogTab::Albums => "Albums",
+            CatalogTab::Artists => "Artists",
+            CatalogTab::Playlists => "Playlists",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Entry of the search results or of the library
+#[derive(Clone)]
+pub enum CatalogItem {
+    Track(Box<Track>),
+    Album(Album),
+    Artist(Artist),
+    Playlist(Playlist),
+}
+
+impl Display for CatalogItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogItem::Track(track) => write!(f, "{}", track.title()),
+            CatalogItem::Album(album) => match album.year {
+                Some(year) => write!(f, "{} ({})", album.title, year),
+                None => write!(f, "{}", album.title),
+            },
+            CatalogItem::Artist(artist) => write!(f, "{}", artist.name),
+            CatalogItem::Playlist(playlist) => {
+                write!(f, "{} [{}]", playlist.title, playlist.track_count)
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::{Config, MB};
+
+use eyre::eyre;
+use yandex_rust_music::Usage;
+
+const USAGE: &str = "Usage: yandex-music-cli [--offline | cache [purge]]";
+
+/// Command run instead of the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Cache(CacheCommand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheCommand {
+    /// Show the space taken by the downloaded tracks
+    Usage,
+    /// Remove the downloaded tracks
+    Purge,
+}
+
+/// What the arguments ask for
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    /// Play the collections made available offline without the network
+    pub offline: bool,
+    /// None to start the player
+    pub command: Option<Command>,
+}
+
+/// Arguments without the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> eyre::Result<Args> {
+    let args: Vec<String> = args.into_iter().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (offline, command) = match args.as_slice() {
+        [] => (false, None),
+        ["--offline"] => (true, None),
+        ["cache"] => (false, Some(Command::Cache(CacheCommand::Usage))),
+        ["cache", "purge"] => (false, Some(Command::Cache(CacheCommand::Purge))),
+        _ => return Err(eyre!("Unknown command {}\n{}", args.join(" "), USAGE)),
+    };
+    Ok(Args { offline, command })
+}
+
+pub fn run(command: Command) -> eyre::Result<()> {
+    let config = match Config::default_path() {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    match command {
+        // Reading the usage leaves the files of a running player alone
+        Command::Cache(CacheCommand::Usage) => {
+            let (dir, usage) = config.cache_usage()?;
+            println!("{}", dir.display());
+            println!("{}", describe(usage));
+        }
+        Command::Cache(CacheCommand::Purge) => {
+            let mut cache = config.open_cache()?;
+            let usage = cache.usage();
+            cache.purge()?;
+            println!("Removed {} files, {}", usage.files, megabytes(usage.size));
+        }
+    }
+    Ok(())
+}
+
+fn megabytes(size: u64) -> String {
+    format!("{:.1} MB", size as f64 / MB as f64)
+}
+
+fn describe(usage: Usage) -> String {
+    format!(
+        "{} files, {} of {}",
+        usage.files,
+        megabytes(usage.size),
+        megabytes(usage.max_size)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn cache_commands_are_parsed() {
+        assert_eq!(parse(args("")).unwrap(), Args::default());
+        assert_eq!(
+            parse(args("cache")).unwrap().command,
+            Some(Command::Cache(CacheCommand::Usage))
+        );
+        assert_eq!(
+            parse(args("cache purge")).unwrap().command,
+            Some(Command::Cache(CacheCommand::Purge))
+        );
+        assert!(parse(args("cache clear")).is_err());
+        assert!(parse(args("play")).is_err());
+    }
+
+    #[test]
+    fn offline_mode_is_parsed() {
+        let offline = Args {
+            offline: true,
+            command: None,
+        };
+        assert_eq!(parse(args("--offline")).unwrap(), offline);
+        assert!(parse(args("--offline cache")).is_err());
+    }
+
+    #[test]
+    fn cache_usage_is_described() {
+        let usage = Usage {
+            files: 2,
+            size: 3 * MB / 2,
+            max_size: 1024 * MB,
+        };
+        assert_eq!(describe(usage), "2 files, 1.5 MB of 1024.0 MB");
+    }
+}
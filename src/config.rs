@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use yandex_rust_music::{Cache, Normalization, OfflineStore, QualityPreference, Usage};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Directory of the application files inside the XDG base directories
+const APP_DIR: &str = "yandex-music-cli";
+pub const MB: u64 = 1024 * 1024;
+
+/// User settings restored on the next launch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Perceived volume from 0 to 1
+    pub volume: f32,
+    pub muted: bool,
+    /// Seconds the next track is faded in over the playing one
+    pub crossfade_secs: u64,
+    pub normalization: Normalization,
+    /// Gain in dB added to normalized tracks
+    pub pre_amp_db: f32,
+    /// Size limit of the downloaded tracks
+    pub cache_size_mb: u64,
+    /// Codec and bitrate of the downloaded tracks
+    pub quality: QualityPreference,
+    /// Play only the collections made available offline, without the network
+    pub offline: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+            crossfade_secs: 0,
+            normalization: Normalization::Off,
+            pre_amp_db: 0.0,
+            cache_size_mb: 1024,
+            quality: QualityPreference::default(),
+            offline: false,
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/yandex-music-cli/config.json`
+    pub fn default_path() -> Option<PathBuf> {
+        xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("config.json"))
+    }
+
+    /// Settings saved in the file, the default ones if there is no file yet
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Downloaded tracks in `$XDG_CACHE_HOME/yandex-music-cli/tracks`
+    pub fn open_cache(&self) -> eyre::Result<Cache> {
+        Ok(Cache::open(tracks_dir()?, self.cache_size_mb * MB)?)
+    }
+
+    /// Directory of the downloaded tracks and the space they take, nothing is changed there
+    pub fn cache_usage(&self) -> eyre::Result<(PathBuf, Usage)> {
+        let dir = tracks_dir()?;
+        let usage = Cache::usage_of(&dir, self.cache_size_mb * MB)?;
+        Ok((dir, usage))
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Collections made available offline in `$XDG_CACHE_HOME/yandex-music-cli/offline.json`,
+/// their tracks are in the cache
+pub fn open_offline_store(cache: Arc<Mutex<Cache>>) -> eyre::Result<OfflineStore> {
+    Ok(OfflineStore::open(
+        cache_dir()?.join("offline.json"),
+        cache,
+    )?)
+}
+
+fn cache_dir() -> eyre::Result<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache").ok_or_else(|| eyre::eyre!("There is no cache directory"))
+}
+
+fn tracks_dir() -> eyre::Result<PathBuf> {
+    Ok(cache_dir()?.join("tracks"))
+}
+
+/// Application directory inside the XDG base directory set by the variable,
+/// `fallback` is relative to the home directory
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))?;
+    Some(base.join(APP_DIR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yandex_rust_music::testing::TempDir;
+
+    #[test]
+    fn config_is_restored_after_save() {
+        let dir = TempDir::new("config");
+        let path = dir.path().join("config.json");
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+        let config = Config {
+            volume: 0.4,
+            muted: true,
+            crossfade_secs: 5,
+            normalization: Normalization::Track,
+            pre_amp_db: 2.0,
+            cache_size_mb: 100,
+            quality: QualityPreference {
+                lossless: true,
+                max_bitrate: Some(192),
+            },
+            offline: true,
+        };
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use yandex_rust_music::{Fetcher, Media, Track};
+
+use super::IoEvent;
+
+pub type DownloadId = u64;
+
+/// Latest progress of each download. It is replaced by the next report instead of
+/// queueing them all in the channel, the app takes it on its ticks
+pub type ProgressReports = Arc<Mutex<HashMap<DownloadId, Progress>>>;
+
+/// The player decodes the start of the file right away, the track is played once it is there
+const HEAD_SIZE: u64 = 64 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// Weight of the last report in the throughput
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// How far the download is
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Progress {
+    pub downloaded: u64,
+    /// Size of the file, unknown until the server tells it
+    pub total: Option<u64>,
+    /// Bytes a second
+    pub rate: u64,
+}
+
+impl Progress {
+    /// Downloaded part from 0 to 1
+    pub fn fraction(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.downloaded as f64 / total as f64).min(1.0))
+    }
+
+    /// Time left at the current rate
+    pub fn eta(&self) -> Option<Duration> {
+        let left = self.total?.saturating_sub(self.downloaded);
+        match self.rate {
+            0 => None,
+            rate => Some(Duration::from_secs_f64(left as f64 / rate as f64)),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total == Some(self.downloaded)
+    }
+}
+
+/// Rate of the download smoothed over the reports, so that it does not jump around
+struct Throughput {
+    at: Instant,
+    downloaded: u64,
+    rate: Option<f64>,
+}
+
+impl Throughput {
+    fn new(at: Instant) -> Self {
+        Self {
+            at,
+            downloaded: 0,
+            rate: None,
+        }
+    }
+
+    /// Bytes a second
+    fn update(&mut self, now: Instant, downloaded: u64) -> u64 {
+        let elapsed = now.duration_since(self.at).as_secs_f64();
+        if elapsed > 0.0 {
+            let current = downloaded.saturating_sub(self.downloaded) as f64 / elapsed;
+            let rate = match self.rate {
+                Some(rate) => RATE_SMOOTHING * current + (1.0 - RATE_SMOOTHING) * rate,
+                None => current,
+            };
+            self.rate = Some(rate);
+            self.at = now;
+            self.downloaded = downloaded;
+        }
+        self.rate.unwrap_or_default().round() as u64
+    }
+}
+
+struct Task {
+    handle: JoinHandle<()>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Runs the downloads of tracks on worker tasks, they report back with io events and
+/// their progress
+pub struct DownloadManager {
+    fetcher: Fetcher,
+    progress: ProgressReports,
+    io_tx: Sender<IoEvent>,
+    tasks: HashMap<DownloadId, Task>,
+}
+
+impl DownloadManager {
+    pub fn new(fetcher: Fetcher, progress: ProgressReports, io_tx: Sender<IoEvent>) -> Self {
+        Self {
+            fetcher,
+            progress,
+            io_tx,
+            tasks: HashMap::new(),
+        }
+    }
+
+    pub fn start(&mut self, id: DownloadId, track: Box<Track>) {
+        self.tasks.retain(|_, task| !task.handle.is_finished());
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let handle = tokio::spawn(download(
+            id,
+            track,
+            Arc::clone(&self.fetcher),
+            Arc::clone(&self.progress),
+            self.io_tx.clone(),
+            Arc::clone(&cancelled),
+        ));
+        self.tasks.insert(id, Task { handle, cancelled });
+    }
+
+    /// Stop the download, nothing is reported about it anymore
+    pub fn cancel(&mut self, id: DownloadId) {
+        if let Some(task) = self.tasks.remove(&id) {
+            task.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+async fn download(
+    id: DownloadId,
+    track: Box<Track>,
+    fetcher: Fetcher,
+    reports: ProgressReports,
+    io_tx: Sender<IoEvent>,
+    cancelled: Arc<AtomicBool>,
+) {
+    debug!("Download {}", track.title());
+    // The service is requested with blocking calls
+    let fetched = tokio::task::spawn_blocking(move || fetcher(&track)).await;
+    let media = match fetched {
+        Ok(Ok(media)) => media,
+        Ok(Err(err)) => return failed(&io_tx, id, err.to_string()).await,
+        Err(err) => return failed(&io_tx, id, err.to_string()).await,
+    };
+    let stream = match &media {
+        Media::Stream(stream) => stream.clone(),
+        Media::File(_) => {
+            if !cancelled.load(Ordering::Relaxed) {
+                let _ = io_tx.send(IoEvent::DownloadReady { id, media }).await;
+            }
+            return;
+        }
+    };
+
+    let mut ready = false;
+    let mut throughput = Throughput::new(Instant::now());
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            debug!("The download {} is cancelled", id);
+            stream.cancel();
+            return;
+        }
+        if let Some(error) = stream.error() {
+            return failed(&io_tx, id, error).await;
+        }
+        let complete = stream.is_complete();
+        let downloaded = stream.downloaded();
+        if !ready && (complete || downloaded >= HEAD_SIZE) {
+            ready = true;
+            let media = media.clone();
+            let _ = io_tx.send(IoEvent::DownloadReady { id, media }).await;
+        }
+        let progress = Progress {
+            downloaded,
+            total: stream.total(),
+            rate: throughput.update(Instant::now(), downloaded),
+        };
+        reports.lock().unwrap().insert(id, progress);
+        if io_tx.is_closed() || complete {
+            return;
+        }
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
+    }
+}
+
+async fn failed(io_tx: &Sender<IoEvent>, id: DownloadId, error: String) {
+    let _ = io_tx.send(IoEvent::DownloadFailed { id, error }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throughput_is_smoothed() {
+        let start = Instant::now();
+        let mut throughput = Throughput::new(start);
+        assert_eq!(throughput.update(start, 0), 0);
+        assert_eq!(
+            throughput.update(start + Duration::from_secs(1), 1000),
+            1000
+        );
+        // The rate changes gradually
+        assert_eq!(
+            throughput.update(start + Duration::from_secs(2), 3000),
+            1300
+        );
+        assert_eq!(throughput.update(start + Duration::from_secs(3), 3000), 910);
+    }
+
+    #[test]
+    fn progress_tells_time_left() {
+        let progress = Progress {
+            downloaded: 250,
+            total: Some(1000),
+            rate: 50,
+        };
+        assert_eq!(progress.fraction(), Some(0.25));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(15)));
+        assert!(!progress.is_complete());
+
+        let unknown = Progress {
+            downloaded: 250,
+            total: None,
+            rate: 0,
+        };
+        assert_eq!(unknown.fraction(), None);
+        assert_eq!(unknown.eta(), None);
+    }
+}
@@ -1,38 +1,86 @@
 use std::sync::Arc;
 
 use eyre::Result;
-use log::{error, info};
+use log::{error, info, warn};
+use yandex_rust_music::{Feedback, MusicBackend, Station, StationId};
 
+use super::download::DownloadManager;
 use super::IoEvent;
-use crate::app::App;
+use crate::app::{App, Operation, Startup};
 
+/// Handles the events of the application. The lock of the app is taken only to apply the
+/// results, the requests and the downloads run without it
 pub struct IoAsyncHandler {
     app: Arc<tokio::sync::Mutex<App>>,
+    backend: Arc<dyn MusicBackend>,
+    downloads: DownloadManager,
 }
 
 impl IoAsyncHandler {
-    pub fn new(app: Arc<tokio::sync::Mutex<App>>) -> Self {
-        Self { app }
+    pub fn new(
+        app: Arc<tokio::sync::Mutex<App>>,
+        backend: Arc<dyn MusicBackend>,
+        downloads: DownloadManager,
+    ) -> Self {
+        Self {
+            app,
+            backend,
+            downloads,
+        }
     }
 
     pub async fn handle_io_event(&mut self, io_event: IoEvent) {
+        let operation = Operation::of(&io_event);
         let result = match io_event {
             IoEvent::Initialize => self.do_initialize().await,
             IoEvent::SongIsOver => self.play_next_song().await,
+            IoEvent::PreloadNextSong => self.preload_next_song().await,
+            IoEvent::TopUpRadio {
+                station,
+                last_track_id,
+            } => self.top_up_radio(station, last_track_id).await,
+            IoEvent::RadioFeedback {
+                station,
+                batch_id,
+                feedback,
+            } => self.send_feedback(station, batch_id, feedback).await,
+            IoEvent::MakeLikedOffline => self.make_liked_offline().await,
+            IoEvent::Download { id, track } => {
+                self.downloads.start(id, track);
+                Ok(())
+            }
+            IoEvent::CancelDownload(id) => {
+                self.downloads.cancel(id);
+                Ok(())
+            }
+            IoEvent::DownloadReady { id, media } => {
+                let mut app = self.app.lock().await;
+                app.download_ready(id, media).map_err(Into::into)
+            }
+            IoEvent::DownloadFailed { id, error } => {
+                let mut app = self.app.lock().await;
+                app.download_failed(id, &error);
+                Ok(())
+            }
         };
 
         if let Err(err) = result {
             error!("Oops, something wrong happen {:?}", err);
         }
 
-        let mut app = self.app.lock().await;
-        app.loaded();
+        if let Some(operation) = operation {
+            let mut app = self.app.lock().await;
+            app.finished(operation);
+        }
     }
 
     async fn do_initialize(&mut self) -> Result<()> {
         info!("Initialize the application");
+        let backend = Arc::clone(&self.backend);
+        let startup =
+            tokio::task::spawn_blocking(move || Startup::fetch(backend.as_ref())).await??;
         let mut app = self.app.lock().await;
-        app.initialized();
+        app.started(startup)?;
         info!("Application initialized");
 
         Ok(())
@@ -42,8 +90,104 @@ impl IoAsyncHandler {
         info!(" Switching to the next song");
 
         let mut app = self.app.lock().await;
-        app.song_switched();
+        app.song_switched()?;
         info!("The song is switched");
         Ok(())
     }
+
+    async fn preload_next_song(&self) -> Result<()> {
+        let mut app = self.app.lock().await;
+        app.preload_next_song()?;
+        Ok(())
+    }
+
+    async fn top_up_radio(&self, station: StationId, last_track_id: Option<String>) -> Result<()> {
+        let backend = Arc::clone(&self.backend);
+        let id = station.clone();
+        let batch = tokio::task::spawn_blocking(move || {
+            backend.station_tracks(&id, last_track_id.as_deref())
+        })
+        .await??;
+        let mut app = self.app.lock().await;
+        app.radio_topped_up(&station, batch);
+        Ok(())
+    }
+
+    /// The ids are fetched once, the tracks in batches of them
+    async fn make_liked_offline(&self) -> Result<()> {
+        let backend = Arc::clone(&self.backend);
+        let tracks = tokio::task::spawn_blocking(move || {
+            let ids = backend.liked_track_ids()?;
+            backend.tracks(&ids)
+        })
+        .await??;
+        let mut app = self.app.lock().await;
+        app.liked_tracks_fetched(tracks)?;
+        Ok(())
+    }
+
+    /// The feedback is not sent again if it fails, the station just picks worse tracks
+    async fn send_feedback(
+        &self,
+        station: Station,
+        batch_id: Option<String>,
+        feedback: Feedback,
+    ) -> Result<()> {
+        let backend = Arc::clone(&self.backend);
+        let sent = tokio::task::spawn_blocking(move || {
+            backend.station_feedback(&station, batch_id.as_deref(), &feedback)
+        })
+        .await?;
+        if let Err(err) = sent {
+            warn!("Cannot send radio feedback: {}", err);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use yandex_rust_music::{FakeBackend, Player};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn downloads_do_not_hold_app() {
+        let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sine-440.wav");
+        let mut backend = FakeBackend::new();
+        backend.add_track("First", Duration::from_secs(1), fixture);
+        backend.set_download_delay(Duration::from_millis(500));
+        let (io_tx, mut io_rx) = tokio::sync::mpsc::channel(100);
+        let app = App::with_backend(io_tx.clone(), Box::new(backend), Player::headless());
+        let downloads = DownloadManager::new(app.fetcher(), app.progress_reports(), io_tx);
+        let backend = app.backend();
+        let app = Arc::new(tokio::sync::Mutex::new(app));
+        let mut handler = IoAsyncHandler::new(Arc::clone(&app), backend, downloads);
+        tokio::spawn(async move {
+            while let Some(io_event) = io_rx.recv().await {
+                handler.handle_io_event(io_event).await;
+            }
+        });
+
+        app.lock().await.initialized().unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        {
+            let app = app
+                .try_lock()
+                .expect("The app is not locked while downloading");
+            assert_eq!(app.downloads().count(), 1);
+            assert!(app.playing().is_none());
+        }
+
+        for _ in 0..20 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if app.lock().await.playing().is_some() {
+                break;
+            }
+        }
+        let app = app.lock().await;
+        assert_eq!(app.playing().unwrap().title(), "First");
+        assert_eq!(app.downloads().count(), 0);
+    }
 }
@@ -1,7 +1,42 @@
+pub mod download;
 pub mod handler;
 
+use download::DownloadId;
+use yandex_rust_music::{Feedback, Media, Station, StationId, Track};
+
 #[derive(Debug, Clone)]
 pub enum IoEvent {
     Initialize,
     SongIsOver,
+    /// Append the next queued song to the player before the current one is over
+    PreloadNextSong,
+    /// Fetch the next tracks of the station after the last queued one
+    TopUpRadio {
+        station: StationId,
+        last_track_id: Option<String>,
+    },
+    /// Tell the station what happened to the played tracks
+    RadioFeedback {
+        station: Station,
+        batch_id: Option<String>,
+        feedback: Feedback,
+    },
+    /// Fetch all of the liked tracks and make them available offline
+    MakeLikedOffline,
+    /// Fetch the audio of the track on a worker task
+    Download {
+        id: DownloadId,
+        track: Box<Track>,
+    },
+    /// The track is not needed anymore, its download is stopped
+    CancelDownload(DownloadId),
+    /// The track can be played, the rest of the file may still be downloaded
+    DownloadReady {
+        id: DownloadId,
+        media: Media,
+    },
+    DownloadFailed {
+        id: DownloadId,
+        error: String,
+    },
 }
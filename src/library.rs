@@ -0,0 +1,67 @@
+use crate::app::DisplayedList;
+use crate::catalog::{CatalogItem, CatalogTab};
+
+use yandex_rust_music::{Page, Track};
+
+/// Likes and playlists of the user
+#[derive(Default)]
+pub struct LibraryView {
+    pub tab: CatalogTab,
+    pub page: u32,
+    pub total: u32,
+    pub per_page: u32,
+    pub items: DisplayedList<CatalogItem>,
+}
+
+impl LibraryView {
+    /// Liked tracks come by pages
+    pub fn set_tracks(&mut self, tracks: Page<Track>) {
+        self.page = tracks.page;
+        self.total = tracks.total;
+        self.per_page = tracks.per_page;
+        self.show(
+            tracks
+                .items
+                .into_iter()
+                .map(|track| CatalogItem::Track(Box::new(track)))
+                .collect(),
+        );
+    }
+
+    /// Other collections are small enough to fit a single page
+    pub fn set_items(&mut self, items: Vec<CatalogItem>) {
+        self.page = 0;
+        self.total = items.len() as u32;
+        self.per_page = self.total;
+        self.show(items);
+    }
+
+    fn show(&mut self, items: Vec<CatalogItem>) {
+        self.items.set_items(items);
+        self.items.next();
+    }
+
+    pub fn has_next_page(&self) -> bool {
+        (self.page + 1) * self.per_page < self.total
+    }
+
+    pub fn page_count(&self) -> u32 {
+        if self.per_page == 0 {
+            0
+        } else {
+            self.total.div_ceil(self.per_page)
+        }
+    }
+
+    /// Tracks listed on the current page
+    pub fn tracks(&self) -> Vec<Track> {
+        self.items
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                CatalogItem::Track(track) => Some(track.as_ref().clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
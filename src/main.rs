@@ -1,7 +1,13 @@
 mod actions;
 mod app;
+mod catalog;
+mod cli;
+mod config;
 mod inputs;
 mod io;
+mod library;
+mod queue;
+mod search;
 mod ui;
 
 use app::App;
@@ -10,6 +16,7 @@ use app::AppReturn;
 use inputs::events::Events;
 use inputs::InputEvent;
 
+use io::download::DownloadManager;
 use io::handler::IoAsyncHandler;
 use io::IoEvent;
 
@@ -31,20 +38,22 @@ pub async fn start_ui(app: &Arc<tokio::sync::Mutex<App>>) -> Result<(), Box<dyn
     terminal.clear()?;
     terminal.hide_cursor()?;
 
-    {
-        let mut app = app.lock().await;
-        app.dispatch(IoEvent::Initialize).await;
-    }
+    app.lock().await.dispatch(IoEvent::Initialize);
 
     let tick_rate = Duration::from_millis(200);
     let mut events = Events::new(tick_rate);
     loop {
-        let mut app = app.lock().await;
-        terminal.draw(|rect| ui::draw(rect, &mut app))?;
+        {
+            let mut app = app.lock().await;
+            terminal.draw(|rect| ui::draw(rect, &mut app))?;
+        }
 
-        let result = match events.next().await {
+        // The worker applies its results to the app while the UI waits for the events
+        let event = events.next().await;
+        let mut app = app.lock().await;
+        let result = match event {
             InputEvent::Input(key) => app.do_action(key).await,
-            InputEvent::Tick => app.update_on_tick().await,
+            InputEvent::Tick => app.update_on_tick(),
         };
 
         if result == AppReturn::Exit {
@@ -62,21 +71,33 @@ pub async fn start_ui(app: &Arc<tokio::sync::Mutex<App>>) -> Result<(), Box<dyn
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let args = cli::parse(std::env::args().skip(1))?;
+    if let Some(command) = args.command {
+        return Ok(cli::run(command)?);
+    }
+
     tui_logger::init_logger(LevelFilter::Debug).unwrap();
     tui_logger::set_default_level(log::LevelFilter::Debug);
 
     let (sync_io_tx, mut sync_io_rx) = tokio::sync::mpsc::channel::<IoEvent>(100);
 
-    let app = Arc::new(tokio::sync::Mutex::new(App::new(sync_io_tx.clone())));
+    let app = App::new(sync_io_tx.clone(), args.offline)?;
+    let downloads = DownloadManager::new(app.fetcher(), app.progress_reports(), sync_io_tx);
+    let backend = app.backend();
+    let app = Arc::new(tokio::sync::Mutex::new(app));
     let app_ui = Arc::clone(&app);
 
     tokio::spawn(async move {
-        let mut handler = IoAsyncHandler::new(app);
+        let mut handler = IoAsyncHandler::new(app, backend, downloads);
         while let Some(io_event) = sync_io_rx.recv().await {
             handler.handle_io_event(io_event).await;
         }
     });
 
-    start_ui(&app_ui).await?;
-    Ok(())
+    let result = start_ui(&app_ui).await;
+    if result.is_err() {
+        // Do not leave the terminal in raw mode on failure
+        crossterm::terminal::disable_raw_mode()?;
+    }
+    result
 }
@@ -0,0 +1,399 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use std::fmt::{self, Display};
+
+use yandex_rust_music::Track;
+
+/// What is played once the queue is over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Repeat {
+    #[default]
+    Off,
+    /// The whole list again
+    All,
+    /// The current track again, skipping still plays the next one
+    One,
+}
+
+impl Repeat {
+    pub fn next(self) -> Self {
+        match self {
+            Repeat::Off => Repeat::All,
+            Repeat::All => Repeat::One,
+            Repeat::One => Repeat::Off,
+        }
+    }
+}
+
+impl Display for Repeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Repeat::Off => "off",
+            Repeat::All => "all",
+            Repeat::One => "one",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Tracks to play: the played ones, the playing one and the upcoming ones.
+/// It is filled from a list but is not changed when the list is
+#[derive(Default)]
+pub struct Queue {
+    history: Vec<Track>,
+    current: Option<Track>,
+    upcoming: Vec<Track>,
+    // Tracks the queue was filled from in their order, played again by `Repeat::All`
+    list: Vec<Track>,
+    shuffle: bool,
+    repeat: Repeat,
+}
+
+impl Queue {
+    /// Play the tracks starting from the one at `start`, the tracks before it are skipped.
+    /// When shuffled, all the other tracks are played after it in random order
+    pub fn play_from(&mut self, mut tracks: Vec<Track>, start: usize) {
+        if start >= tracks.len() {
+            return;
+        }
+        if let Some(current) = self.current.take() {
+            self.history.push(current);
+        }
+        self.list = tracks.clone();
+        if self.shuffle {
+            self.current = Some(tracks.remove(start));
+            tracks.shuffle(&mut rand::thread_rng());
+            self.upcoming = tracks;
+        } else {
+            self.upcoming = tracks.split_off(start + 1);
+            self.current = tracks.pop();
+        }
+        self.refill();
+    }
+
+    /// Queue the list again once all of it is played if it is repeated,
+    /// so the next track is known before the current one is over.
+    /// A shuffled pass does not start with the track played last
+    fn refill(&mut self) {
+        if self.upcoming.is_empty() && self.repeat == Repeat::All {
+            self.upcoming = self.list.clone();
+            if self.shuffle {
+                let mut rng = rand::thread_rng();
+                self.upcoming.shuffle(&mut rng);
+                let last = self.current.as_ref().or(self.history.last());
+                let len = self.upcoming.len();
+                if len > 1 && last.map(|track| &track.id) == Some(&self.upcoming[0].id) {
+                    self.upcoming.swap(0, rng.gen_range(1..len));
+                }
+            }
+        }
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Shuffle the upcoming tracks once, so none of them is repeated until all are played.
+    /// They are put back in the list order when the shuffle is turned off
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle == self.shuffle {
+            return;
+        }
+        self.shuffle = shuffle;
+        if shuffle {
+            self.upcoming.shuffle(&mut rand::thread_rng());
+        } else {
+            let list = &self.list;
+            self.upcoming.sort_by_key(|track| {
+                list.iter()
+                    .position(|listed| listed.id == track.id)
+                    .unwrap_or(usize::MAX)
+            });
+        }
+    }
+
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.repeat = repeat;
+        self.refill();
+    }
+
+    pub fn current(&self) -> Option<&Track> {
+        self.current.as_ref()
+    }
+
+    pub fn upcoming(&self) -> &[Track] {
+        &self.upcoming
+    }
+
+    pub fn history(&self) -> &[Track] {
+        &self.history
+    }
+
+    /// The last queued track, more tracks are added after it
+    pub fn last(&self) -> Option<&Track> {
+        self.upcoming.last().or(self.current.as_ref())
+    }
+
+    /// Whether `next` has a track to switch to
+    pub fn has_next(&self) -> bool {
+        !self.upcoming.is_empty() || (self.repeat == Repeat::All && !self.list.is_empty())
+    }
+
+    /// Switch to the next track, the list starts again after the last one if it is repeated.
+    /// Otherwise there is no current track after the last one
+    pub fn next(&mut self) -> Option<&Track> {
+        if let Some(current) = self.current.take() {
+            self.history.push(current);
+        }
+        self.refill();
+        if !self.upcoming.is_empty() {
+            self.current = Some(self.upcoming.remove(0));
+        }
+        self.refill();
+        self.current.as_ref()
+    }
+
+    /// The track `advance` switches to, it is preloaded to play it without a gap
+    pub fn peek_next(&self) -> Option<&Track> {
+        match self.repeat {
+            Repeat::One => self.current.as_ref(),
+            _ => self.upcoming.first(),
+        }
+    }
+
+    /// Switch to the track played after the current one is over
+    pub fn advance(&mut self) -> Option<&Track> {
+        if self.repeat == Repeat::One && self.current.is_some() {
+            return self.current.as_ref();
+        }
+        self.next()
+    }
+
+    /// Switch back to the last played track, false if nothing was played before
+    pub fn previous(&mut self) -> bool {
+        match self.history.pop() {
+            Some(track) => {
+                if let Some(current) = self.current.replace(track) {
+                    self.upcoming.insert(0, current);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switch to the upcoming track at the index, the tracks before it are skipped
+    pub fn skip_to(&mut self, index: usize) -> Option<&Track> {
+        if index >= self.upcoming.len() {
+            return None;
+        }
+        let skipped: Vec<Track> = self.upcoming.drain(..index).collect();
+        if let Some(current) = self.current.take() {
+            self.history.push(current);
+        }
+        self.history.extend(skipped);
+        self.next()
+    }
+
+    /// Play the track right after the current one
+    pub fn play_next(&mut self, track: Track) {
+        self.upcoming.insert(0, track);
+    }
+
+    /// Play the tracks after all queued ones
+    pub fn add(&mut self, tracks: impl IntoIterator<Item = Track>) {
+        let start = self.upcoming.len();
+        self.upcoming.extend(tracks);
+        self.list.extend_from_slice(&self.upcoming[start..]);
+    }
+
+    /// Remove the upcoming track, it is not played again when the list is repeated
+    pub fn remove(&mut self, index: usize) -> Option<Track> {
+        if index >= self.upcoming.len() {
+            return None;
+        }
+        let track = self.upcoming.remove(index);
+        self.list.retain(|listed| listed.id != track.id);
+        Some(track)
+    }
+
+    /// Swap the upcoming track with the previous one, false if it is the first
+    pub fn move_up(&mut self, index: usize) -> bool {
+        if index == 0 || index >= self.upcoming.len() {
+            return false;
+        }
+        self.upcoming.swap(index - 1, index);
+        true
+    }
+
+    /// Swap the upcoming track with the next one, false if it is the last
+    pub fn move_down(&mut self, index: usize) -> bool {
+        if index + 1 >= self.upcoming.len() {
+            return false;
+        }
+        self.upcoming.swap(index, index + 1);
+        true
+    }
+
+    /// Forget the upcoming tracks, the current one is still played
+    pub fn clear(&mut self) {
+        self.upcoming.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yandex_rust_music::testing;
+
+    fn tracks(titles: &[&str]) -> Vec<Track> {
+        titles.iter().map(|title| testing::track(title)).collect()
+    }
+
+    fn sorted(tracks: &[Track]) -> Vec<&str> {
+        let mut titles = titles(tracks);
+        titles.sort_unstable();
+        titles
+    }
+
+    fn titles(tracks: &[Track]) -> Vec<&str> {
+        tracks.iter().map(|track| track.title.as_str()).collect()
+    }
+
+    #[test]
+    fn queue_plays_tracks_after_the_chosen_one() {
+        let mut queue = Queue::default();
+        queue.play_from(tracks(&["First", "Second", "Third"]), 1);
+
+        assert_eq!(queue.current().unwrap().title(), "Second");
+        assert_eq!(titles(queue.upcoming()), ["Third"]);
+        assert_eq!(queue.next().unwrap().title(), "Third");
+        assert!(queue.next().is_none());
+        assert_eq!(titles(queue.history()), ["Second", "Third"]);
+    }
+
+    #[test]
+    fn queue_can_go_back_to_played_tracks() {
+        let mut queue = Queue::default();
+        queue.play_from(tracks(&["First", "Second"]), 0);
+        assert!(!queue.previous());
+
+        queue.next();
+        assert!(queue.previous());
+        assert_eq!(queue.current().unwrap().title(), "First");
+        assert_eq!(titles(queue.upcoming()), ["Second"]);
+    }
+
+    #[test]
+    fn queue_can_be_edited() {
+        let mut queue = Queue::default();
+        queue.play_from(tracks(&["First", "Second"]), 0);
+        queue.add(tracks(&["Third", "Fourth"]));
+        queue.play_next(tracks(&["Next"]).remove(0));
+        assert_eq!(
+            titles(queue.upcoming()),
+            ["Next", "Second", "Third", "Fourth"]
+        );
+
+        assert!(queue.move_down(0));
+        assert!(!queue.move_down(3));
+        assert!(queue.move_up(3));
+        assert!(!queue.move_up(0));
+        assert_eq!(
+            titles(queue.upcoming()),
+            ["Second", "Next", "Fourth", "Third"]
+        );
+
+        assert_eq!(queue.remove(1).unwrap().title(), "Next");
+        assert!(queue.remove(3).is_none());
+        assert_eq!(queue.last().unwrap().title(), "Third");
+
+        assert_eq!(queue.skip_to(1).unwrap().title(), "Fourth");
+        assert_eq!(titles(queue.history()), ["First", "Second"]);
+
+        queue.clear();
+        assert!(queue.upcoming().is_empty());
+        assert_eq!(queue.current().unwrap().title(), "Fourth");
+    }
+
+    #[test]
+    fn queue_plays_every_shuffled_track_once_per_round() {
+        let list = tracks(&["1", "2", "3", "4", "5", "6", "7", "8"]);
+        let mut queue = Queue::default();
+        queue.set_shuffle(true);
+        queue.set_repeat(Repeat::All);
+        queue.play_from(list, 2);
+        assert_eq!(queue.current().unwrap().title(), "3");
+        assert_eq!(
+            sorted(queue.upcoming()),
+            ["1", "2", "4", "5", "6", "7", "8"]
+        );
+
+        for _ in 0..16 {
+            let next = queue.peek_next().unwrap().clone();
+            assert_eq!(queue.next().unwrap().id, next.id);
+        }
+        let played = queue.history();
+        assert_eq!(
+            sorted(&played[..8]),
+            ["1", "2", "3", "4", "5", "6", "7", "8"]
+        );
+        assert_eq!(
+            sorted(&played[8..]),
+            ["1", "2", "3", "4", "5", "6", "7", "8"]
+        );
+    }
+
+    #[test]
+    fn queue_does_not_repeat_track_between_shuffled_rounds() {
+        let mut queue = Queue::default();
+        queue.set_shuffle(true);
+        queue.set_repeat(Repeat::All);
+        queue.play_from(tracks(&["1", "2"]), 0);
+        for _ in 0..32 {
+            queue.next();
+        }
+        let played = titles(queue.history());
+        assert!(played.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn queue_does_not_repeat_removed_tracks() {
+        let mut queue = Queue::default();
+        queue.set_repeat(Repeat::All);
+        queue.play_from(tracks(&["First", "Second", "Third"]), 0);
+        assert_eq!(queue.remove(0).unwrap().title(), "Second");
+
+        assert_eq!(queue.next().unwrap().title(), "Third");
+        assert_eq!(titles(queue.upcoming()), ["First", "Third"]);
+    }
+
+    #[test]
+    fn queue_keeps_list_order_when_shuffle_is_off() {
+        let mut queue = Queue::default();
+        queue.play_from(tracks(&["1", "2", "3", "4", "5"]), 0);
+        queue.set_shuffle(true);
+        queue.set_shuffle(false);
+        assert_eq!(titles(queue.upcoming()), ["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn queue_repeats_current_track_when_it_is_over() {
+        let mut queue = Queue::default();
+        queue.play_from(tracks(&["First", "Second"]), 0);
+        queue.set_repeat(Repeat::One);
+        assert_eq!(queue.peek_next().unwrap().title(), "First");
+        assert_eq!(queue.advance().unwrap().title(), "First");
+        assert_eq!(queue.next().unwrap().title(), "Second");
+        assert_eq!(queue.advance().unwrap().title(), "Second");
+
+        queue.set_repeat(Repeat::Off);
+        assert!(!queue.has_next());
+        assert!(queue.advance().is_none());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::app::DisplayedList;
+use crate::catalog::{CatalogItem, CatalogTab};
+
+use yandex_rust_music::{SearchResult, Track};
+
+/// Query and results of the catalog search
+#[derive(Default)]
+pub struct SearchView {
+    pub query: String,
+    pub tab: CatalogTab,
+    pub page: u32,
+    pub total: u32,
+    pub per_page: u32,
+    pub results: DisplayedList<CatalogItem>,
+}
+
+impl SearchView {
+    pub fn set_result(&mut self, result: SearchResult) {
+        let (total, per_page, items): (u32, u32, Vec<CatalogItem>) = match self.tab {
+            CatalogTab::Tracks => (
+                result.tracks.total,
+                result.tracks.per_page,
+                result
+                    .tracks
+                    .results
+                    .into_iter()
+                    .map(|track| CatalogItem::Track(Box::new(track)))
+                    .collect(),
+            ),
+            CatalogTab::Albums => (
+                result.albums.total,
+                result.albums.per_page,
+                result
+                    .albums
+                    .results
+                    .into_iter()
+                    .map(CatalogItem::Album)
+                    .collect(),
+            ),
+            CatalogTab::Artists => (
+                result.artists.total,
+                result.artists.per_page,
+                result
+                    .artists
+                    .results
+                    .into_iter()
+                    .map(CatalogItem::Artist)
+                    .collect(),
+            ),
+            CatalogTab::Playlists => (
+                result.playlists.total,
+                result.playlists.per_page,
+                result
+                    .playlists
+                    .results
+                    .into_iter()
+                    .map(CatalogItem::Playlist)
+                    .collect(),
+            ),
+        };
+        self.page = result.page;
+        self.total = total;
+        self.per_page = per_page;
+        self.results.set_items(items);
+        self.results.next();
+    }
+
+    pub fn has_next_page(&self) -> bool {
+        (self.page + 1) * self.per_page < self.total
+    }
+
+    pub fn page_count(&self) -> u32 {
+        if self.per_page == 0 {
+            0
+        } else {
+            self.total.div_ceil(self.per_page)
+        }
+    }
+
+    /// Tracks found on the current page
+    pub fn tracks(&self) -> Vec<Track> {
+        self.results
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                CatalogItem::Track(track) => Some(track.as_ref().clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -1,7 +1,15 @@
 use crate::actions::Actions;
 use crate::app::App;
-use crate::app::AppState;
+use crate::app::DisplayedList;
+use crate::app::DisplayedSources;
 use crate::app::DisplayedTracks;
+use crate::app::Focus;
+use crate::app::Operation;
+use crate::catalog::{CatalogItem, CatalogTab};
+use crate::io::download::Progress;
+use crate::library::LibraryView;
+use crate::queue::Queue;
+use crate::search::SearchView;
 
 use tui::backend::Backend;
 use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
@@ -9,14 +17,19 @@ use tui::style::{Color, Modifier, Style};
 use tui::symbols::line;
 use tui::text::{Span, Spans};
 use tui::widgets::{
-    Block, BorderType, Borders, Cell, LineGauge, List, ListItem, Paragraph, Row, Table,
+    Block, BorderType, Borders, Cell, Clear, LineGauge, List, ListItem, Paragraph, Row, Table, Tabs,
 };
 use tui::Frame;
 
 use std::time::Duration;
 
+use yandex_rust_music::{Normalization, Playlist, Track};
+
 use tui_logger::TuiLoggerWidget;
 
+/// Width of the progress bars of downloads
+const PROGRESS_BAR_WIDTH: usize = 20;
+
 pub fn draw<B>(rect: &mut Frame<B>, app: &mut App)
 where
     B: Backend,
@@ -42,41 +55,122 @@ where
     let title = draw_title();
     rect.render_widget(title, chunks[0]);
 
-    // Body (player and state) and Help
+    // Body (sources, player and state) and Help
     let body_chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Min(20), Constraint::Length(32)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(24),
+                Constraint::Min(20),
+                Constraint::Length(32),
+            ]
+            .as_ref(),
+        )
         .split(chunks[1]);
 
+    let sources = draw_sources(&app.sources, app.focus() == Focus::Sources);
+    rect.render_stateful_widget(sources, body_chunks[0], &mut app.sources.state);
+
     let player_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(3), Constraint::Length(5)].as_ref())
-        .split(body_chunks[0]);
+        .split(body_chunks[1]);
 
-    let playlist = draw_tracks(app.displayed_tracks.clone());
-    rect.render_stateful_widget(playlist, player_chunks[0], &mut app.displayed_tracks.state);
+    if app.is_searching() {
+        let search_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Length(3),
+                    Constraint::Min(3),
+                ]
+                .as_ref(),
+            )
+            .split(player_chunks[0]);
 
-    let state = draw_body(app.is_loading(), app.state());
+        let input = draw_search_input(&app.search.query, app.focus() == Focus::SearchInput);
+        rect.render_widget(input, search_chunks[0]);
+
+        let tabs = draw_catalog_tabs(app.search.tab);
+        rect.render_widget(tabs, search_chunks[1]);
+
+        let results = draw_search_results(&app.search, app.focus() == Focus::SearchResults);
+        rect.render_stateful_widget(results, search_chunks[2], &mut app.search.results.state);
+    } else if app.focus() == Focus::Library {
+        let library_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(3)].as_ref())
+            .split(player_chunks[0]);
+
+        let tabs = draw_catalog_tabs(app.library.tab);
+        rect.render_widget(tabs, library_chunks[0]);
+
+        let items = draw_library(&app.library);
+        rect.render_stateful_widget(items, library_chunks[1], &mut app.library.items.state);
+    } else if app.focus() == Focus::Queue {
+        let queue = draw_queue(app, &app.queue);
+        rect.render_stateful_widget(queue, player_chunks[0], &mut app.queue_state);
+    } else {
+        let playlist = draw_tracks(
+            app,
+            &app.displayed_tracks,
+            &app.tracks_title,
+            app.focus() == Focus::Tracks,
+        );
+        rect.render_stateful_widget(playlist, player_chunks[0], &mut app.displayed_tracks.state);
+    }
+
+    let state = draw_body(app);
     rect.render_widget(state, player_chunks[1]);
 
+    if app.focus() == Focus::PlaylistPicker {
+        let area = centered_rect(body_chunks[1], 60, 50);
+        let picker = draw_playlist_picker(&app.playlist_picker);
+        rect.render_widget(Clear, area);
+        rect.render_stateful_widget(picker, area, &mut app.playlist_picker.state);
+    }
+
+    // The downloads panel is shown under the help while there is something in flight
+    let (operations, operation_lines) = draw_operations(app);
+    let help_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(operation_lines)].as_ref())
+        .split(body_chunks[2]);
+
     let help = draw_help(app.actions());
-    rect.render_widget(help, body_chunks[1]);
+    rect.render_widget(help, help_chunks[0]);
+    if operation_lines > 0 {
+        rect.render_widget(operations, help_chunks[1]);
+    }
+
+    // Duration and volume
+    let player_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(20), Constraint::Length(18)].as_ref())
+        .split(chunks[2]);
 
-    // Duration
     if let Some(duration) = app.state().duration() {
         let total_duration = app.state().total_duration().unwrap();
-        let duration_block = draw_duration(duration, total_duration);
-        rect.render_widget(duration_block, chunks[2]);
+        let title = match app.playing() {
+            Some(track) => now_playing(track, app.is_liked(track)),
+            None => String::from("Nothing is playing"),
+        };
+        let duration_block = draw_duration(&title, duration, total_duration);
+        rect.render_widget(duration_block, player_chunks[0]);
     }
 
+    let volume = draw_volume(app.volume(), app.is_muted());
+    rect.render_widget(volume, player_chunks[1]);
+
     // Logs
     let logs = draw_logs();
     rect.render_widget(logs, chunks[3]);
 }
 
 fn check_size(rect: &Rect) {
-    if rect.width < 52 {
-        panic!("Require width >= 52, (got {})", rect.width);
+    if rect.width < 76 {
+        panic!("Require width >= 76, (got {})", rect.width);
     }
 
     if rect.height < 28 {
@@ -95,17 +189,39 @@ fn draw_title<'a>() -> Paragraph<'a> {
         )
 }
 
-fn draw_body<'a>(loading: bool, state: &AppState) -> Paragraph<'a> {
-    let initialized_text = if state.is_initialized() {
+fn draw_body<'a>(app: &App) -> Paragraph<'a> {
+    let initialized_text = if app.state().is_initialized() {
         "Initialized"
     } else {
         "Not Initialized"
     };
-    let loading_text = if loading { "Loading..." } else { "" };
+    let loading_text = match (app.downloads().count(), app.in_flight().len()) {
+        (0, 0) => String::new(),
+        (0, _) => String::from("Loading..."),
+        (1, _) => String::from("Downloading 1 track..."),
+        (downloads, _) => format!("Downloading {} tracks...", downloads),
+    };
+    let shuffle = if app.queue.is_shuffled() { "on" } else { "off" };
+    let crossfade = match app.crossfade().as_secs() {
+        0 => "off".to_string(),
+        secs => format!("{}s", secs),
+    };
+    let normalization = match app.normalization() {
+        Normalization::Off => Normalization::Off.to_string(),
+        normalization => format!("{} {:+}dB", normalization, app.pre_amp()),
+    };
+    let modes_text = format!(
+        "Shuffle: {}  Repeat: {}  Crossfade: {}  Normalization: {}",
+        shuffle,
+        app.queue.repeat(),
+        crossfade,
+        normalization
+    );
 
     Paragraph::new(vec![
         Spans::from(Span::raw(initialized_text)),
         Spans::from(Span::raw(loading_text)),
+        Spans::from(Span::raw(modes_text)),
     ])
     .style(Style::default().fg(Color::LightCyan))
     .alignment(Alignment::Left)
@@ -117,20 +233,189 @@ fn draw_body<'a>(loading: bool, state: &AppState) -> Paragraph<'a> {
     )
 }
 
-fn draw_tracks(playlist: DisplayedTracks) -> List<'static> {
+fn draw_sources(sources: &DisplayedSources, focused: bool) -> List<'static> {
+    let items: Vec<ListItem> = sources
+        .items
+        .iter()
+        .map(|i| ListItem::new(vec![Spans::from(i.title().to_string())]))
+        .collect();
+    List::new(items)
+        .block(list_block("Sources", focused))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+/// "Artist — Title  [m:ss]", unavailable tracks are greyed out and the downloaded ones
+/// show how far they are
+fn track_item(track: &Track, download: Option<&Progress>) -> ListItem<'static> {
+    let duration = track.total_duration().unwrap_or_default();
+    let mut spans = vec![
+        Span::styled(track.artist_names(), Style::default().fg(Color::LightCyan)),
+        Span::raw(" — "),
+        Span::raw(track.title()),
+        Span::styled(
+            format!("  [{}]", format_duration(&duration)),
+            Style::default().fg(Color::Gray),
+        ),
+    ];
+    if let Some(progress) = download {
+        let indicator = match progress.fraction() {
+            Some(fraction) => format!("  ⇣ {:.0}%", fraction * 100.0),
+            None => String::from("  ⇣"),
+        };
+        spans.push(Span::styled(indicator, Style::default().fg(Color::Yellow)));
+    }
+    if track.artists.is_empty() {
+        spans.drain(..2);
+    }
+    let style = if track.available {
+        Style::default()
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+    ListItem::new(vec![Spans::from(spans)]).style(style)
+}
+
+fn draw_tracks(app: &App, playlist: &DisplayedTracks, title: &str, focused: bool) -> List<'static> {
     let tracks: Vec<ListItem> = playlist
-        .tracks
+        .items
         .iter()
-        .map(|i| ListItem::new(vec![Spans::from(i.title())]))
+        .map(|track| track_item(track, app.download_of(track)))
         .collect();
     let list = List::new(tracks)
-        .block(Block::default().borders(Borders::ALL).title("List"))
+        .block(list_block(title, focused))
         .highlight_style(Style::default().add_modifier(Modifier::BOLD))
         .highlight_symbol("> ");
     list
 }
 
-fn draw_help(actions: &Actions) -> Table {
+fn draw_queue(app: &App, queue: &Queue) -> List<'static> {
+    let title = match queue.current() {
+        Some(track) => format!("Up next after {}", track.title()),
+        None => String::from("Up next"),
+    };
+    let tracks: Vec<ListItem> = queue
+        .upcoming()
+        .iter()
+        .map(|track| track_item(track, app.download_of(track)))
+        .collect();
+    List::new(tracks)
+        .block(list_block(&title, true))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+fn draw_search_input(query: &str, focused: bool) -> Paragraph<'static> {
+    let text = if focused {
+        format!("{}_", query)
+    } else {
+        query.to_string()
+    };
+    Paragraph::new(text).block(list_block("Search", focused))
+}
+
+fn draw_catalog_tabs(selected: CatalogTab) -> Tabs<'static> {
+    let titles = CatalogTab::all()
+        .iter()
+        .map(|tab| Spans::from(tab.to_string()))
+        .collect();
+    Tabs::new(titles)
+        .block(Block::default().borders(Borders::ALL))
+        .highlight_style(
+            Style::default()
+                .fg(Color::LightCyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .select(selected.index())
+}
+
+fn draw_search_results(search: &SearchView, focused: bool) -> List<'static> {
+    let title = format!(
+        "Results {} (page {}/{})",
+        search.total,
+        search.page + 1,
+        search.page_count().max(1)
+    );
+    draw_catalog_items(&search.results.items, &title, focused)
+}
+
+fn draw_library(library: &LibraryView) -> List<'static> {
+    let title = format!(
+        "Library {} (page {}/{})",
+        library.total,
+        library.page + 1,
+        library.page_count().max(1)
+    );
+    draw_catalog_items(&library.items.items, &title, true)
+}
+
+fn draw_catalog_items(items: &[CatalogItem], title: &str, focused: bool) -> List<'static> {
+    let items: Vec<ListItem> = items
+        .iter()
+        .map(|i| ListItem::new(vec![Spans::from(i.to_string())]))
+        .collect();
+    List::new(items)
+        .block(list_block(title, focused))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+fn draw_playlist_picker(playlists: &DisplayedList<Playlist>) -> List<'static> {
+    let items: Vec<ListItem> = playlists
+        .items
+        .iter()
+        .map(|p| {
+            ListItem::new(vec![Spans::from(format!(
+                "{} [{}]",
+                p.title, p.track_count
+            ))])
+        })
+        .collect();
+    List::new(items)
+        .block(list_block("Add to playlist", true))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+/// Part of the area in its center, sizes are in percents
+fn centered_rect(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(vertical[1])[1]
+}
+
+fn list_block(title: &str, focused: bool) -> Block<'static> {
+    let border_style = if focused {
+        Style::default().fg(Color::LightCyan)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .title(title.to_string())
+}
+
+fn draw_help(actions: &Actions) -> Table<'_> {
     let key_style = Style::default().fg(Color::LightCyan);
     let help_style = Style::default().fg(Color::Gray);
 
@@ -179,20 +464,140 @@ fn draw_logs<'a>() -> TuiLoggerWidget<'a> {
         .style(Style::default().fg(Color::White).bg(Color::Black))
 }
 
-fn draw_duration<'a>(duration: &Duration, total_duration: &Duration) -> LineGauge<'a> {
+/// Panel of the operations in flight with the progress of downloads, and its height
+fn draw_operations(app: &App) -> (List<'static>, u16) {
+    let label_style = Style::default().fg(Color::Gray);
+    let items: Vec<ListItem> = app
+        .in_flight()
+        .iter()
+        .map(|operation| match operation {
+            Operation::Initialize => ListItem::new("Starting...").style(label_style),
+            Operation::SwitchSong => ListItem::new("Switching the song...").style(label_style),
+            Operation::PreloadSong => {
+                ListItem::new("Preloading the next song...").style(label_style)
+            }
+            Operation::TopUpRadio => {
+                ListItem::new("Queueing more of the radio...").style(label_style)
+            }
+            Operation::MakeLikedOffline => {
+                ListItem::new("Fetching the liked tracks...").style(label_style)
+            }
+            Operation::Download(id) => match app.download(*id) {
+                Some(download) => download_item(&download.track, &download.progress),
+                None => ListItem::new("Downloading...").style(label_style),
+            },
+        })
+        .collect();
+    let lines: usize = items.iter().map(ListItem::height).sum();
+    let height = if lines == 0 {
+        0
+    } else {
+        lines.min(12) as u16 + 2
+    };
+    let title = match app.offline_queued() {
+        0 => String::from("Downloads"),
+        queued => format!("Downloads, {} more for offline", queued),
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain)
+            .title(title),
+    );
+    (list, height)
+}
+
+/// Title, progress bar and "received / total  rate  ETA"
+fn download_item(track: &Track, progress: &Progress) -> ListItem<'static> {
+    let fraction = progress.fraction().unwrap_or_default();
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let bar = format!(
+        "{}{} {:>3.0}%",
+        "█".repeat(filled),
+        "░".repeat(PROGRESS_BAR_WIDTH - filled),
+        fraction * 100.0
+    );
+    let total = match progress.total {
+        Some(total) => format_size(total),
+        None => String::from("?"),
+    };
+    let mut stats = format!(
+        "{} / {}  {}/s",
+        format_size(progress.downloaded),
+        total,
+        format_size(progress.rate)
+    );
+    if let Some(eta) = progress.eta() {
+        stats.push_str(&format!("  {}", format_duration(&eta)));
+    }
+    ListItem::new(vec![
+        Spans::from(Span::raw(track.title())),
+        Spans::from(Span::styled(bar, Style::default().fg(Color::Yellow))),
+        Spans::from(Span::styled(stats, Style::default().fg(Color::Gray))),
+    ])
+}
+
+/// Size in KB under a megabyte, in MB otherwise
+fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    let bytes = bytes as f64;
+    if bytes < KB * KB {
+        format!("{:.0}KB", bytes / KB)
+    } else {
+        format!("{:.1}MB", bytes / KB / KB)
+    }
+}
+
+/// Duration as m:ss
+fn format_duration(duration: &Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Title of the playing track with a heart if it is liked
+fn now_playing(track: &Track, liked: bool) -> String {
+    let heart = if liked { "♥" } else { "♡" };
+    if track.artists.is_empty() {
+        format!("{} {}", heart, track.title())
+    } else {
+        format!("{} {} — {}", heart, track.artist_names(), track.title())
+    }
+}
+
+fn draw_volume<'a>(volume: f32, muted: bool) -> LineGauge<'a> {
+    let (label, color) = if muted {
+        (String::from("mute"), Color::DarkGray)
+    } else {
+        (format!("{:>3}%", (volume * 100.0).round()), Color::Cyan)
+    };
+
+    LineGauge::default()
+        .block(Block::default().borders(Borders::ALL).title("Volume"))
+        .gauge_style(Style::default().fg(color).bg(Color::Black))
+        .line_set(line::THICK)
+        .label(label)
+        .ratio(volume.clamp(0.0, 1.0) as f64)
+}
+
+fn draw_duration<'a>(title: &str, duration: &Duration, total_duration: &Duration) -> LineGauge<'a> {
     let min = duration.as_secs() / 60;
     let sec = duration.as_secs() % 60;
     let label = format!("{}:{}", min, sec);
 
     let ms = duration.as_millis() as f64;
     let total_ms = total_duration.as_millis() as f64;
-    let ratio = ms / total_ms;
+    // The gauge only takes ratios within 0..=1
+    let ratio = if total_ms > 0.0 {
+        (ms / total_ms).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
 
     LineGauge::default()
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Sleep duration"),
+                .title(title.to_string()),
         )
         .gauge_style(
             Style::default()
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::artist::Artist;
+use crate::client::string_or_number;
+use crate::track::Track;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Album {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub title: String,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub track_count: u32,
+    #[serde(default)]
+    pub artists: Vec<Artist>,
+    pub cover_uri: Option<String>,
+}
+
+/// Album with its tracks, one list per volume (disc)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AlbumWithTracks {
+    pub album: Album,
+    pub volumes: Vec<Vec<Track>>,
+}
+
+impl AlbumWithTracks {
+    /// Tracks of all volumes in the order of the album
+    pub fn tracks(&self) -> Vec<Track> {
+        self.volumes.iter().flatten().cloned().collect()
+    }
+}
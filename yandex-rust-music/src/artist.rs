@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+use crate::album::Album;
+use crate::client::string_or_number;
+use crate::track::Track;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artist {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub name: String,
+}
+
+/// Brief information about the artist shown on the artist page
+#[derive(Clone)]
+pub struct ArtistInfo {
+    pub artist: Artist,
+    pub popular_tracks: Vec<Track>,
+    /// First albums of the discography, see `Client::artist_albums` for the rest
+    pub albums: Vec<Album>,
+}
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use crate::album::{Album, AlbumWithTracks};
+use crate::artist::{Artist, ArtistInfo};
+use crate::client::Client;
+use crate::error::Result;
+use crate::page::Page;
+use crate::playlist::{GeneratedPlaylist, Playlist, PlaylistWithTracks};
+use crate::search::{SearchKind, SearchResult};
+use crate::station::{Feedback, Station, StationId, StationTracks};
+use crate::stream::Media;
+use crate::track::Track;
+
+/// Fetches the audio of tracks, it is called on other threads than the backend
+pub type Fetcher = Arc<dyn Fn(&Track) -> Result<Media> + Send + Sync>;
+
+/// What the player application needs from the music service
+pub trait MusicBackend: Send + Sync {
+    fn generated_playlists(&self) -> Result<Vec<GeneratedPlaylist>>;
+
+    fn generated_playlist_tracks(&self, playlist: &GeneratedPlaylist) -> Result<Vec<Track>>;
+
+    fn search(&self, query: &str, kind: SearchKind, page: u32) -> Result<SearchResult>;
+
+    fn album(&self, album_id: &str) -> Result<AlbumWithTracks>;
+
+    fn artist(&self, artist_id: &str) -> Result<ArtistInfo>;
+
+    fn playlist(&self, owner_uid: u64, kind: u64) -> Result<PlaylistWithTracks>;
+
+    /// Append tracks to the playlist of the user
+    fn add_to_playlist(&self, kind: u64, tracks: &[Track]) -> Result<Playlist>;
+
+    fn user_playlists(&self) -> Result<Vec<Playlist>>;
+
+    fn liked_tracks(&self, page: u32) -> Result<Page<Track>>;
+
+    fn liked_track_ids(&self) -> Result<Vec<String>>;
+
+    /// Tracks of the ids, for the ones not listed with their tracks
+    fn tracks(&self, track_ids: &[String]) -> Result<Vec<Track>>;
+
+    fn like_track(&self, track: &Track) -> Result<()>;
+
+    fn unlike_track(&self, track: &Track) -> Result<()>;
+
+    fn dislike_track(&self, track: &Track) -> Result<()>;
+
+    fn undislike_track(&self, track: &Track) -> Result<()>;
+
+    fn liked_albums(&self) -> Result<Vec<Album>>;
+
+    fn liked_artists(&self) -> Result<Vec<Artist>>;
+
+    fn stations(&self) -> Result<Vec<Station>>;
+
+    fn station_tracks(
+        &self,
+        station: &StationId,
+        last_track_id: Option<&str>,
+    ) -> Result<StationTracks>;
+
+    fn station_feedback(
+        &self,
+        station: &Station,
+        batch_id: Option<&str>,
+        feedback: &Feedback,
+    ) -> Result<()>;
+
+    /// Fetch the audio of the track and return the path to the local file
+    fn download(&self, track: &Track) -> Result<String>;
+
+    /// Audio of the track that can be played while it is downloaded
+    fn stream(&self, track: &Track) -> Result<Media> {
+        self.download(track).map(Media::File)
+    }
+
+    /// File of the track that is already on disk, it is played without a download
+    fn local_file(&self, _track: &Track) -> Option<String> {
+        None
+    }
+
+    /// Stream the tracks apart from the backend, so that downloads do not hold it
+    fn fetcher(&self) -> Fetcher;
+}
+
+impl MusicBackend for Client {
+    fn generated_playlists(&self) -> Result<Vec<GeneratedPlaylist>> {
+        Client::generated_playlists(self)
+    }
+
+    fn generated_playlist_tracks(&self, playlist: &GeneratedPlaylist) -> Result<Vec<Track>> {
+        Client::generated_playlist_tracks(self, playlist)
+    }
+
+    fn search(&self, query: &str, kind: SearchKind, page: u32) -> Result<SearchResult> {
+        Client::search(self, query, kind, page)
+    }
+
+    fn album(&self, album_id: &str) -> Result<AlbumWithTracks> {
+        Client::album(self, album_id)
+    }
+
+    fn artist(&self, artist_id: &str) -> Result<ArtistInfo> {
+        Client::artist(self, artist_id)
+    }
+
+    fn playlist(&self, owner_uid: u64, kind: u64) -> Result<PlaylistWithTracks> {
+        Client::playlist(self, owner_uid, kind)
+    }
+
+    fn add_to_playlist(&self, kind: u64, tracks: &[Track]) -> Result<Playlist> {
+        Client::add_tracks(self, kind, tracks)
+    }
+
+    fn user_playlists(&self) -> Result<Vec<Playlist>> {
+        Client::user_playlists(self)
+    }
+
+    fn liked_tracks(&self, page: u32) -> Result<Page<Track>> {
+        Client::liked_tracks(self, page)
+    }
+
+    fn liked_track_ids(&self) -> Result<Vec<String>> {
+        Client::liked_track_ids(self)
+    }
+
+    fn tracks(&self, track_ids: &[String]) -> Result<Vec<Track>> {
+        Client::tracks(self, track_ids)
+    }
+
+    fn like_track(&self, track: &Track) -> Result<()> {
+        Client::like_track(self, track.id())
+    }
+
+    fn unlike_track(&self, track: &Track) -> Result<()> {
+        Client::unlike_track(self, track.id())
+    }
+
+    fn dislike_track(&self, track: &Track) -> Result<()> {
+        Client::dislike_track(self, track.id())
+    }
+
+    fn undislike_track(&self, track: &Track) -> Result<()> {
+        Client::undislike_track(self, track.id())
+    }
+
+    fn liked_albums(&self) -> Result<Vec<Album>> {
+        Client::liked_albums(self)
+    }
+
+    fn liked_artists(&self) -> Result<Vec<Artist>> {
+        Client::liked_artists(self)
+    }
+
+    fn stations(&self) -> Result<Vec<Station>> {
+        Client::stations(self)
+    }
+
+    fn station_tracks(
+        &self,
+        station: &StationId,
+        last_track_id: Option<&str>,
+    ) -> Result<StationTracks> {
+        Client::station_tracks(self, station, last_track_id)
+    }
+
+    fn station_feedback(
+        &self,
+        station: &Station,
+        batch_id: Option<&str>,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        Client::station_feedback(self, station, batch_id, feedback)
+    }
+
+    fn download(&self, track: &Track) -> Result<String> {
+        track.download()
+    }
+
+    fn stream(&self, track: &Track) -> Result<Media> {
+        track.stream()
+    }
+
+    fn local_file(&self, track: &Track) -> Option<String> {
+        track.cached_file()
+    }
+
+    fn fetcher(&self) -> Fetcher {
+        // Tracks carry the client they are downloaded with
+        Arc::new(|track: &Track| track.stream())
+    }
+}
@@ -0,0 +1,437 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::track::{Quality, QualityPreference};
+
+const INDEX_FILE: &str = "index.json";
+/// Files being downloaded end with it until they are complete
+pub(crate) const PARTIAL_SUFFIX: &str = ".part";
+/// Files the index does not know are removed once they are not written this long,
+/// the younger ones may be downloaded by another process
+const STALE_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    track_id: String,
+    quality: Quality,
+    file: String,
+    size: u64,
+    // Value of the clock when the file was used last
+    used: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    // Counts the uses of files, the least recently used one has the lowest value
+    clock: u64,
+    entries: HashMap<String, Entry>,
+    // Tracks whose files are not evicted
+    #[serde(default)]
+    pinned: HashSet<String>,
+}
+
+/// Space taken by the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub files: usize,
+    pub size: u64,
+    pub max_size: u64,
+}
+
+/// Directory of downloaded tracks keyed by the track id and quality. The least recently
+/// used files are removed once they take more than the size limit
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    index: Index,
+    // Files being played, they are opened again to seek
+    in_use: HashSet<PathBuf>,
+}
+
+impl Cache {
+    /// Open the directory, stale partial files and files that do not match the index
+    /// are removed
+    pub fn open(dir: impl Into<PathBuf>, max_size: u64) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut cache = Self::load(dir, max_size)?;
+        cache.verify()?;
+        cache.evict(None)?;
+        Ok(cache)
+    }
+
+    /// Space taken by the cache in the directory, nothing is changed there
+    pub fn usage_of(dir: impl Into<PathBuf>, max_size: u64) -> Result<Usage> {
+        Ok(Self::load(dir.into(), max_size)?.usage())
+    }
+
+    fn load(dir: PathBuf, max_size: u64) -> Result<Self> {
+        let index = match fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Index::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            dir,
+            max_size,
+            index,
+            in_use: HashSet::new(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn key(track_id: &str, quality: &Quality) -> String {
+        format!("{}-{}", track_id, quality)
+    }
+
+    /// Where the file of the track is downloaded
+    pub fn path(&self, track_id: &str, quality: &Quality) -> PathBuf {
+        self.dir.join(format!(
+            "{}.{}",
+            Self::key(track_id, quality),
+            quality.extension()
+        ))
+    }
+
+    /// Complete file of the track, it becomes the most recently used one
+    pub fn get(&mut self, track_id: &str, quality: &Quality) -> Option<PathBuf> {
+        let key = Self::key(track_id, quality);
+        let entry = self.index.entries.get(&key)?;
+        let path = self.dir.join(&entry.file);
+        if !Self::is_intact(&path, entry.size) {
+            self.index.entries.remove(&key);
+            let _ = fs::remove_file(&path);
+            let _ = self.save();
+            return None;
+        }
+        self.index.clock += 1;
+        let clock = self.index.clock;
+        if let Some(entry) = self.index.entries.get_mut(&key) {
+            entry.used = clock;
+        }
+        // The order of use is only lost if the index cannot be saved
+        let _ = self.save();
+        Some(path)
+    }
+
+    /// Complete file of the track in the quality the preference chooses of the cached ones
+    pub fn find(&mut self, track_id: &str, preference: &QualityPreference) -> Option<PathBuf> {
+        let cached: Vec<Quality> = self
+            .index
+            .entries
+            .values()
+            .filter(|entry| entry.track_id == track_id)
+            .map(|entry| entry.quality.clone())
+            .collect();
+        let quality = preference.choose(&cached)?.clone();
+        self.get(track_id, &quality)
+    }
+
+    /// Add the file completely downloaded to `path`, older files are removed if it is full
+    pub fn insert(&mut self, track_id: &str, quality: &Quality) -> Result<()> {
+        let key = Self::key(track_id, quality);
+        let path = self.path(track_id, quality);
+        let size = fs::metadata(&path)?.len();
+        self.index.clock += 1;
+        let entry = Entry {
+            track_id: track_id.to_string(),
+            quality: quality.clone(),
+            file: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            size,
+            used: self.index.clock,
+        };
+        self.index.entries.insert(key.clone(), entry);
+        self.evict(Some(&key))?;
+        self.save()
+    }
+
+    /// Keep the files of the tracks however long they are not used, instead of the tracks
+    /// pinned before. Pinned files still count towards the size limit
+    pub fn pin(&mut self, track_ids: HashSet<String>) -> Result<()> {
+        self.index.pinned = track_ids;
+        self.evict(None)
+    }
+
+    pub fn is_pinned(&self, track_id: &str) -> bool {
+        self.index.pinned.contains(track_id)
+    }
+
+    /// Files being played instead of the ones set before, they are not evicted
+    pub fn set_in_use(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.in_use = paths.into_iter().collect();
+    }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            files: self.index.entries.len(),
+            size: self.size(),
+            max_size: self.max_size,
+        }
+    }
+
+    pub fn set_max_size(&mut self, max_size: u64) -> Result<()> {
+        self.max_size = max_size;
+        self.evict(None)
+    }
+
+    /// Remove all of the files
+    pub fn purge(&mut self) -> Result<()> {
+        for entry in self.index.entries.values() {
+            let _ = fs::remove_file(self.dir.join(&entry.file));
+        }
+        self.index.entries.clear();
+        self.save()
+    }
+
+    fn size(&self) -> u64 {
+        self.index.entries.values().map(|entry| entry.size).sum()
+    }
+
+    fn is_intact(path: &Path, size: u64) -> bool {
+        fs::metadata(path).is_ok_and(|metadata| metadata.len() == size)
+    }
+
+    /// Forget the files that are missing or of another size than they were downloaded,
+    /// remove the stale ones the index does not know, partial ones included
+    fn verify(&mut self) -> Result<()> {
+        let dir = &self.dir;
+        self.index
+            .entries
+            .retain(|_, entry| Self::is_intact(&dir.join(&entry.file), entry.size));
+        let known: Vec<&str> = self
+            .index
+            .entries
+            .values()
+            .map(|entry| entry.file.as_str())
+            .collect();
+        for file in fs::read_dir(dir)? {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().into_owned();
+            if name == INDEX_FILE || known.contains(&name.as_str()) || !file.path().is_file() {
+                continue;
+            }
+            let modified = file.metadata()?.modified()?;
+            // A file modified in the future is not stale
+            if modified.elapsed().is_ok_and(|age| age > STALE_AGE) {
+                fs::remove_file(file.path())?;
+            }
+        }
+        self.save()
+    }
+
+    /// Remove the least recently used files that are neither pinned nor in use until
+    /// the cache fits the limit
+    fn evict(&mut self, keep: Option<&str>) -> Result<()> {
+        let mut size = self.size();
+        if size <= self.max_size {
+            return Ok(());
+        }
+        let mut entries: Vec<(String, Entry)> = self
+            .index
+            .entries
+            .iter()
+            .filter(|(key, entry)| {
+                Some(key.as_str()) != keep
+                    && !self.index.pinned.contains(&entry.track_id)
+                    && !self.in_use.contains(&self.dir.join(&entry.file))
+            })
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        entries.sort_by_key(|(_, entry)| entry.used);
+        for (key, entry) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            let _ = fs::remove_file(self.dir.join(&entry.file));
+            self.index.entries.remove(&key);
+            size -= entry.size;
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(
+            self.dir.join(INDEX_FILE),
+            serde_json::to_string(&self.index)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Make the file look last written before it could be downloading
+    fn make_stale(path: &Path) {
+        let modified = std::time::SystemTime::now() - STALE_AGE * 2;
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(modified).unwrap();
+    }
+
+    fn add(cache: &mut Cache, track_id: &str, size: usize) {
+        let quality = Quality::default();
+        fs::write(cache.path(track_id, &quality), vec![0; size]).unwrap();
+        cache.insert(track_id, &quality).unwrap();
+    }
+
+    #[test]
+    fn cache_removes_least_recently_used_files() {
+        let temp = TempDir::new("cache-lru");
+        let dir = temp.path();
+        let quality = Quality::default();
+        let mut cache = Cache::open(dir, 300).unwrap();
+        add(&mut cache, "1", 100);
+        add(&mut cache, "2", 100);
+        add(&mut cache, "3", 100);
+        assert!(cache.get("1", &quality).is_some());
+
+        add(&mut cache, "4", 100);
+        assert!(cache.get("2", &quality).is_none());
+        assert!(!cache.path("2", &quality).exists());
+        assert_eq!(
+            cache.usage(),
+            Usage {
+                files: 3,
+                size: 300,
+                max_size: 300
+            }
+        );
+
+        // The order of use survives a restart
+        let mut cache = Cache::open(dir, 300).unwrap();
+        add(&mut cache, "5", 100);
+        assert!(cache.get("3", &quality).is_none());
+        assert!(cache.get("1", &quality).is_some());
+
+        // A file larger than the limit is kept until the next one
+        add(&mut cache, "6", 1000);
+        assert_eq!(cache.usage().files, 1);
+        assert!(cache.get("6", &quality).is_some());
+    }
+
+    #[test]
+    fn cache_removes_partial_and_changed_files() {
+        let temp = TempDir::new("cache-integrity");
+        let dir = temp.path();
+        let quality = Quality::default();
+        let mut cache = Cache::open(dir, 1000).unwrap();
+        add(&mut cache, "whole", 100);
+        add(&mut cache, "truncated", 100);
+        fs::write(cache.path("truncated", &quality), vec![0; 50]).unwrap();
+        let partial = dir.join(format!("partial-{}.mp3{}", quality, PARTIAL_SUFFIX));
+        fs::write(&partial, vec![0; 10]).unwrap();
+        make_stale(&partial);
+        fs::write(dir.join("unknown.mp3"), vec![0; 10]).unwrap();
+        make_stale(&dir.join("unknown.mp3"));
+        // Another process may be downloading it
+        let downloading = dir.join(format!("downloading-{}.mp3{}", quality, PARTIAL_SUFFIX));
+        fs::write(&downloading, vec![0; 10]).unwrap();
+
+        // The usage is told without changing the files
+        assert_eq!(Cache::usage_of(dir, 1000).unwrap().files, 2);
+        assert!(partial.exists());
+
+        let mut cache = Cache::open(dir, 1000).unwrap();
+        assert!(cache.get("whole", &quality).is_some());
+        assert!(cache.get("truncated", &quality).is_none());
+        assert!(!partial.exists());
+        assert!(!dir.join("unknown.mp3").exists());
+        assert!(downloading.exists());
+
+        // A file changed while the cache is open is not used either
+        fs::write(cache.path("whole", &quality), vec![0; 10]).unwrap();
+        assert!(cache.get("whole", &quality).is_none());
+    }
+
+    #[test]
+    fn cache_finds_preferred_quality() {
+        let temp = TempDir::new("cache-quality");
+        let dir = temp.path();
+        let mut cache = Cache::open(dir, 1000).unwrap();
+        for bitrate in [128, 320] {
+            let quality = Quality {
+                codec: String::from("mp3"),
+                bitrate,
+            };
+            fs::write(cache.path("1", &quality), vec![0; 10]).unwrap();
+            cache.insert("1", &quality).unwrap();
+        }
+
+        let best = cache.find("1", &QualityPreference::default()).unwrap();
+        assert!(best.ends_with("1-mp3-320.mp3"));
+        let metered = QualityPreference {
+            lossless: false,
+            max_bitrate: Some(192),
+        };
+        let capped = cache.find("1", &metered).unwrap();
+        assert!(capped.ends_with("1-mp3-128.mp3"));
+        assert!(cache.find("2", &metered).is_none());
+    }
+
+    #[test]
+    fn cache_keeps_pinned_files() {
+        let temp = TempDir::new("cache-pinned");
+        let dir = temp.path();
+        let quality = Quality::default();
+        let mut cache = Cache::open(dir, 300).unwrap();
+        add(&mut cache, "1", 100);
+        add(&mut cache, "2", 100);
+        cache.pin(HashSet::from([String::from("1")])).unwrap();
+        add(&mut cache, "3", 100);
+        add(&mut cache, "4", 100);
+        assert!(cache.get("2", &quality).is_none());
+        assert!(cache.get("3", &quality).is_some());
+
+        // The pins survive a restart
+        let mut cache = Cache::open(dir, 100).unwrap();
+        assert!(cache.is_pinned("1"));
+        assert!(cache.get("1", &quality).is_some());
+        assert_eq!(cache.usage().files, 1);
+
+        cache.pin(HashSet::new()).unwrap();
+        add(&mut cache, "5", 100);
+        assert!(cache.get("1", &quality).is_none());
+    }
+
+    #[test]
+    fn cache_keeps_files_in_use() {
+        let temp = TempDir::new("cache-in-use");
+        let dir = temp.path();
+        let quality = Quality::default();
+        let mut cache = Cache::open(dir, 200).unwrap();
+        add(&mut cache, "1", 100);
+        add(&mut cache, "2", 100);
+        cache.set_in_use([cache.path("1", &quality)]);
+        add(&mut cache, "3", 100);
+        assert!(cache.get("1", &quality).is_some());
+        assert!(cache.get("2", &quality).is_none());
+    }
+
+    #[test]
+    fn cache_can_be_purged() {
+        let temp = TempDir::new("cache-purge");
+        let dir = temp.path();
+        let mut cache = Cache::open(dir, 1000).unwrap();
+        add(&mut cache, "1", 100);
+        add(&mut cache, "2", 100);
+        assert_eq!(cache.usage().size, 200);
+
+        cache.purge().unwrap();
+        assert_eq!(cache.usage().files, 0);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,1736 @@
+use md5::{Digest, Md5};
+use rand::{thread_rng, Rng};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Deserializer};
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::album::{Album, AlbumWithTracks};
+use crate::artist::{Artist, ArtistInfo};
+use crate::cache::{Cache, PARTIAL_SUFFIX};
+use crate::error::{Error, Result};
+use crate::page::Page;
+use crate::playlist::{
+    find_tracks, DiffOp, GeneratedPlaylist, GeneratedPlaylistType, Playlist, PlaylistWithTracks,
+    TrackRef,
+};
+use crate::search::{SearchKind, SearchResult, SearchResults};
+use crate::station::{Feedback, FeedbackEvent, Station, StationId, StationTracks};
+use crate::stream::{self, Stream};
+use crate::tags::{Cover, Tags};
+use crate::track::{Quality, QualityPreference, Track, TrackInfo};
+
+const API_URL: &str = "https://api.music.yandex.net";
+const CLIENT_HEADER: &str = "YandexMusicAndroid/24023621";
+// Salt used by the storage hosts to validate download links
+const SIGN_SALT: &str = "XGRlBW9FXlekgbPrRHuSiA";
+const ALBUMS_PAGE_SIZE: u32 = 20;
+const LIKED_TRACKS_PAGE_SIZE: u32 = 100;
+// Details of more tracks are fetched with several requests
+const TRACKS_BATCH_SIZE: usize = 200;
+// Playlist changes are retried against a fresh revision this many times
+const MAX_REVISION_RETRIES: u32 = 3;
+// Cover embedded into the downloaded files
+const COVER_SIZE: &str = "400x400";
+const MAX_COVER_BYTES: u64 = 4 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct Client {
+    agent: ureq::Agent,
+    base_url: String,
+    token: String,
+    uid: Option<u64>,
+    // Downloaded files are kept in the temporary directory without it
+    cache: Option<Arc<Mutex<Cache>>>,
+    quality: QualityPreference,
+}
+
+impl Client {
+    pub fn new(token: &str) -> Result<Self> {
+        Self::with_base_url(token, API_URL)
+    }
+
+    /// Create a client talking to a custom API host (e.g. a local mock server)
+    pub fn with_base_url(token: &str, base_url: &str) -> Result<Self> {
+        let mut client = Self {
+            agent: stream::agent(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            uid: None,
+            cache: None,
+            quality: QualityPreference::default(),
+        };
+        let status: AccountStatus = client.get("/account/status")?;
+        client.uid = status.account.uid;
+
+        Ok(client)
+    }
+
+    /// Client that did not fetch the account, for tests not touching the network
+    #[cfg(test)]
+    pub(crate) fn unauthorized(base_url: &str) -> Self {
+        Self {
+            agent: stream::agent(),
+            base_url: base_url.to_string(),
+            token: String::new(),
+            uid: None,
+            cache: None,
+            quality: QualityPreference::default(),
+        }
+    }
+
+    pub fn uid(&self) -> Option<u64> {
+        self.uid
+    }
+
+    /// Download the tracks into the cache, it is shared by the clones of the client
+    pub fn set_cache(&mut self, cache: Cache) {
+        self.cache = Some(Arc::new(Mutex::new(cache)));
+    }
+
+    /// Cache the tracks are downloaded into, to share it with the offline collections
+    pub fn cache(&self) -> Option<Arc<Mutex<Cache>>> {
+        self.cache.clone()
+    }
+
+    pub fn quality_preference(&self) -> &QualityPreference {
+        &self.quality
+    }
+
+    /// Quality of the tracks downloaded next, the tracks taken before keep the old one
+    pub fn set_quality_preference(&mut self, quality: QualityPreference) {
+        self.quality = quality;
+    }
+
+    pub fn get_random_track(&self) -> Result<Track> {
+        let playlist = self.playlist_of_the_day()?;
+        if playlist.is_empty() {
+            return Err(Error::NotFound(String::from(
+                "Playlist of the day is empty",
+            )));
+        }
+        let random_track_num = thread_rng().gen_range(0..playlist.len());
+
+        Ok(playlist[random_track_num].clone())
+    }
+
+    pub fn playlist_of_the_day(&self) -> Result<Vec<Track>> {
+        self.generated_playlist(&GeneratedPlaylistType::PlaylistOfTheDay)
+    }
+
+    /// Playlists generated for the user, in the order of the feed
+    pub fn generated_playlists(&self) -> Result<Vec<GeneratedPlaylist>> {
+        let feed: Feed = self.get("/feed")?;
+
+        Ok(feed
+            .generated_playlists
+            .into_iter()
+            .map(|generated| GeneratedPlaylist {
+                playlist_type: generated.playlist_type,
+                title: generated.data.title,
+                description: generated.data.description,
+                owner_uid: generated.data.owner.uid,
+                kind: generated.data.kind,
+            })
+            .collect())
+    }
+
+    pub fn generated_playlist(&self, playlist_type: &GeneratedPlaylistType) -> Result<Vec<Track>> {
+        let playlist = self
+            .generated_playlists()?
+            .into_iter()
+            .find(|playlist| &playlist.playlist_type == playlist_type)
+            .ok_or_else(|| Error::NotFound(format!("No {} playlist in the feed", playlist_type)))?;
+
+        self.generated_playlist_tracks(&playlist)
+    }
+
+    pub fn generated_playlist_tracks(&self, playlist: &GeneratedPlaylist) -> Result<Vec<Track>> {
+        self.user_playlist_tracks(playlist.owner_uid, playlist.kind)
+    }
+
+    pub fn playlist_tracks(&self, playlist: &Playlist) -> Result<Vec<Track>> {
+        self.user_playlist_tracks(playlist.owner.uid, playlist.kind)
+    }
+
+    fn user_playlist_tracks(&self, owner_uid: u64, kind: u64) -> Result<Vec<Track>> {
+        Ok(self.playlist(owner_uid, kind)?.tracks)
+    }
+
+    /// Playlist of any user with its tracks
+    pub fn playlist(&self, owner_uid: u64, kind: u64) -> Result<PlaylistWithTracks> {
+        let playlist: PlaylistTracks =
+            self.get(&format!("/users/{}/playlists/{}", owner_uid, kind))?;
+
+        let track_ids: Vec<String> = playlist.tracks.into_iter().map(|t| t.id).collect();
+        Ok(PlaylistWithTracks {
+            playlist: playlist.playlist,
+            tracks: self.tracks(&track_ids)?,
+        })
+    }
+
+    /// Playlists created by the user
+    pub fn user_playlists(&self) -> Result<Vec<Playlist>> {
+        self.get(&format!("/users/{}/playlists/list", self.account_uid()?))
+    }
+
+    pub fn create_playlist(&self, title: &str, public: bool) -> Result<Playlist> {
+        let visibility = if public { "public" } else { "private" };
+        let path = format!("/users/{}/playlists/create", self.account_uid()?);
+        let response = self
+            .request("POST", &self.url(&path))
+            .send_form(&[("title", title), ("visibility", visibility)])?;
+        Self::result(response)
+    }
+
+    pub fn rename_playlist(&self, kind: u64, title: &str) -> Result<Playlist> {
+        let path = format!("/users/{}/playlists/{}/name", self.account_uid()?, kind);
+        let response = self
+            .request("POST", &self.url(&path))
+            .send_form(&[("value", title)])?;
+        Self::result(response)
+    }
+
+    pub fn delete_playlist(&self, kind: u64) -> Result<()> {
+        let path = format!("/users/{}/playlists/{}/delete", self.account_uid()?, kind);
+        let response = self.request("POST", &self.url(&path)).call()?;
+        let _: IgnoredAny = Self::result(response)?;
+
+        Ok(())
+    }
+
+    /// Insert tracks at the position, the position past the end appends them
+    pub fn insert_tracks(&self, kind: u64, at: usize, tracks: &[Track]) -> Result<Playlist> {
+        let tracks: Vec<TrackRef> = tracks.iter().map(TrackRef::from).collect();
+        self.change_playlist(kind, |current| {
+            Ok(vec![DiffOp::Insert {
+                at: at.min(current.len()),
+                tracks: tracks.clone(),
+            }])
+        })
+    }
+
+    pub fn add_tracks(&self, kind: u64, tracks: &[Track]) -> Result<Playlist> {
+        self.insert_tracks(kind, usize::MAX, tracks)
+    }
+
+    /// Remove the tracks at positions `from..to`,
+    /// they are looked up again if the playlist was changed meanwhile
+    pub fn remove_tracks(&self, kind: u64, from: usize, to: usize) -> Result<Playlist> {
+        let tracks = self.playlist_refs(self.account_uid()?, kind)?.1;
+        let removed = tracks
+            .get(from..to)
+            .ok_or_else(|| {
+                Error::NotFound(format!("No tracks {}..{} in playlist {}", from, to, kind))
+            })?
+            .to_vec();
+        self.change_playlist(kind, |current| {
+            let from = find_tracks(current, &removed, from).ok_or_else(|| {
+                Error::NotFound(format!("Tracks were removed from playlist {}", kind))
+            })?;
+            Ok(vec![DiffOp::Delete {
+                from,
+                to: from + removed.len(),
+                tracks: removed.clone(),
+            }])
+        })
+    }
+
+    /// Move the track at position `from` so it ends up at position `to`
+    pub fn move_track(&self, kind: u64, from: usize, to: usize) -> Result<Playlist> {
+        let tracks = self.playlist_refs(self.account_uid()?, kind)?.1;
+        let moved = tracks
+            .get(from..from + 1)
+            .ok_or_else(|| Error::NotFound(format!("No track {} in playlist {}", from, kind)))?
+            .to_vec();
+        self.change_playlist(kind, |current| {
+            let from = find_tracks(current, &moved, from).ok_or_else(|| {
+                Error::NotFound(format!("Track was removed from playlist {}", kind))
+            })?;
+            Ok(vec![
+                DiffOp::Delete {
+                    from,
+                    to: from + 1,
+                    tracks: moved.clone(),
+                },
+                DiffOp::Insert {
+                    at: to.min(current.len() - 1),
+                    tracks: moved.clone(),
+                },
+            ])
+        })
+    }
+
+    /// Apply the diff made for the latest revision, make it again if the revision is outdated
+    fn change_playlist<F>(&self, kind: u64, diff: F) -> Result<Playlist>
+    where
+        F: Fn(&[TrackRef]) -> Result<Vec<DiffOp>>,
+    {
+        let uid = self.account_uid()?;
+        let mut retries = 0;
+        loop {
+            let (revision, tracks) = self.playlist_refs(uid, kind)?;
+            let diff = serde_json::to_string(&diff(&tracks)?)?;
+            let path = format!("/users/{}/playlists/{}/change-relative", uid, kind);
+            let result = self
+                .request("POST", &self.url(&path))
+                .send_form(&[
+                    ("kind", &kind.to_string()),
+                    ("revision", &revision.to_string()),
+                    ("diff", &diff),
+                ])
+                .map_err(Error::from)
+                .and_then(Self::result);
+            match result {
+                Err(Error::RevisionConflict(_)) if retries < MAX_REVISION_RETRIES => retries += 1,
+                result => return result,
+            }
+        }
+    }
+
+    fn playlist_refs(&self, owner_uid: u64, kind: u64) -> Result<(u64, Vec<TrackRef>)> {
+        let playlist: PlaylistTracks =
+            self.get(&format!("/users/{}/playlists/{}", owner_uid, kind))?;
+        let tracks = playlist
+            .tracks
+            .into_iter()
+            .map(|t| TrackRef {
+                id: t.id,
+                album_id: t.album_id,
+            })
+            .collect();
+
+        Ok((playlist.playlist.revision, tracks))
+    }
+
+    /// Tracks liked by the user from the most recent, pages are numbered from 0
+    pub fn liked_tracks(&self, page: u32) -> Result<Page<Track>> {
+        let ids = self.liked_track_ids()?;
+
+        let start = (page * LIKED_TRACKS_PAGE_SIZE) as usize;
+        let end = ids.len().min(start + LIKED_TRACKS_PAGE_SIZE as usize);
+        let items = match ids.get(start..end) {
+            Some(page_ids) => self.tracks(page_ids)?,
+            None => Vec::new(),
+        };
+
+        Ok(Page {
+            items,
+            page,
+            per_page: LIKED_TRACKS_PAGE_SIZE,
+            total: ids.len() as u32,
+        })
+    }
+
+    /// Ids of the liked tracks from the most recent, cheap to fetch for large collections
+    pub fn liked_track_ids(&self) -> Result<Vec<String>> {
+        let likes: LikedTracks =
+            self.get(&format!("/users/{}/likes/tracks", self.account_uid()?))?;
+
+        Ok(likes.library.tracks.into_iter().map(|t| t.id).collect())
+    }
+
+    pub fn like_track(&self, track_id: &str) -> Result<()> {
+        self.change_track_list("likes", "add-multiple", track_id)
+    }
+
+    pub fn unlike_track(&self, track_id: &str) -> Result<()> {
+        self.change_track_list("likes", "remove", track_id)
+    }
+
+    /// The service does not recommend disliked tracks anymore
+    pub fn dislike_track(&self, track_id: &str) -> Result<()> {
+        self.change_track_list("dislikes", "add-multiple", track_id)
+    }
+
+    pub fn undislike_track(&self, track_id: &str) -> Result<()> {
+        self.change_track_list("dislikes", "remove", track_id)
+    }
+
+    fn change_track_list(&self, list: &str, change: &str, track_id: &str) -> Result<()> {
+        let path = format!("/users/{}/{}/tracks/{}", self.account_uid()?, list, change);
+        let response = self
+            .request("POST", &self.url(&path))
+            .send_form(&[("track-ids", track_id)])?;
+        let _: IgnoredAny = Self::result(response)?;
+
+        Ok(())
+    }
+
+    pub fn liked_albums(&self) -> Result<Vec<Album>> {
+        let response = self
+            .request(
+                "GET",
+                &self.url(&format!("/users/{}/likes/albums", self.account_uid()?)),
+            )
+            .query("rich", "true")
+            .call()?;
+        let likes: Vec<LikedAlbum> = Self::result(response)?;
+
+        Ok(likes.into_iter().map(|like| like.album).collect())
+    }
+
+    pub fn liked_artists(&self) -> Result<Vec<Artist>> {
+        let response = self
+            .request(
+                "GET",
+                &self.url(&format!("/users/{}/likes/artists", self.account_uid()?)),
+            )
+            .query("with-timestamps", "false")
+            .call()?;
+        Self::result(response)
+    }
+
+    /// Album with the tracks of all its volumes (discs)
+    pub fn album(&self, album_id: &str) -> Result<AlbumWithTracks> {
+        let album: AlbumTracks = self.get(&format!("/albums/{}/with-tracks", album_id))?;
+
+        Ok(AlbumWithTracks {
+            album: album.album,
+            volumes: album
+                .volumes
+                .into_iter()
+                .map(|volume| {
+                    volume
+                        .into_iter()
+                        .map(|info| Track::new(info, self.clone()))
+                        .collect()
+                })
+                .collect(),
+        })
+    }
+
+    /// Tracks of all discs of the album
+    pub fn album_tracks(&self, album_id: &str) -> Result<Vec<Track>> {
+        Ok(self.album(album_id)?.tracks())
+    }
+
+    /// Artist with the popular tracks and the first albums
+    pub fn artist(&self, artist_id: &str) -> Result<ArtistInfo> {
+        let brief: ArtistBrief = self.get(&format!("/artists/{}/brief-info", artist_id))?;
+
+        Ok(ArtistInfo {
+            artist: brief.artist,
+            popular_tracks: brief
+                .popular_tracks
+                .into_iter()
+                .map(|info| Track::new(info, self.clone()))
+                .collect(),
+            albums: brief.albums,
+        })
+    }
+
+    /// Albums of the artist from the newest, pages are numbered from 0
+    pub fn artist_albums(&self, artist_id: &str, page: u32) -> Result<Page<Album>> {
+        let response = self
+            .request(
+                "GET",
+                &self.url(&format!("/artists/{}/direct-albums", artist_id)),
+            )
+            .query("page", &page.to_string())
+            .query("page-size", &ALBUMS_PAGE_SIZE.to_string())
+            .query("sort-by", "year")
+            .call()?;
+        let albums: ArtistAlbums = Self::result(response)?;
+
+        Ok(Page {
+            items: albums.albums,
+            page: albums.pager.page,
+            per_page: albums.pager.per_page,
+            total: albums.pager.total,
+        })
+    }
+
+    /// Most popular tracks of the artist
+    pub fn artist_tracks(&self, artist_id: &str) -> Result<Vec<Track>> {
+        let artist: ArtistTracks = self.get(&format!("/artists/{}/tracks", artist_id))?;
+
+        Ok(artist
+            .tracks
+            .into_iter()
+            .map(|info| Track::new(info, self.clone()))
+            .collect())
+    }
+
+    /// Search the catalog, pages are numbered from 0
+    pub fn search(&self, query: &str, kind: SearchKind, page: u32) -> Result<SearchResult> {
+        let response = self
+            .request("GET", &self.url("/search"))
+            .query("text", query)
+            .query("nocorrect", "false")
+            .query("type", &kind.to_string())
+            .query("page", &page.to_string())
+            .query("playlist-in-best", "true")
+            .call()?;
+        let result: RawSearchResult = Self::result(response)?;
+
+        Ok(SearchResult {
+            text: result.text,
+            page: result.page,
+            tracks: result
+                .tracks
+                .unwrap_or_default()
+                .map(|info| Track::new(info, self.clone())),
+            albums: result.albums.unwrap_or_default(),
+            artists: result.artists.unwrap_or_default(),
+            playlists: result.playlists.unwrap_or_default(),
+        })
+    }
+
+    /// My Wave followed by the stations of all genres, moods, activities and eras
+    pub fn stations(&self) -> Result<Vec<Station>> {
+        let response = self
+            .request("GET", &self.url("/rotor/stations/list"))
+            .query("language", "en")
+            .call()?;
+        let listed: Vec<StationItem> = Self::result(response)?;
+
+        let my_wave = Station::my_wave();
+        let mut stations = vec![my_wave.clone()];
+        stations.extend(
+            listed
+                .into_iter()
+                .map(|item| item.station)
+                .filter(|station| station.id != my_wave.id),
+        );
+        Ok(stations)
+    }
+
+    /// Next tracks of the station to be played after the last queued one
+    pub fn station_tracks(
+        &self,
+        station: &StationId,
+        last_track_id: Option<&str>,
+    ) -> Result<StationTracks> {
+        let mut request = self
+            .request(
+                "GET",
+                &self.url(&format!("/rotor/station/{}/tracks", station)),
+            )
+            .query("settings2", "true");
+        if let Some(track_id) = last_track_id {
+            request = request.query("queue", track_id);
+        }
+        let batch: StationBatch = Self::result(request.call()?)?;
+
+        Ok(StationTracks {
+            batch_id: batch.batch_id,
+            tracks: batch
+                .sequence
+                .into_iter()
+                .map(|item| Track::new(item.track, self.clone()))
+                .collect(),
+        })
+    }
+
+    pub fn station_feedback(
+        &self,
+        station: &Station,
+        batch_id: Option<&str>,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        let mut request = self.request(
+            "POST",
+            &self.url(&format!("/rotor/station/{}/feedback", station.id)),
+        );
+        if let Some(batch_id) = batch_id {
+            request = request.query("batch-id", batch_id);
+        }
+        let event = FeedbackEvent::new(station, feedback, SystemTime::now());
+        let response = request.send_json(serde_json::to_value(event)?)?;
+        let _: IgnoredAny = Self::result(response)?;
+
+        Ok(())
+    }
+
+    /// Tracks of the ids, they are fetched in batches
+    pub fn tracks(&self, track_ids: &[String]) -> Result<Vec<Track>> {
+        let mut tracks = Vec::with_capacity(track_ids.len());
+        for batch in track_ids.chunks(TRACKS_BATCH_SIZE) {
+            let response = self
+                .request("POST", &self.url("/tracks"))
+                .send_form(&[("track-ids", &batch.join(","))])?;
+            let infos: Vec<TrackInfo> = Self::result(response)?;
+            tracks.extend(infos.into_iter().map(|info| Track::new(info, self.clone())));
+        }
+
+        Ok(tracks)
+    }
+
+    fn account_uid(&self) -> Result<u64> {
+        self.uid
+            .ok_or_else(|| Error::Auth(String::from("The account has no uid")))
+    }
+
+    /// Full files of the track, previews are left out
+    fn download_infos(&self, track_id: &str) -> Result<Vec<DownloadInfo>> {
+        let infos: Vec<DownloadInfo> = self.get(&format!("/tracks/{}/download-info", track_id))?;
+        Ok(infos.into_iter().filter(|info| !info.preview).collect())
+    }
+
+    pub(crate) fn download_variants(&self, track_id: &str) -> Result<Vec<Quality>> {
+        Ok(self
+            .download_infos(track_id)?
+            .iter()
+            .map(DownloadInfo::quality)
+            .collect())
+    }
+
+    /// Resolve a signed link to the track file of the preferred quality
+    pub(crate) fn download_url(&self, track_id: &str) -> Result<(Quality, String)> {
+        let infos = self.download_infos(track_id)?;
+        let variants: Vec<Quality> = infos.iter().map(DownloadInfo::quality).collect();
+        let index = self.quality.choose_index(&variants).ok_or_else(|| {
+            Error::NotFound(format!("No playable variant for track {}", track_id))
+        })?;
+        let (info, quality) = (&infos[index], &variants[index]);
+
+        let xml = self
+            .request("GET", &info.download_info_url)
+            .call()?
+            .into_string()?;
+        let direct_link = DirectLink::parse(&xml)?;
+
+        Ok((quality.clone(), direct_link.url(self.storage_scheme())))
+    }
+
+    /// Complete file of the track downloaded before in the preferred quality
+    pub(crate) fn cached_file(&self, track_id: &str) -> Option<PathBuf> {
+        let cache = self.cache.as_ref()?;
+        let mut cache = cache.lock().unwrap();
+        cache.find(track_id, &self.quality)
+    }
+
+    /// Where the file of the track is downloaded
+    fn file_path(&self, track_id: &str, quality: &Quality) -> PathBuf {
+        match &self.cache {
+            Some(cache) => cache.lock().unwrap().path(track_id, quality),
+            None => std::env::temp_dir().join("yandex-music").join(format!(
+                "{}-{}.{}",
+                track_id,
+                quality,
+                quality.extension()
+            )),
+        }
+    }
+
+    /// Front cover of the track, None if there is none or it cannot be fetched
+    fn cover(&self, track: &Track) -> Option<Cover> {
+        let response = self.agent.get(&track.cover_url(COVER_SIZE)?).call().ok()?;
+        let mime_type = response.content_type().to_string();
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_COVER_BYTES)
+            .read_to_end(&mut data)
+            .ok()?;
+        Some(Cover { mime_type, data })
+    }
+
+    /// File to keep for the downloaded one: its copy with the tags of the track, or the
+    /// file itself if it is not tagged
+    fn tagged(&self, track: &Track, quality: &Quality, file: &Path) -> PathBuf {
+        if !Tags::can_tag(quality.extension()) {
+            return file.to_path_buf();
+        }
+        let mut tags = Tags::of(track);
+        tags.cover = self.cover(track);
+        match tags.write_copy(file, quality.extension()) {
+            Ok(Some(copy)) => copy,
+            // The file plays without the tags as well
+            _ => file.to_path_buf(),
+        }
+    }
+
+    fn file_added(&self, track_id: &str, quality: &Quality) -> Result<()> {
+        match &self.cache {
+            Some(cache) => cache.lock().unwrap().insert(track_id, quality),
+            None => Ok(()),
+        }
+    }
+
+    /// Download the file of the track, it is written next to its path until it is complete
+    pub(crate) fn download_to(
+        &self,
+        url: &str,
+        track: &Track,
+        quality: &Quality,
+    ) -> Result<PathBuf> {
+        let path = self.file_path(track.id(), quality);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut partial = path.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+
+        // The signed link is all the storage needs, the token is only sent to the API
+        let response = self.agent.get(url).call()?;
+        let mut file = File::create(&partial)?;
+        io::copy(&mut response.into_reader(), &mut file)?;
+        let kept = self.tagged(track, quality, &partial);
+        fs::rename(&kept, &path)?;
+        if kept != partial {
+            fs::remove_file(&partial)?;
+        }
+        self.file_added(track.id(), quality)?;
+
+        Ok(path)
+    }
+
+    /// Download the file in the background, it can be played meanwhile
+    pub(crate) fn stream_to(&self, url: &str, track: &Track, quality: &Quality) -> Result<Stream> {
+        let path = self.file_path(track.id(), quality);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let (agent, url) = (self.agent.clone(), url.to_string());
+        let request = move || agent.get(&url);
+        let client = self.clone();
+        let (track_id, quality) = (track.id().to_string(), quality.clone());
+        // The file is tagged before it is complete, so that the readers see all of it
+        let tagged = {
+            let (client, track, quality) = (self.clone(), track.clone(), quality.clone());
+            move |file: &Path| client.tagged(&track, &quality, file)
+        };
+        // The file is not cached if the index cannot be saved
+        let finished = move || drop(client.file_added(&track_id, &quality));
+        Stream::with_request(request, path, tagged, finished)
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let response = self.request("GET", &self.url(path)).call()?;
+        Self::result(response)
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        self.agent
+            .request(method, url)
+            .set("Authorization", &format!("OAuth {}", self.token))
+            .set("X-Yandex-Music-Client", CLIENT_HEADER)
+    }
+
+    fn result<T: DeserializeOwned>(response: ureq::Response) -> Result<T> {
+        let response: ApiResponse<T> = response.into_json()?;
+        Ok(response.result)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    // Storage hosts are reached with the same scheme as the API,
+    // so a plain HTTP mock server can serve the files as well
+    fn storage_scheme(&self) -> &'static str {
+        if self.base_url.starts_with("http://") {
+            "http"
+        } else {
+            "https"
+        }
+    }
+}
+
+// The token must not end up in logs
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("base_url", &self.base_url)
+            .field("uid", &self.uid)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    result: T,
+}
+
+#[derive(Deserialize)]
+struct AccountStatus {
+    account: Account,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    uid: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Feed {
+    generated_playlists: Vec<FeedPlaylist>,
+}
+
+#[derive(Deserialize)]
+struct FeedPlaylist {
+    #[serde(rename = "type")]
+    playlist_type: GeneratedPlaylistType,
+    data: Playlist,
+}
+
+#[derive(Deserialize)]
+struct PlaylistTracks {
+    #[serde(flatten)]
+    playlist: Playlist,
+    #[serde(default)]
+    tracks: Vec<TrackShort>,
+}
+
+#[derive(Deserialize)]
+struct LikedTracks {
+    library: Library,
+}
+
+#[derive(Deserialize)]
+struct Library {
+    #[serde(default)]
+    tracks: Vec<TrackShort>,
+}
+
+#[derive(Deserialize)]
+struct StationItem {
+    station: Station,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StationBatch {
+    batch_id: String,
+    #[serde(default)]
+    sequence: Vec<StationTrack>,
+}
+
+#[derive(Deserialize)]
+struct StationTrack {
+    track: TrackInfo,
+}
+
+#[derive(Deserialize)]
+struct LikedAlbum {
+    album: Album,
+}
+
+#[derive(Deserialize)]
+struct AlbumTracks {
+    #[serde(flatten)]
+    album: Album,
+    #[serde(default)]
+    volumes: Vec<Vec<TrackInfo>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtistBrief {
+    artist: Artist,
+    #[serde(default)]
+    albums: Vec<Album>,
+    #[serde(default)]
+    popular_tracks: Vec<TrackInfo>,
+}
+
+#[derive(Deserialize)]
+struct ArtistAlbums {
+    #[serde(default)]
+    albums: Vec<Album>,
+    pager: Pager,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pager {
+    page: u32,
+    per_page: u32,
+    total: u32,
+}
+
+#[derive(Deserialize)]
+struct ArtistTracks {
+    #[serde(default)]
+    tracks: Vec<TrackInfo>,
+}
+
+#[derive(Deserialize)]
+struct RawSearchResult {
+    text: String,
+    #[serde(default)]
+    page: u32,
+    tracks: Option<SearchResults<TrackInfo>>,
+    albums: Option<SearchResults<Album>>,
+    artists: Option<SearchResults<Artist>>,
+    playlists: Option<SearchResults<Playlist>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrackShort {
+    #[serde(deserialize_with = "string_or_number")]
+    id: String,
+    #[serde(default, deserialize_with = "optional_string_or_number")]
+    album_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadInfo {
+    codec: String,
+    bitrate_in_kbps: u32,
+    download_info_url: String,
+    #[serde(default)]
+    preview: bool,
+}
+
+impl DownloadInfo {
+    fn quality(&self) -> Quality {
+        Quality {
+            codec: self.codec.clone(),
+            bitrate: self.bitrate_in_kbps,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct DirectLink {
+    host: String,
+    path: String,
+    ts: String,
+    s: String,
+}
+
+impl DirectLink {
+    fn parse(xml: &str) -> Result<Self> {
+        let tag = |name: &str| -> Result<String> {
+            let open = format!("<{}>", name);
+            let close = format!("</{}>", name);
+            let start = xml
+                .find(&open)
+                .ok_or_else(|| Error::InvalidResponse(format!("No <{}> in download info", name)))?
+                + open.len();
+            let end = xml[start..].find(&close).ok_or_else(|| {
+                Error::InvalidResponse(format!("Unclosed <{}> in download info", name))
+            })? + start;
+            Ok(xml[start..end].trim().to_string())
+        };
+
+        Ok(Self {
+            host: tag("host")?,
+            path: tag("path")?,
+            ts: tag("ts")?,
+            s: tag("s")?,
+        })
+    }
+
+    fn sign(&self) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(SIGN_SALT);
+        hasher.update(self.path.trim_start_matches('/'));
+        hasher.update(&self.s);
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn url(&self, scheme: &str) -> String {
+        format!(
+            "{}://{}/get-mp3/{}/{}{}",
+            scheme,
+            self.host,
+            self.sign(),
+            self.ts,
+            self.path
+        )
+    }
+}
+
+/// Ids come as strings in full objects and as numbers in short ones
+pub(crate) fn string_or_number<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        String(String),
+        Number(u64),
+    }
+
+    Ok(match Id::deserialize(deserializer)? {
+        Id::String(s) => s,
+        Id::Number(n) => n.to_string(),
+    })
+}
+
+fn optional_string_or_number<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_or_number(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    const TOKEN: &str = "test-token";
+
+    fn mock_account(server: &MockServer) {
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/account/status")
+                .header("Authorization", "OAuth test-token");
+            then.status(200)
+                .json_body(json!({"result": {"account": {"uid": 42, "login": "user"}}}));
+        });
+    }
+
+    fn mock_track(server: &MockServer, id: &str, title: &str) {
+        server.mock(|when, then| {
+            when.method(POST).path("/tracks").body_contains(id);
+            then.status(200).json_body(json!({"result": [
+                {"id": id, "title": title, "durationMs": 185000}
+            ]}));
+        });
+    }
+
+    fn download_info_xml(host: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <download-info><host>{}</host><path>/rmusic/U2FsdGVk/file.mp3</path>\
+             <ts>0005e0b1a6c4e1d2</ts><region>-1</region><s>a1b2c3d4</s></download-info>",
+            host
+        )
+    }
+
+    fn feed() -> serde_json::Value {
+        json!({"result": {"generatedPlaylists": [
+            {"type": "missedLikes", "data": {
+                "owner": {"uid": 503646255}, "kind": 1, "title": "Missed likes"}},
+            {"type": "playlistOfTheDay", "data": {
+                "owner": {"uid": 503646255}, "kind": 3, "title": "Playlist of the day",
+                "description": "Updated every day"}},
+            {"type": "neverHeard", "data": {
+                "owner": {"uid": 503646255}, "kind": 2, "title": "Never heard"}},
+            {"type": "kinopoisk", "data": {
+                "owner": {"uid": 503646255}, "kind": 4, "title": "Kinopoisk"}}
+        ]}})
+    }
+
+    #[test]
+    fn client_inits_account_with_token() {
+        let server = MockServer::start();
+        mock_account(&server);
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+
+        assert_eq!(client.uid(), Some(42));
+    }
+
+    #[test]
+    fn client_reports_rejected_token() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/account/status");
+            then.status(401).json_body(json!({"error": {
+                "name": "session-expired", "message": "Your OAuth token is expired"
+            }}));
+        });
+
+        let result = Client::with_base_url(TOKEN, &server.base_url());
+
+        assert!(
+            matches!(result, Err(Error::Auth(message)) if message == "Your OAuth token is expired")
+        );
+    }
+
+    #[test]
+    fn client_reports_missing_download_variant() {
+        let server = MockServer::start();
+        mock_account(&server);
+        mock_track(&server, "301", "lossy-only");
+        server.mock(|when, then| {
+            when.method(GET).path("/tracks/301/download-info");
+            then.status(200).json_body(json!({"result": [
+                {"codec": "mp3", "bitrateInKbps": 128, "preview": false,
+                 "downloadInfoUrl": server.url("/info/128")}
+            ]}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let track = client.tracks(&["301".to_string()]).unwrap().remove(0);
+
+        assert!(matches!(track.download(), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn client_can_get_playlist_of_the_day() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/feed");
+            then.status(200).json_body(feed());
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/users/503646255/playlists/3");
+            then.status(200).json_body(json!({"result": {
+                "owner": {"uid": 503646255},
+                "kind": 3,
+                "tracks": [{"id": 101}, {"id": 102}]
+            }}));
+        });
+        let tracks_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/tracks")
+                .body_contains("track-ids=101%2C102");
+            then.status(200).json_body(json!({"result": [
+                {"id": "101", "title": "First", "durationMs": 185000},
+                {"id": "102", "title": "Second", "durationMs": 241000}
+            ]}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let playlist = client.playlist_of_the_day().unwrap();
+
+        tracks_mock.assert();
+        assert_eq!(playlist.len(), 2);
+        assert_eq!(playlist[0].title(), "First");
+        assert_eq!(playlist[1].total_duration().unwrap().as_secs(), 241);
+    }
+
+    #[test]
+    fn client_can_list_generated_playlists() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/feed");
+            then.status(200).json_body(feed());
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let playlists = client.generated_playlists().unwrap();
+
+        let types: Vec<_> = playlists.iter().map(|p| p.playlist_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                GeneratedPlaylistType::MissedLikes,
+                GeneratedPlaylistType::PlaylistOfTheDay,
+                GeneratedPlaylistType::NeverHeard,
+                GeneratedPlaylistType::Other(String::from("kinopoisk")),
+            ]
+        );
+        assert_eq!(playlists[1].title, "Playlist of the day");
+        assert_eq!(
+            playlists[1].description.as_deref(),
+            Some("Updated every day")
+        );
+        assert_eq!(playlists[2].description, None);
+    }
+
+    #[test]
+    fn client_reports_missing_generated_playlist() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/feed");
+            then.status(200).json_body(feed());
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let result = client.generated_playlist(&GeneratedPlaylistType::Podcasts);
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn client_can_search_catalog() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let search_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/search")
+                .query_param("text", "daft punk")
+                .query_param("type", "all")
+                .query_param("page", "0");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(include_str!("../tests/fixtures/search-all.json"));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let result = client.search("daft punk", SearchKind::All, 0).unwrap();
+
+        search_mock.assert();
+        assert_eq!(result.text, "daft punk");
+        assert_eq!(result.tracks.total, 1548);
+        let titles: Vec<_> = result.tracks.results.iter().map(|t| t.title()).collect();
+        assert_eq!(titles, vec!["Get Lucky", "One More Time"]);
+        assert_eq!(result.tracks.results[1].id(), "1710808");
+        assert_eq!(result.albums.results[0].title, "Random Access Memories");
+        assert_eq!(result.albums.results[0].year, Some(2013));
+        assert_eq!(result.albums.results[0].artists[0].name, "Daft Punk");
+        assert_eq!(result.artists.results[0].id, "27003");
+        assert_eq!(result.playlists.results[0].owner.uid, 1081347);
+        assert_eq!(result.playlists.results[0].track_count, 30);
+    }
+
+    #[test]
+    fn search_by_kind_leaves_other_sections_empty() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/search")
+                .query_param("type", "artist")
+                .query_param("page", "2");
+            then.status(200).json_body(json!({"result": {
+                "type": "artist", "page": 2, "perPage": 10, "text": "daft punk",
+                "artists": {"total": 12, "perPage": 10, "results": [
+                    {"id": 27003, "name": "Daft Punk"}
+                ]}
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let result = client.search("daft punk", SearchKind::Artist, 2).unwrap();
+
+        assert_eq!(result.page, 2);
+        assert_eq!(result.artists.results.len(), 1);
+        assert!(result.tracks.results.is_empty());
+        assert!(result.albums.results.is_empty());
+        assert!(result.playlists.results.is_empty());
+    }
+
+    #[test]
+    fn client_can_get_album_tracks() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/albums/175233/with-tracks");
+            then.status(200).json_body(json!({"result": {
+                "id": 175233, "title": "Discovery",
+                "volumes": [
+                    [{"id": "1710808", "title": "One More Time", "durationMs": 320357},
+                     {"id": "1710809", "title": "Aerodynamic", "durationMs": 212000}],
+                    [{"id": "1710810", "title": "Bonus", "durationMs": 100000}]
+                ]
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let album = client.album("175233").unwrap();
+
+        assert_eq!(album.album.title, "Discovery");
+        assert_eq!(album.volumes.len(), 2);
+        let titles: Vec<_> = album.tracks().iter().map(|t| t.title()).collect();
+        assert_eq!(titles, vec!["One More Time", "Aerodynamic", "Bonus"]);
+    }
+
+    #[test]
+    fn client_can_get_artist() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/artists/27003/brief-info");
+            then.status(200).json_body(json!({"result": {
+                "artist": {"id": 27003, "name": "Daft Punk"},
+                "albums": [{"id": 175233, "title": "Discovery", "year": 2001}],
+                "popularTracks": [
+                    {"id": "1710808", "title": "One More Time", "durationMs": 320357,
+                     "artists": [{"id": 27003, "name": "Daft Punk"}],
+                     "albums": [{"id": 175233, "title": "Discovery", "year": 2001}]}
+                ]
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let artist = client.artist("27003").unwrap();
+
+        assert_eq!(artist.artist.name, "Daft Punk");
+        assert_eq!(artist.albums[0].year, Some(2001));
+        let track = &artist.popular_tracks[0];
+        assert_eq!(track.artists[0].id, "27003");
+        assert_eq!(track.album_id.as_deref(), Some("175233"));
+    }
+
+    #[test]
+    fn client_can_page_through_discography() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/artists/27003/direct-albums")
+                .query_param("page", "1")
+                .query_param("page-size", "20");
+            then.status(200).json_body(json!({"result": {
+                "albums": [{"id": 1, "title": "Homework", "year": 1997}],
+                "pager": {"page": 1, "perPage": 20, "total": 21}
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let discography = client.artist_albums("27003", 1).unwrap();
+
+        assert_eq!(discography.items[0].title, "Homework");
+        assert_eq!(discography.total, 21);
+        assert!(!discography.has_next_page());
+    }
+
+    #[test]
+    fn liked_tracks_are_fetched_by_pages() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let likes: Vec<_> = (0..150)
+            .map(|id| json!({"id": id.to_string(), "albumId": "1", "timestamp": "2022-01-01"}))
+            .collect();
+        server.mock(|when, then| {
+            when.method(GET).path("/users/42/likes/tracks");
+            then.status(200).json_body(json!({"result": {
+                "library": {"uid": 42, "revision": 7, "tracks": likes}
+            }}));
+        });
+        let tracks_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/tracks")
+                .body_contains("track-ids=100%2C101")
+                .body_contains("148%2C149");
+            then.status(200).json_body(json!({"result": [
+                {"id": "100", "title": "Hundredth", "durationMs": 1000}
+            ]}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let page = client.liked_tracks(1).unwrap();
+
+        tracks_mock.assert();
+        assert_eq!(page.total, 150);
+        assert_eq!(page.page_count(), 2);
+        assert!(!page.has_next_page());
+        assert_eq!(page.items[0].title(), "Hundredth");
+        assert!(client.liked_tracks(2).unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn playlist_tracks_are_fetched_in_batches() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let tracks: Vec<_> = (0..250).map(|id| json!({"id": id})).collect();
+        server.mock(|when, then| {
+            when.method(GET).path("/users/7/playlists/1003");
+            then.status(200).json_body(json!({"result": {
+                "owner": {"uid": 7, "login": "friend"}, "kind": 1003,
+                "title": "Long one", "trackCount": 250, "tracks": tracks
+            }}));
+        });
+        let tracks_mock = server.mock(|when, then| {
+            when.method(POST).path("/tracks");
+            then.status(200).json_body(json!({"result": [
+                {"id": "1", "title": "Any", "durationMs": 1000}
+            ]}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let playlist = client.playlist(7, 1003).unwrap();
+
+        tracks_mock.assert_hits(2);
+        assert_eq!(playlist.playlist.title, "Long one");
+        assert_eq!(playlist.playlist.owner.login.as_deref(), Some("friend"));
+        assert_eq!(playlist.tracks.len(), 2);
+    }
+
+    #[test]
+    fn client_can_like_and_dislike_tracks() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let changes = [
+            "likes/tracks/add-multiple",
+            "likes/tracks/remove",
+            "dislikes/tracks/add-multiple",
+            "dislikes/tracks/remove",
+        ];
+        let mocks: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                server.mock(|when, then| {
+                    when.method(POST)
+                        .path(format!("/users/42/{}", change))
+                        .body("track-ids=1710808");
+                    then.status(200)
+                        .json_body(json!({"result": {"revision": 8}}));
+                })
+            })
+            .collect();
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        client.like_track("1710808").unwrap();
+        client.unlike_track("1710808").unwrap();
+        client.dislike_track("1710808").unwrap();
+        client.undislike_track("1710808").unwrap();
+
+        for mock in mocks {
+            mock.assert();
+        }
+    }
+
+    fn mock_road_trip(server: &MockServer, revision: u64) {
+        server.mock(|when, then| {
+            when.method(GET).path("/users/42/playlists/1000");
+            then.status(200).json_body(json!({"result": {
+                "owner": {"uid": 42}, "kind": 1000, "title": "Road trip", "revision": revision,
+                "tracks": [{"id": 1, "albumId": 10}, {"id": 2, "albumId": 20}, {"id": 3}]
+            }}));
+        });
+    }
+
+    #[test]
+    fn client_can_manage_playlists() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let create_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/users/42/playlists/create")
+                .body("title=Road+trip&visibility=private");
+            then.status(200).json_body(json!({"result": {
+                "owner": {"uid": 42}, "kind": 1000, "title": "Road trip", "revision": 1
+            }}));
+        });
+        let rename_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/users/42/playlists/1000/name")
+                .body("value=Long+road");
+            then.status(200).json_body(json!({"result": {
+                "owner": {"uid": 42}, "kind": 1000, "title": "Long road", "revision": 2
+            }}));
+        });
+        let delete_mock = server.mock(|when, then| {
+            when.method(POST).path("/users/42/playlists/1000/delete");
+            then.status(200).json_body(json!({"result": "ok"}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let playlist = client.create_playlist("Road trip", false).unwrap();
+        assert_eq!(playlist.kind, 1000);
+        assert_eq!(
+            client.rename_playlist(1000, "Long road").unwrap().title,
+            "Long road"
+        );
+        client.delete_playlist(1000).unwrap();
+
+        create_mock.assert();
+        rename_mock.assert();
+        delete_mock.assert();
+    }
+
+    #[test]
+    fn playlist_changes_are_sent_as_diff() {
+        let server = MockServer::start();
+        mock_account(&server);
+        mock_road_trip(&server, 5);
+        let change_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/users/42/playlists/1000/change-relative")
+                .x_www_form_urlencoded_tuple("kind", "1000")
+                .x_www_form_urlencoded_tuple("revision", "5")
+                .x_www_form_urlencoded_tuple(
+                    "diff",
+                    r#"[{"op":"delete","from":0,"to":1,"tracks":[{"id":"1","albumId":"10"}]},{"op":"insert","at":2,"tracks":[{"id":"1","albumId":"10"}]}]"#,
+                );
+            then.status(200).json_body(json!({"result": {
+                "owner": {"uid": 42}, "kind": 1000, "title": "Road trip", "revision": 6
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let playlist = client.move_track(1000, 0, 5).unwrap();
+
+        change_mock.assert();
+        assert_eq!(playlist.revision, 6);
+    }
+
+    #[test]
+    fn playlist_change_is_retried_on_revision_conflict() {
+        let server = MockServer::start();
+        mock_account(&server);
+        mock_road_trip(&server, 5);
+        let change_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/users/42/playlists/1000/change-relative")
+                .x_www_form_urlencoded_tuple(
+                    "diff",
+                    r#"[{"op":"insert","at":3,"tracks":[{"id":"4","albumId":null}]}]"#,
+                );
+            then.status(412).json_body(json!({"error": {
+                "name": "wrong-revision", "message": "Wrong revision"
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let track = Track::local(String::from("4"), "Fourth", std::time::Duration::ZERO, None);
+        let result = client.add_tracks(1000, &[track]);
+
+        assert!(matches!(result, Err(Error::RevisionConflict(_))));
+        change_mock.assert_hits(MAX_REVISION_RETRIES as usize + 1);
+    }
+
+    #[test]
+    fn removed_tracks_are_found_after_changes() {
+        let refs = |ids: &[&str]| -> Vec<TrackRef> {
+            ids.iter()
+                .map(|id| TrackRef {
+                    id: id.to_string(),
+                    album_id: None,
+                })
+                .collect()
+        };
+        let playlist = refs(&["1", "2", "3", "2", "3"]);
+
+        assert_eq!(find_tracks(&playlist, &refs(&["2", "3"]), 3), Some(3));
+        assert_eq!(find_tracks(&playlist, &refs(&["2", "3"]), 0), Some(1));
+        assert_eq!(find_tracks(&playlist, &refs(&["3", "1"]), 0), None);
+    }
+
+    #[test]
+    fn client_can_list_stations() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/rotor/stations/list");
+            then.status(200).json_body(json!({"result": [
+                {"station": {"id": {"type": "genre", "tag": "rock"}, "name": "Rock",
+                             "idForFrom": "genre-rock"}},
+                {"station": {"id": {"type": "mood", "tag": "calm"}, "name": "Calm",
+                             "idForFrom": "mood-calm"}}
+            ]}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let stations = client.stations().unwrap();
+
+        let names: Vec<_> = stations.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["My Wave", "Rock", "Calm"]);
+        assert_eq!(stations[1].id.to_string(), "genre:rock");
+    }
+
+    #[test]
+    fn client_can_get_station_tracks_after_queue() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let tracks_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/rotor/station/user:onyourwave/tracks")
+                .query_param("queue", "101");
+            then.status(200).json_body(json!({"result": {
+                "id": {"type": "user", "tag": "onyourwave"},
+                "batchId": "batch-2",
+                "sequence": [
+                    {"type": "track", "liked": false,
+                     "track": {"id": "102", "title": "Next", "durationMs": 1000}}
+                ]
+            }}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let batch = client
+            .station_tracks(&Station::my_wave().id, Some("101"))
+            .unwrap();
+
+        tracks_mock.assert();
+        assert_eq!(batch.batch_id, "batch-2");
+        assert_eq!(batch.tracks[0].title(), "Next");
+    }
+
+    #[test]
+    fn client_sends_station_feedback() {
+        let server = MockServer::start();
+        mock_account(&server);
+        let started_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/rotor/station/user:onyourwave/feedback")
+                .json_body_partial(r#"{"type": "radioStarted", "from": "user-onyourwave"}"#);
+            then.status(200).json_body(json!({"result": "ok"}));
+        });
+        let skip_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/rotor/station/user:onyourwave/feedback")
+                .query_param("batch-id", "batch-1")
+                .json_body_partial(
+                    r#"{"type": "skip", "trackId": "101", "totalPlayedSeconds": 2.5}"#,
+                );
+            then.status(200).json_body(json!({"result": "ok"}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        let station = Station::my_wave();
+        client
+            .station_feedback(&station, None, &Feedback::RadioStarted)
+            .unwrap();
+        let skip = Feedback::Skip {
+            track_id: String::from("101"),
+            played: std::time::Duration::from_millis(2500),
+        };
+        client
+            .station_feedback(&station, Some("batch-1"), &skip)
+            .unwrap();
+
+        started_mock.assert();
+        skip_mock.assert();
+    }
+
+    #[test]
+    fn client_can_list_user_library() {
+        let server = MockServer::start();
+        mock_account(&server);
+        server.mock(|when, then| {
+            when.method(GET).path("/users/42/playlists/list");
+            then.status(200).json_body(json!({"result": [
+                {"owner": {"uid": 42}, "kind": 1000, "title": "Road trip", "trackCount": 12}
+            ]}));
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/users/42/likes/albums")
+                .query_param("rich", "true");
+            then.status(200).json_body(json!({"result": [
+                {"timestamp": "2022-01-01", "album": {"id": 175233, "title": "Discovery"}}
+            ]}));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/users/42/likes/artists");
+            then.status(200).json_body(json!({"result": [
+                {"id": 27003, "name": "Daft Punk"}
+            ]}));
+        });
+
+        let client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+
+        let playlists = client.user_playlists().unwrap();
+        assert_eq!(playlists[0].title, "Road trip");
+        assert_eq!(playlists[0].track_count, 12);
+        assert_eq!(client.liked_albums().unwrap()[0].id, "175233");
+        assert_eq!(client.liked_artists().unwrap()[0].name, "Daft Punk");
+    }
+
+    #[test]
+    fn direct_link_is_parsed_and_signed() {
+        let link = DirectLink::parse(&download_info_xml("s1.storage.yandex.net")).unwrap();
+
+        assert_eq!(link.host, "s1.storage.yandex.net");
+        assert_eq!(link.path, "/rmusic/U2FsdGVk/file.mp3");
+        assert_eq!(link.ts, "0005e0b1a6c4e1d2");
+        assert_eq!(link.s, "a1b2c3d4");
+
+        let mut hasher = Md5::new();
+        hasher.update("XGRlBW9FXlekgbPrRHuSiArmusic/U2FsdGVk/file.mp3a1b2c3d4");
+        let sign = format!("{:x}", hasher.finalize());
+        assert_eq!(
+            link.url("https"),
+            format!(
+                "https://s1.storage.yandex.net/get-mp3/{}/0005e0b1a6c4e1d2/rmusic/U2FsdGVk/file.mp3",
+                sign
+            )
+        );
+    }
+
+    #[test]
+    fn track_can_be_downloaded() {
+        let server = MockServer::start();
+        mock_account(&server);
+        mock_track(&server, "201", "mock-download-track");
+        server.mock(|when, then| {
+            when.method(GET).path("/tracks/201/download-info");
+            then.status(200).json_body(json!({"result": [
+                {"codec": "mp3", "bitrateInKbps": 128, "preview": false,
+                 "downloadInfoUrl": server.url("/info/128")},
+                {"codec": "mp3", "bitrateInKbps": 320, "preview": false,
+                 "downloadInfoUrl": server.url("/info/320")}
+            ]}));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/info/320");
+            then.status(200)
+                .body(download_info_xml(&server.address().to_string()));
+        });
+        let file_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path_contains("/get-mp3/")
+                .path_contains("/0005e0b1a6c4e1d2/rmusic/U2FsdGVk/file.mp3")
+                .matches(|request| {
+                    let mut headers = request.headers.iter().flatten();
+                    !headers.any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+                });
+            then.status(200).body("fake mp3 payload");
+        });
+
+        let temp = TempDir::new("download");
+        let dir = temp.path();
+        let mut client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        client.set_cache(Cache::open(dir, 1 << 20).unwrap());
+        let track = client.tracks(&["201".to_string()]).unwrap().remove(0);
+        let filename = track.download().unwrap();
+        // The cached file is used the second time
+        assert_eq!(track.download().unwrap(), filename);
+
+        file_mock.assert();
+        let content = std::fs::read(&filename).unwrap();
+        let tags = Tags::read(Path::new(&filename)).unwrap();
+        assert_eq!(filename, dir.join("201-mp3-320.mp3").to_string_lossy());
+        // The tags are written in front of the audio
+        assert!(content.ends_with(b"fake mp3 payload"));
+        assert_eq!(tags.title, "mock-download-track");
+        assert_eq!(tags.track_id.as_deref(), Some("201"));
+    }
+
+    #[test]
+    fn track_is_downloaded_in_preferred_quality() {
+        let server = MockServer::start();
+        mock_account(&server);
+        mock_track(&server, "202", "mock-quality-track");
+        server.mock(|when, then| {
+            when.method(GET).path("/tracks/202/download-info");
+            then.status(200).json_body(json!({"result": [
+                {"codec": "mp3", "bitrateInKbps": 320, "preview": true,
+                 "downloadInfoUrl": server.url("/info/preview")},
+                {"codec": "mp3", "bitrateInKbps": 128, "preview": false,
+                 "downloadInfoUrl": server.url("/info/128")},
+                {"codec": "mp3", "bitrateInKbps": 320, "preview": false,
+                 "downloadInfoUrl": server.url("/info/320")}
+            ]}));
+        });
+        let info_mock = server.mock(|when, then| {
+            when.method(GET).path("/info/128");
+            then.status(200)
+                .body(download_info_xml(&server.address().to_string()));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path_contains("/get-mp3/");
+            then.status(200).body("ID3 fake mp3 payload");
+        });
+
+        let temp = TempDir::new("quality");
+        let dir = temp.path();
+        let mut client = Client::with_base_url(TOKEN, &server.base_url()).unwrap();
+        client.set_cache(Cache::open(dir, 1 << 20).unwrap());
+        client.set_quality_preference(QualityPreference {
+            lossless: true,
+            max_bitrate: Some(192),
+        });
+        let track = client.tracks(&["202".to_string()]).unwrap().remove(0);
+        let variants: Vec<String> = track
+            .download_variants()
+            .unwrap()
+            .iter()
+            .map(Quality::to_string)
+            .collect();
+        assert_eq!(variants, ["mp3-128", "mp3-320"]);
+
+        let filename = track.download().unwrap();
+        info_mock.assert();
+        assert_eq!(filename, dir.join("202-mp3-128.mp3").to_string_lossy());
+    }
+}
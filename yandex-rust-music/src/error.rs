@@ -0,0 +1,107 @@
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Authorization failed: {0}")]
+    Auth(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Unexpected response: {0}")]
+    InvalidResponse(String),
+    #[error("Cannot decode audio: {0}")]
+    Decode(String),
+    #[error("No audio device: {0}")]
+    NoAudioDevice(String),
+    #[error("Playlist was changed meanwhile: {0}")]
+    RevisionConflict(String),
+    #[error("Not available offline: {0}")]
+    Offline(String),
+    #[error("Cannot tag the file: {0}")]
+    Tag(String),
+    #[error("There is nothing to play")]
+    EmptyQueue,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "python")]
+    #[error("Python error: {0}")]
+    Python(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorInfo,
+}
+
+#[derive(Deserialize)]
+struct ErrorInfo {
+    name: String,
+    message: Option<String>,
+}
+
+impl From<ureq::Error> for Error {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Status(code, response) => {
+                let body = response.into_string().unwrap_or_default();
+                // The API describes failures as {"error": {"name": ..., "message": ...}}
+                let (name, message) = match serde_json::from_str::<ErrorResponse>(&body) {
+                    Ok(ErrorResponse { error }) => {
+                        let message = error.message.unwrap_or_else(|| error.name.clone());
+                        (error.name, message)
+                    }
+                    Err(_) => (String::new(), body),
+                };
+                match code {
+                    401 | 403 => Error::Auth(message),
+                    404 => Error::NotFound(message),
+                    412 => Error::RevisionConflict(message),
+                    _ if name == "wrong-revision" => Error::RevisionConflict(message),
+                    _ => Error::Network(format!("HTTP {}: {}", code, message)),
+                }
+            }
+            ureq::Error::Transport(transport) => Error::Network(transport.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::InvalidResponse(err.to_string())
+    }
+}
+
+impl From<id3::Error> for Error {
+    fn from(err: id3::Error) -> Self {
+        Error::Tag(err.to_string())
+    }
+}
+
+impl From<rodio::decoder::DecoderError> for Error {
+    fn from(err: rodio::decoder::DecoderError) -> Self {
+        Error::Decode(err.to_string())
+    }
+}
+
+impl From<rodio::StreamError> for Error {
+    fn from(err: rodio::StreamError) -> Self {
+        Error::NoAudioDevice(err.to_string())
+    }
+}
+
+impl From<rodio::PlayError> for Error {
+    fn from(err: rodio::PlayError) -> Self {
+        Error::NoAudioDevice(err.to_string())
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<pyo3::PyErr> for Error {
+    fn from(err: pyo3::PyErr) -> Self {
+        Error::Python(err.to_string())
+    }
+}
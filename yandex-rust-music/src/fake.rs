@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::album::{Album, AlbumWithTracks};
+use crate::artist::{Artist, ArtistInfo};
+use crate::backend::{Fetcher, MusicBackend};
+use crate::error::{Error, Result};
+use crate::page::Page;
+use crate::playlist::{
+    GeneratedPlaylist, GeneratedPlaylistType, Owner, Playlist, PlaylistWithTracks,
+};
+use crate::search::{SearchKind, SearchResult, SearchResults};
+use crate::station::{Feedback, Station, StationId, StationTracks};
+use crate::stream::Media;
+use crate::track::Track;
+
+/// In-memory backend serving tracks from local audio files,
+/// for running the application without network access
+#[derive(Default)]
+pub struct FakeBackend {
+    playlists: Vec<(GeneratedPlaylist, Vec<Track>)>,
+    files: HashMap<String, PathBuf>,
+    albums: Vec<Album>,
+    // The collections of the user are changed through the shared backend
+    user_playlists: Mutex<Vec<PlaylistWithTracks>>,
+    liked_tracks: Mutex<Vec<String>>,
+    disliked_tracks: Mutex<Vec<String>>,
+    liked_albums: Vec<String>,
+    liked_artists: Vec<String>,
+    feedback: Arc<Mutex<Vec<Feedback>>>,
+    // The files are fetched for the time instead of being on disk
+    download_delay: Option<Duration>,
+}
+
+// Tracks of a station batch
+const STATION_BATCH_SIZE: usize = 2;
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Following tracks are added to this playlist
+    pub fn add_playlist(&mut self, playlist_type: GeneratedPlaylistType, title: &str) {
+        let playlist = GeneratedPlaylist {
+            playlist_type,
+            title: title.to_string(),
+            description: None,
+            owner_uid: 0,
+            kind: self.playlists.len() as u64,
+        };
+        self.playlists.push((playlist, Vec::new()));
+    }
+
+    /// Following tracks are released on this album of the artist
+    pub fn add_album(&mut self, title: &str, artist: &str) {
+        let artist = self
+            .albums
+            .iter()
+            .flat_map(|album| &album.artists)
+            .find(|a| a.name == artist)
+            .cloned()
+            .unwrap_or_else(|| Artist {
+                id: format!("artist-{}", artist),
+                name: artist.to_string(),
+            });
+        self.albums.push(Album {
+            id: format!("album-{}", title),
+            title: title.to_string(),
+            year: None,
+            genre: None,
+            track_count: 0,
+            artists: vec![artist],
+            cover_uri: None,
+        });
+    }
+
+    /// Add a track to the last added playlist (the playlist of the day by default)
+    pub fn add_track(&mut self, title: &str, total_duration: Duration, path: impl Into<PathBuf>) {
+        if self.playlists.is_empty() {
+            self.add_playlist(
+                GeneratedPlaylistType::PlaylistOfTheDay,
+                "Playlist of the day",
+            );
+        }
+        let id = self.files.len().to_string();
+        self.files.insert(id.clone(), path.into());
+        let track = Track::local(id, title, total_duration, self.albums.last());
+        let (_, tracks) = self.playlists.last_mut().unwrap();
+        tracks.push(track);
+    }
+
+    /// Playlist of the user with already added tracks
+    pub fn add_user_playlist(&mut self, title: &str, track_titles: &[&str]) {
+        let tracks: Vec<Track> = track_titles
+            .iter()
+            .filter_map(|title| self.find_track(title))
+            .cloned()
+            .collect();
+        let playlist = Playlist {
+            owner: Owner {
+                uid: 0,
+                login: None,
+                name: None,
+            },
+            kind: self.user_playlists.get_mut().unwrap().len() as u64,
+            title: title.to_string(),
+            description: None,
+            track_count: tracks.len() as u32,
+            revision: 1,
+        };
+        self.user_playlists
+            .get_mut()
+            .unwrap()
+            .push(PlaylistWithTracks { playlist, tracks });
+    }
+
+    /// Likes are listed from the most recent one
+    pub fn add_liked_track(&mut self, title: &str) {
+        if let Some(track) = self.find_track(title) {
+            let id = track.id().to_string();
+            self.liked_tracks.get_mut().unwrap().insert(0, id);
+        }
+    }
+
+    pub fn add_liked_album(&mut self, title: &str) {
+        if let Some(album) = self.albums.iter().find(|album| album.title == title) {
+            let id = album.id.clone();
+            self.liked_albums.insert(0, id);
+        }
+    }
+
+    pub fn add_liked_artist(&mut self, name: &str) {
+        let id = self
+            .albums
+            .iter()
+            .flat_map(|album| &album.artists)
+            .find(|artist| artist.name == name)
+            .map(|artist| artist.id.clone());
+        if let Some(id) = id {
+            self.liked_artists.insert(0, id);
+        }
+    }
+
+    /// Take the time to fetch each file, as if it was downloaded
+    pub fn set_download_delay(&mut self, delay: Duration) {
+        self.download_delay = Some(delay);
+    }
+
+    /// Feedback sent to the stations, shared to be checked after the backend is moved
+    pub fn feedback(&self) -> Arc<Mutex<Vec<Feedback>>> {
+        Arc::clone(&self.feedback)
+    }
+
+    fn find_track(&self, title: &str) -> Option<&Track> {
+        self.all_tracks().find(|track| track.title == title)
+    }
+
+    fn all_tracks(&self) -> impl Iterator<Item = &Track> {
+        self.playlists.iter().flat_map(|(_, tracks)| tracks)
+    }
+
+    fn album_tracks(&self, album_id: &str) -> Vec<Track> {
+        let mut tracks: Vec<Track> = self
+            .all_tracks()
+            .filter(|track| track.album_id.as_deref() == Some(album_id))
+            .cloned()
+            .collect();
+        let mut ids = HashSet::new();
+        tracks.retain(|track| ids.insert(track.id().to_string()));
+        tracks
+    }
+}
+
+impl MusicBackend for FakeBackend {
+    fn generated_playlists(&self) -> Result<Vec<GeneratedPlaylist>> {
+        Ok(self
+            .playlists
+            .iter()
+            .map(|(playlist, _)| playlist.clone())
+            .collect())
+    }
+
+    fn generated_playlist_tracks(&self, playlist: &GeneratedPlaylist) -> Result<Vec<Track>> {
+        self.playlists
+            .iter()
+            .find(|(p, _)| p.kind == playlist.kind)
+            .map(|(_, tracks)| tracks.clone())
+            .ok_or_else(|| Error::NotFound(format!("No playlist {}", playlist.title)))
+    }
+
+    /// Only tracks are searched, by a case insensitive match of the title
+    fn search(&self, query: &str, kind: SearchKind, page: u32) -> Result<SearchResult> {
+        let query = query.to_lowercase();
+        let mut tracks = SearchResults::default();
+        if page == 0 && matches!(kind, SearchKind::All | SearchKind::Track) {
+            tracks.results = self
+                .all_tracks()
+                .filter(|track| track.title().to_lowercase().contains(&query))
+                .cloned()
+                .collect();
+            tracks.total = tracks.results.len() as u32;
+            tracks.per_page = tracks.total;
+        }
+
+        Ok(SearchResult {
+            text: query,
+            page,
+            tracks,
+            albums: SearchResults::default(),
+            artists: SearchResults::default(),
+            playlists: SearchResults::default(),
+        })
+    }
+
+    fn album(&self, album_id: &str) -> Result<AlbumWithTracks> {
+        let album = self
+            .albums
+            .iter()
+            .find(|album| album.id == album_id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("No album {}", album_id)))?;
+
+        Ok(AlbumWithTracks {
+            album,
+            volumes: vec![self.album_tracks(album_id)],
+        })
+    }
+
+    /// All tracks of the artist are popular
+    fn artist(&self, artist_id: &str) -> Result<ArtistInfo> {
+        let albums: Vec<Album> = self
+            .albums
+            .iter()
+            .filter(|album| album.artists.iter().any(|a| a.id == artist_id))
+            .cloned()
+            .collect();
+        let artist = albums
+            .iter()
+            .flat_map(|album| &album.artists)
+            .find(|a| a.id == artist_id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("No artist {}", artist_id)))?;
+        let popular_tracks = albums
+            .iter()
+            .flat_map(|album| self.album_tracks(&album.id))
+            .collect();
+
+        Ok(ArtistInfo {
+            artist,
+            popular_tracks,
+            albums,
+        })
+    }
+
+    fn playlist(&self, _owner_uid: u64, kind: u64) -> Result<PlaylistWithTracks> {
+        self.user_playlists
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.playlist.kind == kind)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("No playlist {}", kind)))
+    }
+
+    fn add_to_playlist(&self, kind: u64, tracks: &[Track]) -> Result<Playlist> {
+        let mut playlists = self.user_playlists.lock().unwrap();
+        let playlist = playlists
+            .iter_mut()
+            .find(|p| p.playlist.kind == kind)
+            .ok_or_else(|| Error::NotFound(format!("No playlist {}", kind)))?;
+        playlist.tracks.extend_from_slice(tracks);
+        playlist.playlist.track_count = playlist.tracks.len() as u32;
+        playlist.playlist.revision += 1;
+
+        Ok(playlist.playlist.clone())
+    }
+
+    fn user_playlists(&self) -> Result<Vec<Playlist>> {
+        Ok(self
+            .user_playlists
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|p| p.playlist.clone())
+            .collect())
+    }
+
+    /// All liked tracks are on the first page
+    fn liked_tracks(&self, page: u32) -> Result<Page<Track>> {
+        let liked = self.liked_tracks.lock().unwrap();
+        let items: Vec<Track> = if page == 0 {
+            liked
+                .iter()
+                .filter_map(|id| self.all_tracks().find(|track| &track.id == id))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let total = liked.len() as u32;
+
+        Ok(Page {
+            items,
+            page,
+            per_page: total,
+            total,
+        })
+    }
+
+    fn liked_track_ids(&self) -> Result<Vec<String>> {
+        Ok(self.liked_tracks.lock().unwrap().clone())
+    }
+
+    /// Unknown ids are left out
+    fn tracks(&self, track_ids: &[String]) -> Result<Vec<Track>> {
+        Ok(track_ids
+            .iter()
+            .filter_map(|id| self.all_tracks().find(|track| &track.id == id))
+            .cloned()
+            .collect())
+    }
+
+    fn like_track(&self, track: &Track) -> Result<()> {
+        self.undislike_track(track)?;
+        let mut liked = self.liked_tracks.lock().unwrap();
+        if !liked.contains(&track.id) {
+            liked.insert(0, track.id.clone());
+        }
+        Ok(())
+    }
+
+    fn unlike_track(&self, track: &Track) -> Result<()> {
+        self.liked_tracks
+            .lock()
+            .unwrap()
+            .retain(|id| id != &track.id);
+        Ok(())
+    }
+
+    /// Disliked tracks are not liked anymore, like in the service
+    fn dislike_track(&self, track: &Track) -> Result<()> {
+        self.unlike_track(track)?;
+        let mut disliked = self.disliked_tracks.lock().unwrap();
+        if !disliked.contains(&track.id) {
+            disliked.push(track.id.clone());
+        }
+        Ok(())
+    }
+
+    fn undislike_track(&self, track: &Track) -> Result<()> {
+        self.disliked_tracks
+            .lock()
+            .unwrap()
+            .retain(|id| id != &track.id);
+        Ok(())
+    }
+
+    fn liked_albums(&self) -> Result<Vec<Album>> {
+        Ok(self
+            .liked_albums
+            .iter()
+            .filter_map(|id| self.albums.iter().find(|album| &album.id == id))
+            .cloned()
+            .collect())
+    }
+
+    fn liked_artists(&self) -> Result<Vec<Artist>> {
+        Ok(self
+            .liked_artists
+            .iter()
+            .filter_map(|id| {
+                self.albums
+                    .iter()
+                    .flat_map(|album| &album.artists)
+                    .find(|artist| &artist.id == id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// My Wave only
+    fn stations(&self) -> Result<Vec<Station>> {
+        Ok(vec![Station::my_wave()])
+    }
+
+    /// All tracks are played in a loop
+    fn station_tracks(
+        &self,
+        station: &StationId,
+        last_track_id: Option<&str>,
+    ) -> Result<StationTracks> {
+        let mut ids = HashSet::new();
+        let tracks: Vec<&Track> = self
+            .all_tracks()
+            .filter(|track| ids.insert(track.id()))
+            .collect();
+        if tracks.is_empty() {
+            return Ok(StationTracks {
+                batch_id: format!("{}-empty", station),
+                tracks: Vec::new(),
+            });
+        }
+        let start = last_track_id
+            .and_then(|id| tracks.iter().position(|track| track.id() == id))
+            .map_or(0, |last| last + 1);
+
+        Ok(StationTracks {
+            batch_id: format!("batch-{}", start),
+            tracks: (start..start + STATION_BATCH_SIZE)
+                .map(|i| tracks[i % tracks.len()].clone())
+                .collect(),
+        })
+    }
+
+    fn station_feedback(
+        &self,
+        _station: &Station,
+        _batch_id: Option<&str>,
+        feedback: &Feedback,
+    ) -> Result<()> {
+        self.feedback.lock().unwrap().push(feedback.clone());
+        Ok(())
+    }
+
+    fn download(&self, track: &Track) -> Result<String> {
+        if let Some(delay) = self.download_delay {
+            std::thread::sleep(delay);
+        }
+        file_of(&self.files, track)
+    }
+
+    fn local_file(&self, track: &Track) -> Option<String> {
+        match self.download_delay {
+            Some(_) => None,
+            None => file_of(&self.files, track).ok(),
+        }
+    }
+
+    fn fetcher(&self) -> Fetcher {
+        let files = self.files.clone();
+        let delay = self.download_delay;
+        Arc::new(move |track: &Track| {
+            if let Some(delay) = delay {
+                std::thread::sleep(delay);
+            }
+            file_of(&files, track).map(Media::File)
+        })
+    }
+}
+
+fn file_of(files: &HashMap<String, PathBuf>, track: &Track) -> Result<String> {
+    let path = files
+        .get(track.id())
+        .ok_or_else(|| Error::NotFound(format!("No audio file for {}", track.title())))?;
+    Ok(path.to_string_lossy().into_owned())
+}
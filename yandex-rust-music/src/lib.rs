@@ -1,126 +1,70 @@
-use pyo3::prelude::*;
-use rand::{thread_rng, Rng};
-
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+mod album;
+mod artist;
+mod backend;
+mod cache;
+mod client;
+mod error;
+mod fake;
+mod loudness;
+mod offline;
+mod page;
+mod playlist;
+#[cfg(feature = "python")]
+pub mod python;
+mod search;
+mod source;
+mod station;
+mod stream;
+mod tags;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod track;
+
+pub use album::{Album, AlbumWithTracks};
+pub use artist::{Artist, ArtistInfo};
+pub use backend::{Fetcher, MusicBackend};
+pub use cache::{Cache, Usage};
+pub use client::Client;
+pub use error::{Error, Result};
+pub use fake::FakeBackend;
+pub use loudness::{integrated_loudness, LoudnessCache, Normalization, TARGET_LOUDNESS};
+pub use offline::{OfflineBackend, OfflineStore};
+pub use page::Page;
+pub use playlist::{GeneratedPlaylist, GeneratedPlaylistType, Owner, Playlist, PlaylistWithTracks};
+pub use search::{SearchKind, SearchResult, SearchResults};
+pub use station::{Feedback, Station, StationCategory, StationId, StationTracks};
+pub use stream::{Media, Stream, StreamReader};
+pub use tags::{Cover, Tags};
+pub use track::{Quality, QualityPreference, Track};
+
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+use source::{Amplified, Crossfaded, EndMark, Gain, Handoff, Next, NextSlot, Tracked};
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::time::{Duration, Instant};
 
-use eyre::Result;
-
-#[derive(Clone)]
-pub struct Client {
-    client_py: PyObject,
-}
+/// Loudness range of the volume control: the lowest volume above zero is this much quieter
+const VOLUME_RANGE_DB: f32 = 60.0;
 
-impl Client {
-    pub fn new(token: &str) -> Self {
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-        let yandex_music_py = PyModule::import(py, "yandex_music").unwrap();
-        let client_class_py = yandex_music_py.getattr("Client").unwrap();
-        let client_py = client_class_py.call1(((token),)).unwrap();
-        client_py.call_method0("init").unwrap();
+/// Longest crossfade between sounds
+pub const MAX_CROSSFADE: Duration = Duration::from_secs(12);
 
-        Self {
-            client_py: client_py.into(),
-        }
-    }
+/// Range of the gain added to normalized tracks
+pub const PRE_AMP_RANGE_DB: f32 = 12.0;
 
-    pub fn get_random_track(&self) -> Track {
-        let playlist = self.playlist_of_the_day();
-        let random_track_num = thread_rng().gen_range(0..playlist.len());
+/// Loudness and peak of a track measured in the background, None for silence
+type Measured = (Track, Option<(f32, f32)>);
 
-        playlist[random_track_num].clone()
+/// Amplitude of a perceived volume from 0 to 1, equal volume steps change loudness by equal dB
+fn amplitude(volume: f32) -> f32 {
+    if volume <= 0.0 {
+        0.0
+    } else {
+        10f32.powf((volume.min(1.0) - 1.0) * VOLUME_RANGE_DB / 20.0)
     }
-
-    pub fn playlist_of_the_day(&self) -> Vec<Track> {
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-        let clone_client_py = self.client_py.clone_ref(py);
-        let ref_client_py = clone_client_py.as_ref(py);
-
-        let feed_py = ref_client_py.call_method0("feed").unwrap();
-        let generated_playlists_py = feed_py.getattr("generated_playlists").unwrap();
-        // TODO: hard-code playlist of the day - update to extract playlist of the day
-        let playlist_of_the_day_py = generated_playlists_py.get_item(2).unwrap();
-        let playlist_py = playlist_of_the_day_py.getattr("data").unwrap();
-        let track_count_py = playlist_py.getattr("track_count").unwrap();
-        let track_count = track_count_py.extract::<usize>().unwrap();
-
-        let mut tracks = Vec::new();
-        for i in 0..track_count {
-            let tracks_short_py = playlist_py.getattr("tracks").unwrap();
-            let track_short_py = tracks_short_py.get_item(i).unwrap();
-            let track_py = track_short_py.call_method0("fetch_track").unwrap();
-
-            let title_py = track_py.getattr("title").unwrap();
-            let title = title_py.extract::<&str>().unwrap();
-
-            let total_duration_py = track_py.getattr("duration_ms").unwrap();
-            let total_duration_ms = total_duration_py.extract::<u64>().unwrap();
-            tracks.push(Track {
-                title: String::from(title),
-                total_duration: Duration::from_millis(total_duration_ms),
-                track_py: track_py.into(),
-            });
-        }
-
-        tracks
-    }
-}
-
-#[derive(Clone)]
-pub struct Track {
-    title: String,
-    total_duration: Duration,
-    track_py: PyObject,
 }
 
-impl Track {
-    pub fn download(&self) -> String {
-        let filename = self.title.clone() + ".mp3";
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-        let clone_track_py = self.track_py.clone_ref(py);
-        let ref_track_py = clone_track_py.as_ref(py);
-        if !Path::new(&filename).exists() {
-            // TODO set bitrait from settings
-            ref_track_py
-                .call_method("download", (&filename, "mp3", 320), None)
-                .unwrap();
-        }
-
-        filename
-    }
-
-    // FIXME: not going to work this way
-    pub async fn download_async(&self) -> String {
-        let filename = self.title.clone() + ".mp3";
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-        let clone_track_py = self.track_py.clone_ref(py);
-        let ref_track_py = clone_track_py.as_ref(py);
-        if !Path::new(&filename).exists() {
-            // TODO set bitrait from settings
-            let coroutine = ref_track_py
-                .call_method("download_async", (&filename, "mp3", 320), None)
-                .unwrap();
-        }
-
-        filename
-    }
-
-    pub fn total_duration(&self) -> Option<Duration> {
-        let total_duration = self.total_duration;
-        Some(total_duration)
-    }
-
-    pub fn title(&self) -> String {
-        self.title.clone()
-    }
-}
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Status {
     Playing(Instant, Duration),
@@ -129,45 +73,357 @@ pub enum Status {
 }
 
 impl Status {
-    pub fn elapsed(self) -> Duration {
+    pub fn elapsed(self) -> Result<Duration> {
         match self {
-            Status::Paused(d) => d,
-            Status::Playing(start, extra) => start.elapsed() + extra,
-            Status::Empty => panic!("Cannot take elapsed time if there is nothing to play"),
+            Status::Paused(d) => Ok(d),
+            Status::Playing(start, extra) => Ok(start.elapsed() + extra),
+            Status::Empty => Err(Error::EmptyQueue),
         }
     }
 
-    pub fn pause(&mut self) {
+    pub fn pause(&mut self) -> Result<()> {
         *self = match *self {
             Status::Paused(_) => *self,
             Status::Playing(start, extra) => Status::Paused(start.elapsed() + extra),
-            Status::Empty => panic!("Cannot pause a song if there is nothing to play"),
+            Status::Empty => return Err(Error::EmptyQueue),
         };
+        Ok(())
     }
 
-    pub fn play(&mut self) {
+    pub fn play(&mut self) -> Result<()> {
         *self = match *self {
             Status::Playing(_, _) => *self,
             Status::Paused(duration) => Status::Playing(Instant::now(), duration),
-            Status::Empty => panic!("Cannot play a song if there is nothing to play"),
+            Status::Empty => return Err(Error::EmptyQueue),
         };
+        Ok(())
+    }
+
+    pub fn seek(&mut self, position: Duration) -> Result<()> {
+        *self = match *self {
+            Status::Playing(_, _) => Status::Playing(Instant::now(), position),
+            Status::Paused(_) => Status::Paused(position),
+            Status::Empty => return Err(Error::EmptyQueue),
+        };
+        Ok(())
+    }
+}
+
+/// Sound appended to the sink
+struct Sound {
+    // Decoded again to seek
+    media: Media,
+    ended: EndMark,
+    // The sound queued after this one is faded in over its tail
+    next: NextSlot,
+    // How long it was faded in over the previous sound
+    fade: Duration,
+    // The track played, its loudness is found again when the normalization changes
+    track: Option<Track>,
+    gain: Gain,
+}
+
+impl Sound {
+    fn ended(&self) -> Option<Instant> {
+        *self.ended.lock().unwrap()
     }
 }
 
 pub struct Player {
     sink: rodio::Sink,
-    _stream: OutputStream,
-    _stream_handle: OutputStreamHandle,
+    // None for a headless player
+    stream: Option<(OutputStream, OutputStreamHandle)>,
     status: Status,
+    // Sounds in the sink, the first one is playing
+    sounds: VecDeque<Sound>,
+    // Perceived volume from 0 to 1
+    volume: f32,
+    muted: bool,
+    crossfade: Duration,
+    normalization: Normalization,
+    pre_amp_db: f32,
+    loudness: LoudnessCache,
+    // Measured loudness is saved there if it is set
+    loudness_path: Option<PathBuf>,
+    // Tracks are measured in the background, the results come back here
+    measured: (Sender<Measured>, Receiver<Measured>),
+    measuring: HashSet<String>,
 }
 
 unsafe impl Send for Player {}
 
 impl Player {
-    pub fn append(&mut self, filename: &str) {
-        // Load a sound from a file, using a path relative to Cargo.toml
-        let file = BufReader::new(File::open(filename).unwrap());
-        let source = Decoder::new(file).unwrap();
+    /// Player without an output device: audio is consumed in real time
+    /// by a background thread, so it can run on machines with no sound card
+    pub fn headless() -> Self {
+        Self {
+            sink: Self::headless_sink(),
+            stream: None,
+            status: Status::Empty,
+            sounds: VecDeque::new(),
+            volume: 1.0,
+            muted: false,
+            crossfade: Duration::ZERO,
+            normalization: Normalization::Off,
+            pre_amp_db: 0.0,
+            loudness: LoudnessCache::default(),
+            loudness_path: None,
+            measured: mpsc::channel(),
+            measuring: HashSet::new(),
+        }
+    }
+
+    fn headless_sink() -> Sink {
+        let (sink, mut output) = Sink::new_idle();
+        std::thread::spawn(move || loop {
+            // Pull 10ms worth of samples, the queue ends once the sink is dropped
+            let chunk = output.sample_rate() as usize * output.channels() as usize / 100;
+            if output.by_ref().take(chunk).count() < chunk {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        });
+
+        sink
+    }
+
+    pub fn new() -> Result<Self> {
+        let (stream, stream_handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&stream_handle)?;
+        Ok(Self {
+            sink,
+            stream: Some((stream, stream_handle)),
+            status: Status::Empty,
+            sounds: VecDeque::new(),
+            volume: 1.0,
+            muted: false,
+            crossfade: Duration::ZERO,
+            normalization: Normalization::Off,
+            pre_amp_db: 0.0,
+            loudness: LoudnessCache::default(),
+            loudness_path: None,
+            measured: mpsc::channel(),
+            measuring: HashSet::new(),
+        })
+    }
+
+    fn new_sink(&self) -> Result<Sink> {
+        let sink = match &self.stream {
+            Some((_, stream_handle)) => Sink::try_new(stream_handle)?,
+            None => Self::headless_sink(),
+        };
+        sink.set_volume(self.amplitude());
+        Ok(sink)
+    }
+
+    fn amplitude(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            amplitude(self.volume)
+        }
+    }
+
+    /// Perceived volume from 0 to 1, kept while muted
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        self.sink.set_volume(self.amplitude());
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn mute(&mut self) {
+        self.muted = true;
+        self.sink.set_volume(self.amplitude());
+    }
+
+    pub fn unmute(&mut self) {
+        self.muted = false;
+        self.sink.set_volume(self.amplitude());
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        self.crossfade
+    }
+
+    /// Fade the sounds appended from now on over the tail of the previous ones,
+    /// up to `MAX_CROSSFADE`. Zero plays them one after another without a gap
+    pub fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade.min(MAX_CROSSFADE);
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Adjust the volume of the tracks by their loudness, the playing ones included
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+        self.apply_gain();
+    }
+
+    /// Gain in dB added to normalized tracks
+    pub fn pre_amp(&self) -> f32 {
+        self.pre_amp_db
+    }
+
+    pub fn set_pre_amp(&mut self, db: f32) {
+        self.pre_amp_db = db.clamp(-PRE_AMP_RANGE_DB, PRE_AMP_RANGE_DB);
+        self.apply_gain();
+    }
+
+    /// Keep the loudness of the tracks measured before in the file
+    pub fn use_loudness_cache(&mut self, path: PathBuf) -> Result<()> {
+        self.loudness = LoudnessCache::load(&path)?;
+        self.loudness_path = Some(path);
+        Ok(())
+    }
+
+    /// Set the gain of the queued sounds, the playing one included
+    fn apply_gain(&mut self) {
+        let queued: Vec<(Option<Track>, Media, Gain)> = self
+            .sounds
+            .iter()
+            .map(|sound| (sound.track.clone(), sound.media.clone(), sound.gain.clone()))
+            .collect();
+        for (track, media, gain) in queued {
+            self.measure(track.as_ref(), &media);
+            gain.set(self.gain(track.as_ref()));
+        }
+    }
+
+    /// Amplitude bringing the track to the target loudness, 1 until it is known
+    fn gain(&mut self, track: Option<&Track>) -> f32 {
+        let track = match track {
+            Some(track) if self.normalization != Normalization::Off => track,
+            _ => return 1.0,
+        };
+        self.loudness.loudness(track).map_or(1.0, |loudness| {
+            loudness.gain(self.normalization, self.pre_amp_db)
+        })
+    }
+
+    /// Measure the track the service has not measured in the background, it takes decoding
+    /// all of the file. Files still being downloaded are measured once they are complete
+    fn measure(&mut self, track: Option<&Track>, media: &Media) {
+        let track = match track {
+            Some(track) if self.normalization != Normalization::Off => track,
+            _ => return,
+        };
+        if track.loudness.is_some()
+            || self.loudness.get(&track.id).is_some()
+            || !self.measuring.insert(track.id.clone())
+        {
+            return;
+        }
+        let (sender, track, media) = (self.measured.0.clone(), track.clone(), media.clone());
+        std::thread::spawn(move || {
+            let downloaded = match &media {
+                Media::Stream(stream) => stream.wait().is_ok(),
+                Media::File(_) => true,
+            };
+            let loudness = downloaded
+                .then(|| media.decode().ok().and_then(loudness::measure))
+                .flatten();
+            // The player is gone if it cannot be sent
+            let _ = sender.send((track, loudness));
+        });
+    }
+
+    /// Keep the loudness measured in the background and adjust the sounds by it
+    fn receive_loudness(&mut self) {
+        let mut changed = false;
+        while let Ok((track, loudness)) = self.measured.1.try_recv() {
+            self.measuring.remove(&track.id);
+            if let Some((loudness, peak)) = loudness {
+                self.loudness.insert(&track, loudness, Some(peak));
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+        if let Some(path) = &self.loudness_path {
+            // It is measured again next time if it cannot be saved
+            let _ = self.loudness.save(path);
+        }
+        self.apply_gain();
+    }
+
+    /// Decode the media from the position, the crossfade is read ahead to fade the tail
+    fn load(
+        &mut self,
+        media: Media,
+        position: Duration,
+        fade: Duration,
+        track: Option<Track>,
+    ) -> Result<(Next, Sound)> {
+        self.measure(track.as_ref(), &media);
+        let gain = Gain::new(self.gain(track.as_ref()));
+        // A streamed file is decoded as it is downloaded
+        let source = Amplified::new(media.decode()?.skip_duration(position), gain.clone());
+        let sound = Sound {
+            media,
+            ended: EndMark::default(),
+            next: NextSlot::default(),
+            fade,
+            track,
+            gain,
+        };
+        let source = Crossfaded::new(
+            source,
+            self.crossfade,
+            sound.next.clone(),
+            sound.ended.clone(),
+        );
+        Ok((Box::new(Tracked::new(source, sound.ended.clone())), sound))
+    }
+
+    /// Queue the sound after the previous one, faded in over it if it is not over yet
+    fn queue(&mut self, source: Next, sound: &Sound) {
+        match self.sounds.back() {
+            Some(previous) if !sound.fade.is_zero() => {
+                let slot = previous.next.clone();
+                self.sink.append(Handoff::new(source, sound.fade, slot));
+            }
+            _ => self.sink.append(source),
+        }
+    }
+
+    /// Queue the sound, the first one is paused until `play`,
+    /// the next ones are crossfaded with the previous one
+    pub fn append(&mut self, media: impl Into<Media>) -> Result<()> {
+        self.append_with_fade(media.into(), self.crossfade, None)
+    }
+
+    /// Queue the sound to follow the previous one without a gap, as tracks of an album do
+    pub fn append_gapless(&mut self, media: impl Into<Media>) -> Result<()> {
+        self.append_with_fade(media.into(), Duration::ZERO, None)
+    }
+
+    /// Queue the track, its volume is normalized by its loudness
+    pub fn append_track(&mut self, track: &Track, media: impl Into<Media>) -> Result<()> {
+        self.append_with_fade(media.into(), self.crossfade, Some(track.clone()))
+    }
+
+    /// Queue the track to follow the previous one without a gap
+    pub fn append_track_gapless(&mut self, track: &Track, media: impl Into<Media>) -> Result<()> {
+        self.append_with_fade(media.into(), Duration::ZERO, Some(track.clone()))
+    }
+
+    fn append_with_fade(
+        &mut self,
+        media: Media,
+        fade: Duration,
+        track: Option<Track>,
+    ) -> Result<()> {
+        let (source, sound) = self.load(media, Duration::ZERO, fade, track)?;
         // FIXME: for some reason
         // we cannot get duration from Source here
         // self.current_sound = Some(Sound {
@@ -175,40 +431,139 @@ impl Player {
         //         .total_duration()
         //         .expect("Cannot get duration of source"),
         // });
-        if self.sink.empty() {
+        self.drop_ended();
+        if self.sounds.is_empty() {
             self.status = Status::Paused(Duration::from_secs(0));
+            if !self.sink.is_paused() {
+                self.sink.pause();
+            }
         }
 
-        self.sink.append(source);
-        if !self.sink.is_paused() {
-            self.sink.pause();
+        self.queue(source, &sound);
+        self.sounds.push_back(sound);
+        Ok(())
+    }
+
+    /// Forget the sounds that are over, the time of the next one is counted from that moment
+    fn drop_ended(&mut self) {
+        while let Some(ended) = self.sounds.front().and_then(Sound::ended) {
+            self.sounds.pop_front();
+            self.status = match self.status {
+                _ if self.sounds.is_empty() => Status::Empty,
+                Status::Playing(_, _) => Status::Playing(ended, Duration::ZERO),
+                _ => Status::Paused(Duration::ZERO),
+            };
         }
     }
 
-    pub fn play(&mut self) {
-        if !self.sink.empty() {
-            self.sink.play();
-            self.status.play();
-        } else {
-            panic!("There is nothing to play in Player");
+    /// Files of the sounds in the sink, they are opened again to seek
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.sounds
+            .iter()
+            .map(|sound| sound.media.path().to_path_buf())
+            .collect()
+    }
+
+    /// Number of sounds in the sink, the playing one included
+    pub fn queued(&mut self) -> usize {
+        self.drop_ended();
+        self.sounds.len()
+    }
+
+    pub fn play(&mut self) -> Result<()> {
+        if self.sink.empty() {
+            return Err(Error::EmptyQueue);
         }
+        self.sink.play();
+        self.status.play()
     }
 
-    pub fn pause(&mut self) {
-        if !self.sink.empty() {
-            self.sink.pause();
-            self.status.pause();
-        } else {
-            panic!("There is nothing to pause in Player");
+    pub fn pause(&mut self) -> Result<()> {
+        if self.sink.empty() {
+            return Err(Error::EmptyQueue);
+        }
+        self.sink.pause();
+        self.status.pause()
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        self.sink.stop();
+        self.sink = self.new_sink()?;
+        self.status = Status::Empty;
+        self.sounds.clear();
+        Ok(())
+    }
+
+    /// Continue the current sound from the position, it is over if the position is past its end
+    pub fn seek(&mut self, position: Duration) -> Result<()> {
+        self.reload(position, true)
+    }
+
+    /// Keep only the current sound, the ones queued after it are dropped
+    pub fn drop_queued(&mut self) -> Result<()> {
+        if self.queued() < 2 {
+            return Ok(());
         }
+        let elapsed = self.status.elapsed()?;
+        self.reload(elapsed, false)
     }
 
-    pub fn stop(&mut self) {
+    /// Decode the sounds again into a new sink as rodio cannot seek or remove queued sounds
+    fn reload(&mut self, position: Duration, keep_queued: bool) -> Result<()> {
+        if self.status()? == Status::Empty {
+            return Err(Error::EmptyQueue);
+        }
+        let count = if keep_queued { self.sounds.len() } else { 1 };
+        let queued: Vec<(Media, Duration, Option<Track>)> = self
+            .sounds
+            .iter()
+            .take(count)
+            .map(|sound| (sound.media.clone(), sound.fade, sound.track.clone()))
+            .collect();
+        let mut sources = Vec::with_capacity(count);
+        for (i, (media, fade, track)) in queued.into_iter().enumerate() {
+            let (start, fade) = if i == 0 {
+                (position, Duration::ZERO)
+            } else {
+                (Duration::ZERO, fade)
+            };
+            sources.push(self.load(media, start, fade, track)?);
+        }
+
         self.sink.stop();
-        *self = Self::default();
+        self.sink = self.new_sink()?;
+        if matches!(self.status, Status::Paused(_)) {
+            self.sink.pause();
+        }
+        self.sounds.clear();
+        for (source, sound) in sources {
+            self.queue(source, &sound);
+            self.sounds.push_back(sound);
+        }
+        self.status.seek(position)
+    }
+
+    /// Seek no further than the end of the track, the next one is played from there
+    pub fn seek_forward(&mut self, offset: Duration) -> Result<()> {
+        let elapsed = self.status()?.elapsed()?;
+        let length = self
+            .sounds
+            .front()
+            .and_then(|sound| sound.track.as_ref())
+            .and_then(Track::total_duration)
+            .filter(|length| !length.is_zero());
+        let position = elapsed + offset;
+        self.seek(length.map_or(position, |length| position.min(length)))
+    }
+
+    pub fn seek_backward(&mut self, offset: Duration) -> Result<()> {
+        let elapsed = self.status()?.elapsed()?;
+        self.seek(elapsed.saturating_sub(offset))
     }
 
     pub fn status(&mut self) -> Result<Status> {
+        self.receive_loudness();
+        self.drop_ended();
         if self.sink.empty() && self.status != Status::Empty {
             self.status = Status::Empty
         }
@@ -217,22 +572,11 @@ impl Player {
     }
 }
 
-impl Default for Player {
-    fn default() -> Self {
-        let (stream, stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
-        Self {
-            sink,
-            _stream: stream,
-            _stream_handle: stream_handle,
-            status: Status::Empty,
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{serve, TempDir};
+    use std::fs::File;
     use std::io::Read;
     fn create_client() -> Client {
         let mut token_file = File::open("../token").unwrap();
@@ -240,27 +584,27 @@ mod tests {
         let _ = token_file.read_to_string(&mut token);
         let _ = token.pop();
 
-        Client::new(&token)
+        Client::new(&token).unwrap()
     }
 
     #[test]
     fn it_works() {
         let client = create_client();
-        let track = client.get_random_track();
-        println!("Track name = {}", track.title);
+        let track = client.get_random_track().unwrap();
+        println!("Track name = {}", track.title());
 
-        let local_track_path = track.download();
+        let local_track_path = track.download().unwrap();
 
-        let mut player = Player::default();
-        player.append(&local_track_path);
-        player.play();
+        let mut player = Player::new().unwrap();
+        player.append(local_track_path).unwrap();
+        player.play().unwrap();
         let status = player.status();
         assert!(status.is_ok());
         println!("Started playing...");
 
         std::thread::sleep(std::time::Duration::from_secs(1));
 
-        player.pause();
+        player.pause().unwrap();
         let status = player.status();
         assert!(status.is_ok());
     }
@@ -268,15 +612,15 @@ mod tests {
     #[test]
     fn track_can_get_total_duration() {
         let client = create_client();
-        let track = client.get_random_track();
+        let track = client.get_random_track().unwrap();
         let total_duration: Duration = track.total_duration().unwrap();
         assert!(total_duration.as_secs() > 60);
     }
 
     #[test]
     fn client_can_get_playlist_of_the_day() {
-        let client = Client::new("AQAAAAA59C-DAAG8Xn4u-YGNfkkqnBG_DcwEnjM");
-        let playlist = client.playlist_of_the_day();
+        let client = Client::new("AQAAAAA59C-DAAG8Xn4u-YGNfkkqnBG_DcwEnjM").unwrap();
+        let playlist = client.playlist_of_the_day().unwrap();
 
         assert_eq!(playlist.len(), 60);
     }
@@ -284,7 +628,7 @@ mod tests {
     #[test]
     fn status_can_pause_and_play() {
         let mut status = Status::Playing(Instant::now(), Duration::from_secs(0));
-        status.pause();
+        status.pause().unwrap();
 
         let duration = match status {
             Status::Paused(d) => d,
@@ -292,52 +636,243 @@ mod tests {
         };
 
         ::std::thread::sleep(Duration::from_secs(2));
-        status.play();
+        status.play().unwrap();
 
-        assert_eq!(status.elapsed().as_secs(), duration.as_secs());
+        assert_eq!(status.elapsed().unwrap().as_secs(), duration.as_secs());
+    }
+
+    #[test]
+    fn status_can_seek() {
+        let mut status = Status::Paused(Duration::from_secs(10));
+        status.seek(Duration::from_secs(3)).unwrap();
+        assert_eq!(status, Status::Paused(Duration::from_secs(3)));
+
+        status.play().unwrap();
+        status.seek(Duration::from_secs(30)).unwrap();
+        assert_eq!(status.elapsed().unwrap().as_secs(), 30);
+
+        assert!(Status::Empty.seek(Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn player_can_seek() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/fixtures/sine-440.wav"
+        );
+        let mut player = Player::headless();
+        player.append(fixture).unwrap();
+        player.play().unwrap();
+
+        player.seek(Duration::from_millis(600)).unwrap();
+        assert!(player.status().unwrap().elapsed().unwrap() >= Duration::from_millis(600));
+        player.seek_backward(Duration::from_secs(5)).unwrap();
+        assert!(player.status().unwrap().elapsed().unwrap() < Duration::from_millis(600));
+
+        player.seek_forward(Duration::from_secs(5)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(player.status().unwrap(), Status::Empty);
+    }
+
+    #[test]
+    fn player_plays_queued_sounds_without_gap() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/fixtures/sine-440.wav"
+        );
+        let mut player = Player::headless();
+        player.append(fixture).unwrap();
+        player.append(fixture).unwrap();
+        player.play().unwrap();
+        assert_eq!(player.queued(), 2);
+
+        player.seek(Duration::from_millis(800)).unwrap();
+        assert_eq!(player.queued(), 2);
+        std::thread::sleep(Duration::from_millis(400));
+
+        // The second sound is playing since the first one ended, about 200ms ago
+        assert_eq!(player.queued(), 1);
+        let elapsed = player.status().unwrap().elapsed().unwrap();
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+        assert!(matches!(player.status(), Ok(Status::Playing(..))));
+    }
+
+    #[test]
+    fn player_crossfades_queued_sounds() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/fixtures");
+        let mut player = Player::headless();
+        player.set_crossfade(Duration::from_secs(30));
+        assert_eq!(player.crossfade(), MAX_CROSSFADE);
+
+        player.set_crossfade(Duration::from_millis(400));
+        player.append(format!("{}/sine-440.wav", fixtures)).unwrap();
+        player.append(format!("{}/sine-660.wav", fixtures)).unwrap();
+        player.play().unwrap();
+        std::thread::sleep(Duration::from_millis(800));
+
+        // The second sound started to fade in 200ms ago, 400ms before the first one ended
+        assert_eq!(player.queued(), 1);
+        let elapsed = player.status().unwrap().elapsed().unwrap();
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+
+        std::thread::sleep(Duration::from_millis(1000));
+        assert_eq!(player.status().unwrap(), Status::Empty);
+    }
+
+    #[test]
+    fn player_normalizes_loudness_of_tracks() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/fixtures/sine-440.wav"
+        );
+        let temp = TempDir::new("gain");
+        let dir = temp.path();
+        let mut track = Track::local(String::from("sine"), "Sine", Duration::from_secs(1), None);
+        let mut player = Player::headless();
+        player
+            .use_loudness_cache(dir.join("loudness.json"))
+            .unwrap();
+        player.append_track(&track, fixture).unwrap();
+        assert!(player.loudness.get("sine").is_none());
+
+        // The track is measured in the background, its gain is set once it is known
+        player.set_normalization(Normalization::Track);
+        assert_eq!(player.sounds[0].gain.get(), 1.0);
+        let started = Instant::now();
+        while player.loudness.get("sine").is_none() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            player.status().unwrap();
+        }
+        let measured = player.loudness.get("sine").unwrap();
+        assert!(measured > -20.0 && measured < -10.0, "{}", measured);
+        assert_eq!(player.sounds[0].gain.get(), player.gain(Some(&track)));
+        assert_ne!(player.sounds[0].gain.get(), 1.0);
+        let restored = LoudnessCache::load(&dir.join("loudness.json")).unwrap();
+        assert_eq!(restored.get("sine"), Some(measured));
+
+        // The loudness measured by the service is preferred
+        track.loudness = Some(-20.0);
+        assert!((player.gain(Some(&track)) - 1.9953).abs() < 1e-3);
+        player.set_pre_amp(-20.0);
+        assert_eq!(player.pre_amp(), -PRE_AMP_RANGE_DB);
+        assert!((player.gain(Some(&track)) - 0.5012).abs() < 1e-3);
+        assert_eq!(player.gain(None), 1.0);
+    }
+
+    #[test]
+    fn player_measures_streamed_track_once_it_is_downloaded() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/fixtures/sine-440.wav"
+        );
+        let (url, _) = serve(std::fs::read(fixture).unwrap(), 16_000);
+        let dir = TempDir::new("measure-stream");
+        let stream = Stream::start(&url, dir.path().join("track.wav")).unwrap();
+        let track = Track::local(String::from("sine"), "Sine", Duration::from_secs(1), None);
+        let mut player = Player::headless();
+        player.set_normalization(Normalization::Track);
+        player.append_track(&track, stream.clone()).unwrap();
+        assert!(!stream.is_complete());
+
+        let started = Instant::now();
+        while player.loudness.get("sine").is_none() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            player.status().unwrap();
+        }
+        assert!(stream.is_complete());
+        assert!(player.loudness.get("sine").is_some());
+        assert_ne!(player.sounds[0].gain.get(), 1.0);
+    }
+
+    #[test]
+    fn player_can_drop_queued_sounds() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/fixtures/sine-440.wav"
+        );
+        let mut player = Player::headless();
+        player.append(fixture).unwrap();
+        player.append(fixture).unwrap();
+        player.drop_queued().unwrap();
+        assert_eq!(player.queued(), 1);
+        assert_eq!(player.status().unwrap(), Status::Paused(Duration::ZERO));
+    }
+
+    #[test]
+    fn volume_is_perceptual() {
+        assert_eq!(amplitude(0.0), 0.0);
+        assert_eq!(amplitude(1.0), 1.0);
+        // Half of the range is 30 dB quieter
+        assert!((amplitude(0.5) - 0.0316).abs() < 0.0001);
+        let ratio = amplitude(0.5) / amplitude(0.25);
+        assert!((amplitude(0.75) / amplitude(0.5) - ratio).abs() < 0.001);
+    }
+
+    #[test]
+    fn player_keeps_volume_for_next_sounds() {
+        let mut player = Player::headless();
+        player.set_volume(1.5);
+        assert_eq!(player.volume(), 1.0);
+
+        player.set_volume(0.5);
+        player.mute();
+        player.stop().unwrap();
+        assert_eq!(player.sink.volume(), 0.0);
+
+        player.unmute();
+        assert_eq!(player.volume(), 0.5);
+        assert_eq!(player.sink.volume(), amplitude(0.5));
     }
 
     #[test]
-    #[should_panic]
     fn player_can_stop() {
         let client = create_client();
-        let track = client.get_random_track();
-        println!("Track name = {}", track.title);
+        let track = client.get_random_track().unwrap();
+        println!("Track name = {}", track.title());
 
-        let local_track_path = track.download();
+        let local_track_path = track.download().unwrap();
 
-        let mut player = Player::default();
-        player.append(&local_track_path);
-        let _status = player.play();
+        let mut player = Player::new().unwrap();
+        player.append(local_track_path).unwrap();
+        player.play().unwrap();
         println!("Started playing...");
 
         std::thread::sleep(std::time::Duration::from_secs(1));
-        player.stop();
+        player.stop().unwrap();
+
+        assert!(matches!(player.play(), Err(Error::EmptyQueue)));
+    }
+
+    #[test]
+    fn status_is_empty_without_song() {
+        let mut status = Status::Empty;
 
-        player.play();
+        assert!(matches!(status.elapsed(), Err(Error::EmptyQueue)));
+        assert!(matches!(status.play(), Err(Error::EmptyQueue)));
+        assert!(matches!(status.pause(), Err(Error::EmptyQueue)));
     }
 
     #[test]
     fn player_can_get_status() {
         let client = create_client();
-        let track = client.get_random_track();
-        println!("Track name = {}", track.title);
+        let track = client.get_random_track().unwrap();
+        println!("Track name = {}", track.title());
 
-        let local_track_path = track.download();
+        let local_track_path = track.download().unwrap();
 
-        let mut player = Player::default();
+        let mut player = Player::new().unwrap();
         assert_eq!(Status::Empty, player.status().unwrap());
 
-        player.append(&local_track_path);
-        player.play();
+        player.append(local_track_path).unwrap();
+        player.play().unwrap();
         let duration = Duration::from_secs(2);
 
         ::std::thread::sleep(duration);
-        player.pause();
+        player.pause().unwrap();
         let status = player.status().unwrap();
-        assert_eq!(status.elapsed().as_secs(), duration.as_secs());
+        assert_eq!(status.elapsed().unwrap().as_secs(), duration.as_secs());
 
         let status = player.status().unwrap();
-        assert_eq!(status.elapsed().as_secs(), duration.as_secs());
+        assert_eq!(status.elapsed().unwrap().as_secs(), duration.as_secs());
     }
 }
@@ -0,0 +1,377 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::track::Track;
+
+/// Loudness every track is brought to, as streaming services do
+pub const TARGET_LOUDNESS: f32 = -14.0;
+
+/// Quiet tracks are not boosted more than this, nor so much that their peak is over
+/// the full scale
+const MAX_GAIN_DB: f32 = 12.0;
+
+/// Which loudness the volume of a track is adjusted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    #[default]
+    Off,
+    /// Every track is as loud as the others
+    Track,
+    /// Tracks of an album keep their loudness relative to each other
+    Album,
+}
+
+impl Normalization {
+    pub fn next(self) -> Self {
+        match self {
+            Normalization::Off => Normalization::Track,
+            Normalization::Track => Normalization::Album,
+            Normalization::Album => Normalization::Off,
+        }
+    }
+}
+
+impl Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Normalization::Off => "off",
+            Normalization::Track => "track",
+            Normalization::Album => "album",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Integrated loudness of a track and of its album in LUFS, and the peak of the track in dBFS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Loudness {
+    pub(crate) track: f32,
+    pub(crate) album: Option<f32>,
+    pub(crate) peak: Option<f32>,
+}
+
+impl Loudness {
+    /// Amplitude bringing the sound to the target loudness, 1 when it is not normalized
+    pub(crate) fn gain(&self, normalization: Normalization, pre_amp_db: f32) -> f32 {
+        let loudness = match normalization {
+            Normalization::Off => return 1.0,
+            Normalization::Track => self.track,
+            Normalization::Album => self.album.unwrap_or(self.track),
+        };
+        let db = (TARGET_LOUDNESS - loudness + pre_amp_db).min(MAX_GAIN_DB);
+        // Amplified samples are clipped past the full scale
+        let db = self.peak.map_or(db, |peak| db.min(-peak));
+        10f32.powf(db / 20.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Measured {
+    loudness: f32,
+    #[serde(default)]
+    peak: Option<f32>,
+    album_id: Option<String>,
+}
+
+/// Loudness of the tracks by their id, measuring a track takes decoding all of it
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LoudnessCache {
+    tracks: HashMap<String, Measured>,
+}
+
+impl LoudnessCache {
+    /// Loudness saved in the file, an empty cache if there is no file yet
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, track_id: &str) -> Option<f32> {
+        self.tracks.get(track_id).map(|measured| measured.loudness)
+    }
+
+    /// Keep the loudness of the track in LUFS and its peak in dBFS
+    pub fn insert(&mut self, track: &Track, loudness: f32, peak: Option<f32>) {
+        let measured = Measured {
+            loudness,
+            peak,
+            album_id: track.album_id.clone(),
+        };
+        self.tracks.insert(track.id.clone(), measured);
+    }
+
+    /// Loudness of the known tracks of the album played one after another
+    pub fn album(&self, album_id: &str) -> Option<f32> {
+        let powers: Vec<f64> = self
+            .tracks
+            .values()
+            .filter(|measured| measured.album_id.as_deref() == Some(album_id))
+            .map(|measured| 10f64.powf(measured.loudness as f64 / 10.0))
+            .collect();
+        if powers.is_empty() {
+            return None;
+        }
+        let mean = powers.iter().sum::<f64>() / powers.len() as f64;
+        Some((10.0 * mean.log10()) as f32)
+    }
+
+    /// Loudness of the track and its album, the service value is preferred to a measured one
+    pub(crate) fn loudness(&mut self, track: &Track) -> Option<Loudness> {
+        if let Some(loudness) = track.loudness {
+            self.insert(track, loudness, track.peak);
+        }
+        let measured = self.tracks.get(&track.id)?;
+        let (track_loudness, peak) = (measured.loudness, measured.peak);
+        let album = track.album_id.as_deref().and_then(|id| self.album(id));
+        Some(Loudness {
+            track: track_loudness,
+            album,
+            peak,
+        })
+    }
+}
+
+/// Biquad filter of one channel
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// K-weighting of ITU-R BS.1770 for the sample rate: a high shelf modelling the head
+/// followed by a high pass
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let rate = sample_rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    (shelf, high_pass)
+}
+
+fn block_loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Integrated loudness of the sound in LUFS as EBU R128 defines it: the K-weighted power
+/// of 400ms blocks overlapping by 75%, leaving out silence and blocks much quieter than
+/// the rest. None if the sound is silent or shorter than a block
+pub fn integrated_loudness<S: Source<Item = i16>>(source: S) -> Option<f32> {
+    measure(source).map(|(loudness, _)| loudness)
+}
+
+/// Integrated loudness of the sound in LUFS and its sample peak in dBFS
+pub(crate) fn measure<S: Source<Item = i16>>(source: S) -> Option<(f32, f32)> {
+    let channels = source.channels().max(1) as usize;
+    let sample_rate = source.sample_rate();
+    let mut filters = vec![k_weighting(sample_rate); channels];
+
+    // Power of the 100ms steps the blocks are made of
+    let step = (sample_rate as usize / 10).max(1);
+    let mut steps = Vec::new();
+    let (mut energy, mut frames, mut peak) = (0.0, 0, 0);
+    for (i, sample) in source.enumerate() {
+        peak = peak.max(sample.unsigned_abs());
+        let (shelf, high_pass) = &mut filters[i % channels];
+        let filtered = high_pass.process(shelf.process(sample as f64 / i16::MAX as f64));
+        energy += filtered * filtered;
+        if i % channels == channels - 1 {
+            frames += 1;
+            if frames == step {
+                steps.push(energy);
+                energy = 0.0;
+                frames = 0;
+            }
+        }
+    }
+
+    let blocks: Vec<f64> = steps
+        .windows(4)
+        .map(|window| window.iter().sum::<f64>() / (4 * step) as f64)
+        .filter(|&power| block_loudness(power) > -70.0)
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+    let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+    let threshold = block_loudness(mean(&blocks)) - 10.0;
+    let gated: Vec<f64> = blocks
+        .into_iter()
+        .filter(|&power| block_loudness(power) > threshold)
+        .collect();
+    let peak = 20.0 * (peak as f32 / i16::MAX as f32).log10();
+    Some((block_loudness(mean(&gated)) as f32, peak))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use rodio::buffer::SamplesBuffer;
+    use std::f32::consts::TAU;
+    use std::time::Duration;
+
+    const RATE: u32 = 48000;
+
+    /// Samples of a sine wave of the amplitude relative to the full scale, on every channel
+    fn samples(frequency: f32, amplitude: f32, millis: u32, channels: u16) -> Vec<i16> {
+        (0..millis * RATE / 1000)
+            .flat_map(|i| {
+                let phase = TAU * frequency * i as f32 / RATE as f32;
+                let sample = (phase.sin() * amplitude * i16::MAX as f32) as i16;
+                std::iter::repeat_n(sample, channels as usize)
+            })
+            .collect()
+    }
+
+    fn sine(frequency: f32, amplitude: f32, millis: u32, channels: u16) -> SamplesBuffer<i16> {
+        let samples = samples(frequency, amplitude, millis, channels);
+        SamplesBuffer::new(channels, RATE, samples)
+    }
+
+    fn track(id: &str, album_id: &str, loudness: Option<f32>) -> Track {
+        let mut track = Track::local(id.to_string(), id, Duration::from_secs(1), None);
+        track.album_id = Some(album_id.to_string());
+        track.loudness = loudness;
+        track
+    }
+
+    #[test]
+    fn loudness_of_sine_matches_reference() {
+        // A 1kHz sine on both channels is as loud as its peak level in dBFS
+        let loudness = integrated_loudness(sine(1000.0, 0.1, 2000, 2)).unwrap();
+        assert!((loudness - -20.0).abs() < 0.05, "{}", loudness);
+
+        // and 3dB quieter on one channel
+        let loudness = integrated_loudness(sine(1000.0, 0.5, 2000, 1)).unwrap();
+        assert!((loudness - -9.03).abs() < 0.05, "{}", loudness);
+    }
+
+    #[test]
+    fn peak_is_measured_with_loudness() {
+        let (_, peak) = measure(sine(1000.0, 0.5, 2000, 2)).unwrap();
+        assert!((peak - -6.02).abs() < 0.05, "{}", peak);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        assert!(integrated_loudness(sine(1000.0, 0.0, 2000, 1)).is_none());
+        assert!(integrated_loudness(sine(1000.0, 1.0, 300, 1)).is_none());
+    }
+
+    #[test]
+    fn quiet_parts_are_gated() {
+        let mut both = samples(1000.0, 0.5, 2000, 1);
+        both.extend(samples(1000.0, 0.01, 2000, 1));
+        let loudness = integrated_loudness(SamplesBuffer::new(1, RATE, both)).unwrap();
+        let reference = integrated_loudness(sine(1000.0, 0.5, 2000, 1)).unwrap();
+        // Only the blocks overlapping the loud part count, half of the sound would be 3dB quieter
+        assert!((loudness - reference).abs() < 0.5, "{}", loudness);
+    }
+
+    #[test]
+    fn gain_brings_track_to_target() {
+        let loudness = Loudness {
+            track: -20.0,
+            album: Some(-8.0),
+            peak: None,
+        };
+        assert_eq!(loudness.gain(Normalization::Off, 5.0), 1.0);
+        assert!((loudness.gain(Normalization::Track, 0.0) - 1.9953).abs() < 1e-3);
+        assert!((loudness.gain(Normalization::Album, 0.0) - 0.5012).abs() < 1e-3);
+        assert!((loudness.gain(Normalization::Album, 6.0) - 1.0).abs() < 1e-3);
+
+        let silent = Loudness {
+            track: -60.0,
+            album: None,
+            peak: None,
+        };
+        assert!((silent.gain(Normalization::Album, 0.0) - 3.981).abs() < 1e-3);
+
+        // The peak is not amplified past the full scale
+        let peaky = Loudness {
+            track: -30.0,
+            album: None,
+            peak: Some(-6.0),
+        };
+        assert!((peaky.gain(Normalization::Track, 0.0) - 1.9953).abs() < 1e-3);
+        assert!((peaky.gain(Normalization::Track, -12.0) - 1.5849).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cache_knows_album_loudness() {
+        let dir = TempDir::new("loudness");
+        let path = dir.path().join("loudness.json");
+        let mut cache = LoudnessCache::default();
+        cache.insert(&track("1", "album", None), -10.0, Some(-1.0));
+        let loudness = cache.loudness(&track("2", "album", Some(-20.0))).unwrap();
+        assert_eq!(loudness.track, -20.0);
+        assert!((loudness.album.unwrap() - -12.6).abs() < 0.1);
+        assert!(cache.loudness(&track("3", "album", None)).is_none());
+
+        cache.save(&path).unwrap();
+        let restored = LoudnessCache::load(&path).unwrap();
+        assert_eq!(restored.get("2"), Some(-20.0));
+        assert_eq!(restored.album("album"), cache.album("album"));
+    }
+}
@@ -0,0 +1,490 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::album::{Album, AlbumWithTracks};
+use crate::artist::{Artist, ArtistInfo};
+use crate::backend::{Fetcher, MusicBackend};
+use crate::cache::Cache;
+use crate::error::{Error, Result};
+use crate::page::Page;
+use crate::playlist::{GeneratedPlaylist, GeneratedPlaylistType, Playlist, PlaylistWithTracks};
+use crate::search::{SearchKind, SearchResult, SearchResults};
+use crate::station::{Feedback, Station, StationId, StationTracks};
+use crate::stream::Media;
+use crate::track::{QualityPreference, Track};
+
+/// Type of the generated playlists listing the offline collections
+const OFFLINE_PLAYLIST: &str = "offline";
+/// Liked tracks are listed by pages as the service does
+const LIKED_PAGE_SIZE: usize = 100;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Catalog {
+    playlists: Vec<PlaylistWithTracks>,
+    albums: Vec<AlbumWithTracks>,
+    liked: Vec<Track>,
+}
+
+/// Playlists, albums and liked tracks made available offline. Their details are saved in
+/// a file, the files of their tracks are pinned in the cache
+pub struct OfflineStore {
+    path: PathBuf,
+    cache: Arc<Mutex<Cache>>,
+    catalog: Catalog,
+}
+
+impl OfflineStore {
+    /// Open the collections saved in the file, there are none if it does not exist yet
+    pub fn open(path: impl Into<PathBuf>, cache: Arc<Mutex<Cache>>) -> Result<Self> {
+        let path = path.into();
+        let catalog = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Catalog::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            cache,
+            catalog,
+        })
+    }
+
+    /// Save the playlist, it replaces the tracks it had when it was saved before
+    pub fn add_playlist(&mut self, playlist: PlaylistWithTracks) -> Result<()> {
+        let playlists = &mut self.catalog.playlists;
+        playlists.retain(|saved| {
+            saved.playlist.owner.uid != playlist.playlist.owner.uid
+                || saved.playlist.kind != playlist.playlist.kind
+        });
+        playlists.push(playlist);
+        self.save()
+    }
+
+    pub fn add_album(&mut self, album: AlbumWithTracks) -> Result<()> {
+        let albums = &mut self.catalog.albums;
+        albums.retain(|saved| saved.album.id != album.album.id);
+        albums.push(album);
+        self.save()
+    }
+
+    /// Save the whole collection of liked tracks, from the most recent like
+    pub fn set_liked(&mut self, tracks: Vec<Track>) -> Result<()> {
+        self.catalog.liked = tracks;
+        self.save()
+    }
+
+    /// Tracks of all the collections, each of them once
+    pub fn tracks(&self) -> Vec<Track> {
+        let mut ids = HashSet::new();
+        self.catalog
+            .liked
+            .iter()
+            .chain(self.catalog.playlists.iter().flat_map(|p| &p.tracks))
+            .chain(
+                self.catalog
+                    .albums
+                    .iter()
+                    .flat_map(|a| a.volumes.iter().flatten()),
+            )
+            .filter(|track| ids.insert(track.id().to_string()))
+            .cloned()
+            .collect()
+    }
+
+    /// Collections in the order they are listed, the liked tracks first
+    fn collections(&self) -> Vec<(String, Vec<Track>)> {
+        let liked = Some((String::from("Liked tracks"), self.catalog.liked.clone()))
+            .filter(|(_, tracks)| !tracks.is_empty());
+        let playlists = self
+            .catalog
+            .playlists
+            .iter()
+            .map(|p| (p.playlist.title.clone(), p.tracks.clone()));
+        let albums = self
+            .catalog
+            .albums
+            .iter()
+            .map(|a| (format!("Album: {}", a.album.title), a.tracks()));
+        liked.into_iter().chain(playlists).chain(albums).collect()
+    }
+
+    /// Write the collections and pin their tracks, so that their files stay in the cache
+    fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&self.catalog)?)?;
+        let ids = self
+            .tracks()
+            .iter()
+            .map(|track| track.id().to_string())
+            .collect();
+        self.cache.lock().unwrap().pin(ids)
+    }
+}
+
+/// Backend serving the collections made available offline from the local files,
+/// it makes no requests to the service
+pub struct OfflineBackend {
+    store: OfflineStore,
+    quality: QualityPreference,
+}
+
+impl OfflineBackend {
+    pub fn new(store: OfflineStore, quality: QualityPreference) -> Self {
+        Self { store, quality }
+    }
+
+    fn catalog(&self) -> &Catalog {
+        &self.store.catalog
+    }
+}
+
+fn offline(what: String) -> Error {
+    Error::Offline(what)
+}
+
+fn matches(text: &str, query: &str) -> bool {
+    text.to_lowercase().contains(&query.to_lowercase())
+}
+
+fn results<T>(results: Vec<T>) -> SearchResults<T> {
+    SearchResults {
+        total: results.len() as u32,
+        per_page: results.len() as u32,
+        results,
+    }
+}
+
+impl MusicBackend for OfflineBackend {
+    /// The offline collections are listed as generated playlists, numbered by the kind
+    fn generated_playlists(&self) -> Result<Vec<GeneratedPlaylist>> {
+        let playlists = self
+            .store
+            .collections()
+            .into_iter()
+            .enumerate()
+            .map(|(kind, (title, _))| GeneratedPlaylist {
+                playlist_type: GeneratedPlaylistType::Other(OFFLINE_PLAYLIST.to_string()),
+                title,
+                description: None,
+                owner_uid: 0,
+                kind: kind as u64,
+            })
+            .collect();
+        Ok(playlists)
+    }
+
+    fn generated_playlist_tracks(&self, playlist: &GeneratedPlaylist) -> Result<Vec<Track>> {
+        self.store
+            .collections()
+            .into_iter()
+            .nth(playlist.kind as usize)
+            .map(|(_, tracks)| tracks)
+            .ok_or_else(|| offline(playlist.title.clone()))
+    }
+
+    /// Look for the saved tracks, albums and playlists, the results fit a single page
+    fn search(&self, query: &str, kind: SearchKind, page: u32) -> Result<SearchResult> {
+        let wants = |section: SearchKind| page == 0 && (kind == SearchKind::All || kind == section);
+        let mut result = SearchResult {
+            text: query.to_string(),
+            page,
+            tracks: SearchResults::default(),
+            albums: SearchResults::default(),
+            artists: SearchResults::default(),
+            playlists: SearchResults::default(),
+        };
+        if wants(SearchKind::Track) {
+            let tracks = self.store.tracks().into_iter();
+            result.tracks = results(tracks.filter(|t| matches(&t.title, query)).collect());
+        }
+        if wants(SearchKind::Album) {
+            let albums = self.catalog().albums.iter().map(|a| a.album.clone());
+            result.albums = results(albums.filter(|a| matches(&a.title, query)).collect());
+        }
+        if wants(SearchKind::Playlist) {
+            let playlists = self.catalog().playlists.iter().map(|p| p.playlist.clone());
+            result.playlists = results(playlists.filter(|p| matches(&p.title, query)).collect());
+        }
+        Ok(result)
+    }
+
+    fn album(&self, album_id: &str) -> Result<AlbumWithTracks> {
+        self.catalog()
+            .albums
+            .iter()
+            .find(|album| album.album.id == album_id)
+            .cloned()
+            .ok_or_else(|| offline(format!("album {}", album_id)))
+    }
+
+    /// Saved tracks of the artist
+    fn artist(&self, artist_id: &str) -> Result<ArtistInfo> {
+        let tracks = self.store.tracks();
+        let artist = tracks
+            .iter()
+            .flat_map(|track| &track.artists)
+            .find(|artist| artist.id == artist_id)
+            .cloned()
+            .ok_or_else(|| offline(format!("artist {}", artist_id)))?;
+        let popular_tracks = tracks
+            .into_iter()
+            .filter(|track| track.artists.iter().any(|a| a.id == artist_id))
+            .collect();
+        let albums = self
+            .catalog()
+            .albums
+            .iter()
+            .map(|album| &album.album)
+            .filter(|album| album.artists.iter().any(|a| a.id == artist_id))
+            .cloned()
+            .collect();
+        Ok(ArtistInfo {
+            artist,
+            popular_tracks,
+            albums,
+        })
+    }
+
+    fn playlist(&self, owner_uid: u64, kind: u64) -> Result<PlaylistWithTracks> {
+        self.catalog()
+            .playlists
+            .iter()
+            .find(|p| p.playlist.owner.uid == owner_uid && p.playlist.kind == kind)
+            .cloned()
+            .ok_or_else(|| offline(format!("playlist {}", kind)))
+    }
+
+    fn add_to_playlist(&self, _kind: u64, _tracks: &[Track]) -> Result<Playlist> {
+        Err(offline(String::from("changing playlists")))
+    }
+
+    fn user_playlists(&self) -> Result<Vec<Playlist>> {
+        let playlists = self.catalog().playlists.iter();
+        Ok(playlists.map(|p| p.playlist.clone()).collect())
+    }
+
+    fn liked_tracks(&self, page: u32) -> Result<Page<Track>> {
+        let liked = &self.catalog().liked;
+        Ok(Page {
+            items: liked
+                .iter()
+                .skip(page as usize * LIKED_PAGE_SIZE)
+                .take(LIKED_PAGE_SIZE)
+                .cloned()
+                .collect(),
+            page,
+            per_page: LIKED_PAGE_SIZE as u32,
+            total: liked.len() as u32,
+        })
+    }
+
+    fn liked_track_ids(&self) -> Result<Vec<String>> {
+        let liked = self.catalog().liked.iter();
+        Ok(liked.map(|track| track.id().to_string()).collect())
+    }
+
+    /// Only the tracks of the saved collections are known
+    fn tracks(&self, track_ids: &[String]) -> Result<Vec<Track>> {
+        let tracks = self.store.tracks();
+        Ok(track_ids
+            .iter()
+            .filter_map(|id| tracks.iter().find(|track| track.id() == id))
+            .cloned()
+            .collect())
+    }
+
+    fn like_track(&self, _track: &Track) -> Result<()> {
+        Err(offline(String::from("likes")))
+    }
+
+    fn unlike_track(&self, _track: &Track) -> Result<()> {
+        Err(offline(String::from("likes")))
+    }
+
+    fn dislike_track(&self, _track: &Track) -> Result<()> {
+        Err(offline(String::from("dislikes")))
+    }
+
+    fn undislike_track(&self, _track: &Track) -> Result<()> {
+        Err(offline(String::from("dislikes")))
+    }
+
+    fn liked_albums(&self) -> Result<Vec<Album>> {
+        let albums = self.catalog().albums.iter();
+        Ok(albums.map(|album| album.album.clone()).collect())
+    }
+
+    /// Artists of the saved albums
+    fn liked_artists(&self) -> Result<Vec<Artist>> {
+        let mut ids = HashSet::new();
+        let artists = self.catalog().albums.iter();
+        Ok(artists
+            .flat_map(|album| &album.album.artists)
+            .filter(|artist| ids.insert(artist.id.clone()))
+            .cloned()
+            .collect())
+    }
+
+    fn stations(&self) -> Result<Vec<Station>> {
+        Ok(Vec::new())
+    }
+
+    fn station_tracks(
+        &self,
+        station: &StationId,
+        _last_track_id: Option<&str>,
+    ) -> Result<StationTracks> {
+        Err(offline(format!("station {}", station)))
+    }
+
+    /// The feedback is dropped, it is not worth keeping until the network is back
+    fn station_feedback(
+        &self,
+        _station: &Station,
+        _batch_id: Option<&str>,
+        _feedback: &Feedback,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn download(&self, track: &Track) -> Result<String> {
+        self.local_file(track).ok_or_else(|| offline(track.title()))
+    }
+
+    fn local_file(&self, track: &Track) -> Option<String> {
+        let mut cache = self.store.cache.lock().unwrap();
+        let path = cache.find(track.id(), &self.quality)?;
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    fn fetcher(&self) -> Fetcher {
+        let cache = Arc::clone(&self.store.cache);
+        let quality = self.quality.clone();
+        Arc::new(move |track: &Track| {
+            let mut cache = cache.lock().unwrap();
+            let path = cache
+                .find(track.id(), &quality)
+                .ok_or_else(|| offline(track.title()))?;
+            Ok(Media::File(path.to_string_lossy().into_owned()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::Owner;
+    use crate::testing::TempDir;
+    use crate::track::Quality;
+    use std::time::Duration;
+
+    fn album(id: &str, titles: &[&str]) -> AlbumWithTracks {
+        let album = Album {
+            id: id.to_string(),
+            title: format!("Album {}", id),
+            year: Some(2020),
+            genre: None,
+            track_count: titles.len() as u32,
+            artists: vec![Artist {
+                id: String::from("artist"),
+                name: String::from("Artist"),
+            }],
+            cover_uri: None,
+        };
+        let tracks = titles
+            .iter()
+            .map(|title| {
+                let id = format!("{}-{}", id, title);
+                Track::local(id, title, Duration::from_secs(1), Some(&album))
+            })
+            .collect();
+        AlbumWithTracks {
+            album,
+            volumes: vec![tracks],
+        }
+    }
+
+    fn playlist(title: &str, tracks: Vec<Track>) -> PlaylistWithTracks {
+        let playlist = Playlist {
+            owner: Owner {
+                uid: 1,
+                login: None,
+                name: None,
+            },
+            kind: 3,
+            title: title.to_string(),
+            description: None,
+            track_count: tracks.len() as u32,
+            revision: 1,
+        };
+        PlaylistWithTracks { playlist, tracks }
+    }
+
+    #[test]
+    fn offline_collections_are_served_from_disk() {
+        let temp = TempDir::new("offline-collections");
+        let dir = temp.path();
+        let cache = Cache::open(dir.join("tracks"), 1000).unwrap();
+        let cache = Arc::new(Mutex::new(cache));
+        let path = dir.join("offline.json");
+        let mut store = OfflineStore::open(&path, Arc::clone(&cache)).unwrap();
+        let first = album("1", &["One", "Two"]);
+        let tracks = first.tracks();
+        store.add_album(first).unwrap();
+        store
+            .add_playlist(playlist("Old title", tracks[..1].to_vec()))
+            .unwrap();
+        store
+            .add_playlist(playlist("Train", tracks.clone()))
+            .unwrap();
+        store.set_liked(vec![tracks[1].clone()]).unwrap();
+        assert_eq!(store.tracks().len(), 2);
+        assert!(cache.lock().unwrap().is_pinned("1-One"));
+
+        let quality = Quality::default();
+        let file = cache.lock().unwrap().path("1-One", &quality);
+        fs::write(&file, vec![0; 10]).unwrap();
+        cache.lock().unwrap().insert("1-One", &quality).unwrap();
+
+        // The collections are read back from the file
+        let store = OfflineStore::open(&path, cache).unwrap();
+        let backend = OfflineBackend::new(store, QualityPreference::default());
+        let sources: Vec<String> = backend
+            .generated_playlists()
+            .unwrap()
+            .into_iter()
+            .map(|p| p.title)
+            .collect();
+        assert_eq!(sources, ["Liked tracks", "Train", "Album: Album 1"]);
+        let train = &backend.generated_playlists().unwrap()[1];
+        assert_eq!(backend.generated_playlist_tracks(train).unwrap().len(), 2);
+        assert_eq!(backend.user_playlists().unwrap().len(), 1);
+        assert_eq!(backend.playlist(1, 3).unwrap().playlist.title, "Train");
+        assert_eq!(backend.album("1").unwrap().tracks().len(), 2);
+        assert!(matches!(backend.album("2"), Err(Error::Offline(_))));
+        assert_eq!(backend.artist("artist").unwrap().popular_tracks.len(), 2);
+        assert_eq!(backend.liked_track_ids().unwrap(), ["1-Two"]);
+        assert_eq!(backend.liked_tracks(0).unwrap().total, 1);
+        let found = backend.search("two", SearchKind::All, 0).unwrap();
+        assert_eq!(found.tracks.results[0].title(), "Two");
+        assert_eq!(found.albums.total, 0);
+
+        // Only the downloaded tracks can be played
+        let expected = file.to_string_lossy().into_owned();
+        assert_eq!(backend.local_file(&tracks[0]), Some(expected.clone()));
+        assert_eq!(backend.local_file(&tracks[1]), None);
+        let fetch = backend.fetcher();
+        assert!(matches!(fetch(&tracks[0]), Ok(Media::File(path)) if path == expected));
+        assert!(matches!(fetch(&tracks[1]), Err(Error::Offline(_))));
+        assert!(matches!(
+            backend.like_track(&tracks[0]),
+            Err(Error::Offline(_))
+        ));
+    }
+}
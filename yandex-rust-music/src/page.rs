@@ -0,0 +1,46 @@
+/// Part of a long list fetched at once, pages are numbered from 0
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: u32,
+}
+
+impl<T> Page<T> {
+    /// An empty page size has no pages, like in `page_count`
+    pub fn has_next_page(&self) -> bool {
+        self.per_page > 0 && (self.page as u64 + 1) * (self.per_page as u64) < self.total as u64
+    }
+
+    pub fn page_count(&self) -> u32 {
+        if self.per_page == 0 {
+            0
+        } else {
+            self.total.div_ceil(self.per_page)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(page: u32, per_page: u32, total: u32) -> Page<()> {
+        Page {
+            items: Vec::new(),
+            page,
+            per_page,
+            total,
+        }
+    }
+
+    #[test]
+    fn last_page_has_no_next_one() {
+        assert!(page(0, 10, 25).has_next_page());
+        assert!(!page(2, 10, 25).has_next_page());
+        assert!(!page(0, 0, 25).has_next_page());
+        assert_eq!(page(0, 0, 25).page_count(), 0);
+        assert!(!page(u32::MAX, u32::MAX, u32::MAX).has_next_page());
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display};
+
+use crate::track::Track;
+
+/// Type of a playlist the service generates for the user
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum GeneratedPlaylistType {
+    PlaylistOfTheDay,
+    MissedLikes,
+    NeverHeard,
+    RecentTracks,
+    Podcasts,
+    Origin,
+    Other(String),
+}
+
+impl From<String> for GeneratedPlaylistType {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "playlistOfTheDay" => Self::PlaylistOfTheDay,
+            "missedLikes" => Self::MissedLikes,
+            "neverHeard" => Self::NeverHeard,
+            "recentTracks" => Self::RecentTracks,
+            "podcasts" => Self::Podcasts,
+            "origin" => Self::Origin,
+            _ => Self::Other(name),
+        }
+    }
+}
+
+impl Display for GeneratedPlaylistType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Self::PlaylistOfTheDay => "playlistOfTheDay",
+            Self::MissedLikes => "missedLikes",
+            Self::NeverHeard => "neverHeard",
+            Self::RecentTracks => "recentTracks",
+            Self::Podcasts => "podcasts",
+            Self::Origin => "origin",
+            Self::Other(name) => name,
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Playlist from the feed, its tracks are fetched on demand
+#[derive(Debug, Clone)]
+pub struct GeneratedPlaylist {
+    pub playlist_type: GeneratedPlaylistType,
+    pub title: String,
+    pub description: Option<String>,
+    pub(crate) owner_uid: u64,
+    pub(crate) kind: u64,
+}
+
+/// Playlist created by a user, its tracks are fetched on demand
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    pub owner: Owner,
+    pub kind: u64,
+    #[serde(default)]
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub track_count: u32,
+    /// Changes are accepted only against the latest revision
+    #[serde(default)]
+    pub revision: u64,
+}
+
+/// Playlist with the details of all its tracks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaylistWithTracks {
+    pub playlist: Playlist,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Owner {
+    pub uid: u64,
+    pub login: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Track in a playlist diff
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrackRef {
+    pub(crate) id: String,
+    pub(crate) album_id: Option<String>,
+}
+
+impl From<&Track> for TrackRef {
+    fn from(track: &Track) -> Self {
+        Self {
+            id: track.id.clone(),
+            album_id: track.album_id.clone(),
+        }
+    }
+}
+
+/// Operation of the diff the service applies to a revision of a playlist,
+/// operations are applied one after another
+#[derive(Debug, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub(crate) enum DiffOp {
+    Insert {
+        at: usize,
+        tracks: Vec<TrackRef>,
+    },
+    Delete {
+        from: usize,
+        to: usize,
+        tracks: Vec<TrackRef>,
+    },
+}
+
+/// Position of the tracks in the playlist, preferring the expected one
+pub(crate) fn find_tracks(
+    playlist: &[TrackRef],
+    tracks: &[TrackRef],
+    expected: usize,
+) -> Option<usize> {
+    let matches_at = |at: usize| playlist.get(at..at + tracks.len()) == Some(tracks);
+    if matches_at(expected) {
+        return Some(expected);
+    }
+    (0..playlist.len()).find(|at| matches_at(*at))
+}
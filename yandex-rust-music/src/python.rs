@@ -0,0 +1,124 @@
+//! Bridge to the Python `yandex_music` package, kept for comparison with the
+//! native client. Enabled with the `python` feature.
+
+use pyo3::prelude::*;
+use rand::{thread_rng, Rng};
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::track::Quality;
+
+#[derive(Clone)]
+pub struct Client {
+    client_py: PyObject,
+}
+
+impl Client {
+    pub fn new(token: &str) -> Result<Self> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let yandex_music_py = PyModule::import(py, "yandex_music")?;
+        let client_class_py = yandex_music_py.getattr("Client")?;
+        let client_py = client_class_py.call1(((token),))?;
+        client_py.call_method0("init")?;
+
+        Ok(Self {
+            client_py: client_py.into(),
+        })
+    }
+
+    pub fn get_random_track(&self) -> Result<Track> {
+        let playlist = self.playlist_of_the_day()?;
+        if playlist.is_empty() {
+            return Err(Error::NotFound(String::from(
+                "Playlist of the day is empty",
+            )));
+        }
+        let random_track_num = thread_rng().gen_range(0..playlist.len());
+
+        Ok(playlist[random_track_num].clone())
+    }
+
+    pub fn playlist_of_the_day(&self) -> Result<Vec<Track>> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let clone_client_py = self.client_py.clone_ref(py);
+        let ref_client_py = clone_client_py.as_ref(py);
+
+        let feed_py = ref_client_py.call_method0("feed")?;
+        let generated_playlists_py = feed_py.getattr("generated_playlists")?;
+        let mut playlist_of_the_day_py = None;
+        for generated_playlist_py in generated_playlists_py.iter()? {
+            let generated_playlist_py = generated_playlist_py?;
+            let type_py = generated_playlist_py.getattr("type")?;
+            if type_py.extract::<&str>()? == "playlistOfTheDay" {
+                playlist_of_the_day_py = Some(generated_playlist_py);
+                break;
+            }
+        }
+        let playlist_of_the_day_py = playlist_of_the_day_py.ok_or_else(|| {
+            Error::NotFound(String::from("No playlistOfTheDay playlist in the feed"))
+        })?;
+        let playlist_py = playlist_of_the_day_py.getattr("data")?;
+        let track_count_py = playlist_py.getattr("track_count")?;
+        let track_count = track_count_py.extract::<usize>()?;
+
+        let mut tracks = Vec::new();
+        for i in 0..track_count {
+            let tracks_short_py = playlist_py.getattr("tracks")?;
+            let track_short_py = tracks_short_py.get_item(i)?;
+            let track_py = track_short_py.call_method0("fetch_track")?;
+
+            let title_py = track_py.getattr("title")?;
+            let title = title_py.extract::<&str>()?;
+
+            let total_duration_py = track_py.getattr("duration_ms")?;
+            let total_duration_ms = total_duration_py.extract::<u64>()?;
+            tracks.push(Track {
+                title: String::from(title),
+                total_duration: Duration::from_millis(total_duration_ms),
+                track_py: track_py.into(),
+            });
+        }
+
+        Ok(tracks)
+    }
+}
+
+#[derive(Clone)]
+pub struct Track {
+    title: String,
+    total_duration: Duration,
+    track_py: PyObject,
+}
+
+impl Track {
+    /// Download the file in the quality, the native client downloads in the background
+    pub fn download(&self, quality: &Quality) -> Result<String> {
+        let filename = format!("{}.{}", self.title, quality.extension());
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let clone_track_py = self.track_py.clone_ref(py);
+        let ref_track_py = clone_track_py.as_ref(py);
+        if !Path::new(&filename).exists() {
+            ref_track_py.call_method(
+                "download",
+                (&filename, &quality.codec, quality.bitrate),
+                None,
+            )?;
+        }
+
+        Ok(filename)
+    }
+
+    pub fn total_duration(&self) -> Option<Duration> {
+        let total_duration = self.total_duration;
+        Some(total_duration)
+    }
+
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
+}
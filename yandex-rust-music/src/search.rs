@@ -0,0 +1,73 @@
+use serde::Deserialize;
+
+use std::fmt::{self, Display};
+
+use crate::album::Album;
+use crate::artist::Artist;
+use crate::playlist::Playlist;
+use crate::track::Track;
+
+/// What to look for in the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchKind {
+    All,
+    Track,
+    Album,
+    Artist,
+    Playlist,
+}
+
+impl Display for SearchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            SearchKind::All => "all",
+            SearchKind::Track => "track",
+            SearchKind::Album => "album",
+            SearchKind::Artist => "artist",
+            SearchKind::Playlist => "playlist",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// One page of catalog search results, sections not requested are empty
+#[derive(Clone)]
+pub struct SearchResult {
+    pub text: String,
+    pub page: u32,
+    pub tracks: SearchResults<Track>,
+    pub albums: SearchResults<Album>,
+    pub artists: SearchResults<Artist>,
+    pub playlists: SearchResults<Playlist>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults<T> {
+    #[serde(default)]
+    pub total: u32,
+    #[serde(default)]
+    pub per_page: u32,
+    #[serde(default = "Vec::new")]
+    pub results: Vec<T>,
+}
+
+impl<T> Default for SearchResults<T> {
+    fn default() -> Self {
+        Self {
+            total: 0,
+            per_page: 0,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl<T> SearchResults<T> {
+    pub(crate) fn map<U>(self, f: impl FnMut(T) -> U) -> SearchResults<U> {
+        SearchResults {
+            total: self.total,
+            per_page: self.per_page,
+            results: self.results.into_iter().map(f).collect(),
+        }
+    }
+}
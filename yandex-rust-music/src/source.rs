@@ -0,0 +1,463 @@
+use rodio::{Sample, Source};
+
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Moment a sound played by the sink ran out of samples, None while it is playing
+pub(crate) type EndMark = Arc<Mutex<Option<Instant>>>;
+
+/// Source noting the moment it has no more samples, the sink starts the next one right then
+pub(crate) struct Tracked<S> {
+    inner: S,
+    ended: EndMark,
+}
+
+impl<S> Tracked<S> {
+    pub(crate) fn new(inner: S, ended: EndMark) -> Self {
+        Self { inner, ended }
+    }
+}
+
+impl<S> Iterator for Tracked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let sample = self.inner.next();
+        if sample.is_none() {
+            let mut ended = self.ended.lock().unwrap();
+            if ended.is_none() {
+                *ended = Some(Instant::now());
+            }
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Source for Tracked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Amplitude of a sound, it can be changed while the sound plays
+#[derive(Clone, Debug)]
+pub(crate) struct Gain(Arc<AtomicU32>);
+
+impl Gain {
+    pub(crate) fn new(amplitude: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(amplitude.to_bits())))
+    }
+
+    pub(crate) fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set(&self, amplitude: f32) {
+        self.0.store(amplitude.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Source amplified by the gain it is currently set to
+pub(crate) struct Amplified<S> {
+    inner: S,
+    gain: Gain,
+}
+
+impl<S> Amplified<S> {
+    pub(crate) fn new(inner: S, gain: Gain) -> Self {
+        Self { inner, gain }
+    }
+}
+
+impl<S> Iterator for Amplified<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()? as f32 * self.gain.get();
+        Some(sample.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Source for Amplified<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+pub(crate) type Next = Box<dyn Source<Item = i16> + Send>;
+
+/// Sound queued after another one and how long it is faded in over the tail of that one.
+/// It is put back with no fade once the tail is over, the sink plays the rest of it
+pub(crate) type NextSlot = Arc<Mutex<Option<(Next, Duration)>>>;
+
+/// Number of samples the duration takes, whole frames only
+fn samples(duration: Duration, channels: u16, sample_rate: u32) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64) as usize * channels as usize
+}
+
+/// Sound being faded in over the tail
+struct FadeIn {
+    source: Next,
+    // Samples of the tail mixed with the sound
+    len: usize,
+}
+
+/// Source reading its inner source ahead, so the samples left once it is over are the tail.
+/// The tail is mixed with the head of the next sound, fading one out and the other in.
+/// The sound is marked ended when the next one starts to fade in
+pub(crate) struct Crossfaded<S> {
+    inner: S,
+    ahead: VecDeque<i16>,
+    // Number of samples read ahead once the lookahead is filled
+    lookahead: usize,
+    next: NextSlot,
+    ended: EndMark,
+    channels: u16,
+    sample_rate: u32,
+    // The inner source is over, only the tail is left
+    draining: bool,
+    fade_in: Option<FadeIn>,
+}
+
+impl<S> Crossfaded<S>
+where
+    S: Source<Item = i16>,
+{
+    /// Read up to `lookahead` of the source ahead, it is the longest tail that can be faded.
+    /// It is filled while the sound plays, not to wait for a stream to download that much
+    pub(crate) fn new(inner: S, lookahead: Duration, next: NextSlot, ended: EndMark) -> Self {
+        let channels = inner.channels();
+        let sample_rate = inner.sample_rate();
+        Self {
+            inner,
+            ahead: VecDeque::new(),
+            lookahead: samples(lookahead, channels, sample_rate),
+            next,
+            ended,
+            channels,
+            sample_rate,
+            draining: false,
+            fade_in: None,
+        }
+    }
+
+    /// Take the next sound to fade it in, it is left to the sink if the formats differ
+    fn take_next(&mut self) -> Option<FadeIn> {
+        let mut slot = self.next.lock().unwrap();
+        let (source, fade) = slot.take()?;
+        // The tail of a short sound may be shorter, it is faded from the start of a frame
+        let len = samples(fade, self.channels, self.sample_rate).min(self.ahead.len());
+        let len = len - len % self.channels as usize;
+        if len == 0
+            || source.channels() != self.channels
+            || source.sample_rate() != self.sample_rate
+        {
+            *slot = Some((source, Duration::ZERO));
+            return None;
+        }
+        Some(FadeIn { source, len })
+    }
+
+    /// Give the rest of the faded in sound back to the sink
+    fn put_back(&mut self) {
+        if let Some(fade_in) = self.fade_in.take() {
+            *self.next.lock().unwrap() = Some((fade_in.source, Duration::ZERO));
+        }
+    }
+}
+
+impl<S> Iterator for Crossfaded<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if !self.draining {
+            // Two samples are read for every one played until the lookahead is filled
+            let reads = if self.ahead.len() < self.lookahead {
+                2
+            } else {
+                1
+            };
+            for _ in 0..reads {
+                match self.inner.next() {
+                    Some(sample) => self.ahead.push_back(sample),
+                    None => {
+                        self.draining = true;
+                        self.fade_in = self.take_next();
+                        break;
+                    }
+                }
+            }
+            if !self.draining {
+                return self.ahead.pop_front();
+            }
+        }
+
+        let left = self.ahead.len();
+        let sample = match self.ahead.pop_front() {
+            Some(sample) => sample,
+            None => {
+                self.put_back();
+                return None;
+            }
+        };
+        let fade_in = match &mut self.fade_in {
+            Some(fade_in) if left <= fade_in.len => fade_in,
+            _ => return Some(sample),
+        };
+        if left == fade_in.len {
+            let mut ended = self.ended.lock().unwrap();
+            if ended.is_none() {
+                *ended = Some(Instant::now());
+            }
+        }
+
+        // Equal power curves keep the loudness of the mix steady
+        let channels = self.channels as usize;
+        let frame = (fade_in.len - left) / channels;
+        let progress = frame as f32 / (fade_in.len / channels) as f32;
+        let (fade_up, fade_out) = (progress * FRAC_PI_2).sin_cos();
+        let head = fade_in.source.next().unwrap_or(0);
+        let mixed = sample as f32 * fade_out + head as f32 * fade_up;
+        Some(mixed.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+    }
+}
+
+impl<S> Source for Crossfaded<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Rest of a sound faded in over the tail of the previous one, taken once that one is over
+pub(crate) struct Handoff {
+    slot: NextSlot,
+    source: Option<Next>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Handoff {
+    /// Put the sound into the slot of the previous one to be faded in
+    pub(crate) fn new(source: Next, fade: Duration, slot: NextSlot) -> Self {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        *slot.lock().unwrap() = Some((source, fade));
+        Self {
+            slot,
+            source: None,
+            channels,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for Handoff {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.source.is_none() {
+            self.source = self.slot.lock().unwrap().take().map(|(source, _)| source);
+        }
+        self.source.as_mut()?.next()
+    }
+}
+
+impl Source for Handoff {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use std::f32::consts::TAU;
+
+    const RATE: u32 = 1000;
+
+    /// Mono sine wave at half of the full scale
+    fn sine(frequency: f32, millis: u32) -> Vec<i16> {
+        (0..millis * RATE / 1000)
+            .map(|i| {
+                let phase = TAU * frequency * i as f32 / RATE as f32;
+                (phase.sin() * i16::MAX as f32 / 2.0) as i16
+            })
+            .collect()
+    }
+
+    fn buffer(samples: &[i16]) -> SamplesBuffer<i16> {
+        SamplesBuffer::new(1, RATE, samples.to_vec())
+    }
+
+    #[test]
+    fn tail_is_mixed_with_head_of_next_sound() {
+        let first = sine(44.0, 1000);
+        let second = sine(66.0, 1000);
+        let slot = NextSlot::default();
+        let ended = EndMark::default();
+        let mut source = Crossfaded::new(
+            buffer(&first),
+            Duration::from_millis(300),
+            slot.clone(),
+            ended.clone(),
+        );
+        let mut handoff = Handoff::new(Box::new(buffer(&second)), Duration::from_millis(200), slot);
+
+        let played: Vec<i16> = source.by_ref().take(800).collect();
+        assert_eq!(played, first[..800]);
+        assert!(ended.lock().unwrap().is_none());
+
+        let mixed: Vec<i16> = source.by_ref().collect();
+        assert!(ended.lock().unwrap().is_some());
+        assert_eq!(mixed.len(), 200);
+        for (i, sample) in mixed.iter().enumerate() {
+            let (fade_in, fade_out) = (i as f32 / 200.0 * FRAC_PI_2).sin_cos();
+            let expected = first[800 + i] as f32 * fade_out + second[i] as f32 * fade_in;
+            assert!((*sample as f32 - expected).abs() <= 1.0, "sample {}", i);
+        }
+        assert_eq!(mixed[0], first[800]);
+
+        // The sink plays the rest of the next sound after the tail
+        let rest: Vec<i16> = handoff.by_ref().collect();
+        assert_eq!(rest, second[200..]);
+    }
+
+    #[test]
+    fn lookahead_is_read_while_sound_plays() {
+        let first = sine(44.0, 1000);
+        let mut source = Crossfaded::new(
+            buffer(&first),
+            Duration::from_millis(300),
+            NextSlot::default(),
+            EndMark::default(),
+        );
+        assert_eq!(source.inner.size_hint().0, 1000);
+
+        let played: Vec<i16> = source.by_ref().take(100).collect();
+        assert_eq!(played, first[..100]);
+        assert_eq!(source.inner.size_hint().0, 800);
+        source.by_ref().take(300).for_each(drop);
+        assert_eq!(source.ahead.len(), 300);
+        assert_eq!(source.collect::<Vec<_>>(), first[400..]);
+    }
+
+    #[test]
+    fn sound_is_not_faded_without_lookahead() {
+        let first = sine(44.0, 500);
+        let second = sine(66.0, 500);
+        let slot = NextSlot::default();
+        let ended = EndMark::default();
+        let source = Crossfaded::new(buffer(&first), Duration::ZERO, slot.clone(), ended);
+        let handoff = Handoff::new(Box::new(buffer(&second)), Duration::from_secs(5), slot);
+
+        assert_eq!(source.collect::<Vec<_>>(), first);
+        assert_eq!(handoff.collect::<Vec<_>>(), second);
+    }
+
+    #[test]
+    fn gain_is_changed_while_sound_plays() {
+        let gain = Gain::new(2.0);
+        let mut source = Amplified::new(buffer(&[100, -20000, 300, 400]), gain.clone());
+        assert_eq!(source.by_ref().take(2).collect::<Vec<_>>(), [200, i16::MIN]);
+        gain.set(0.5);
+        assert_eq!(source.collect::<Vec<_>>(), [150, 200]);
+    }
+
+    #[test]
+    fn sounds_of_other_format_are_not_faded() {
+        let first = sine(44.0, 500);
+        let slot = NextSlot::default();
+        let ended = EndMark::default();
+        let source = Crossfaded::new(
+            buffer(&first),
+            Duration::from_millis(300),
+            slot.clone(),
+            ended,
+        );
+        let stereo = SamplesBuffer::new(2, RATE, vec![100i16; 400]);
+        let handoff = Handoff::new(Box::new(stereo), Duration::from_millis(300), slot);
+
+        assert_eq!(source.collect::<Vec<_>>(), first);
+        assert_eq!(handoff.count(), 400);
+    }
+}
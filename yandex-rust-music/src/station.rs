@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::track::Track;
+
+const MY_WAVE_TAG: &str = "onyourwave";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StationId {
+    #[serde(rename = "type")]
+    pub station_type: String,
+    pub tag: String,
+}
+
+impl Display for StationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.station_type, self.tag)
+    }
+}
+
+/// Group of the station in the stations list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StationCategory {
+    MyWave,
+    Genre,
+    Mood,
+    Activity,
+    Era,
+    Other(String),
+}
+
+/// Endless radio playing tracks picked by the service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Station {
+    pub id: StationId,
+    pub name: String,
+    // Tells the service where the radio was started from
+    #[serde(default)]
+    pub(crate) id_for_from: String,
+}
+
+impl Station {
+    /// The personal station of the user, it is not listed with the others
+    pub fn my_wave() -> Self {
+        Self {
+            id: StationId {
+                station_type: String::from("user"),
+                tag: String::from(MY_WAVE_TAG),
+            },
+            name: String::from("My Wave"),
+            id_for_from: String::from("user-onyourwave"),
+        }
+    }
+
+    pub fn category(&self) -> StationCategory {
+        match self.id.station_type.as_str() {
+            "user" if self.id.tag == MY_WAVE_TAG => StationCategory::MyWave,
+            "genre" => StationCategory::Genre,
+            "mood" => StationCategory::Mood,
+            "activity" => StationCategory::Activity,
+            "epoch" => StationCategory::Era,
+            other => StationCategory::Other(other.to_string()),
+        }
+    }
+}
+
+/// Next tracks of the station, feedback about them refers to the batch
+#[derive(Debug, Clone)]
+pub struct StationTracks {
+    pub batch_id: String,
+    pub tracks: Vec<Track>,
+}
+
+/// What happened with the station, the service picks next tracks from it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Feedback {
+    RadioStarted,
+    TrackStarted { track_id: String },
+    TrackFinished { track_id: String, played: Duration },
+    Skip { track_id: String, played: Duration },
+    Like { track_id: String },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FeedbackEvent {
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_played_seconds: Option<f64>,
+}
+
+impl FeedbackEvent {
+    pub(crate) fn new(station: &Station, feedback: &Feedback, now: SystemTime) -> Self {
+        let mut event = Self {
+            event_type: "",
+            timestamp: iso_timestamp(now),
+            from: None,
+            track_id: None,
+            total_played_seconds: None,
+        };
+        match feedback {
+            Feedback::RadioStarted => {
+                event.event_type = "radioStarted";
+                event.from = Some(station.id_for_from.clone());
+            }
+            Feedback::TrackStarted { track_id } => {
+                event.event_type = "trackStarted";
+                event.track_id = Some(track_id.clone());
+            }
+            Feedback::TrackFinished { track_id, played } => {
+                event.event_type = "trackFinished";
+                event.track_id = Some(track_id.clone());
+                event.total_played_seconds = Some(played.as_secs_f64());
+            }
+            Feedback::Skip { track_id, played } => {
+                event.event_type = "skip";
+                event.track_id = Some(track_id.clone());
+                event.total_played_seconds = Some(played.as_secs_f64());
+            }
+            Feedback::Like { track_id } => {
+                event.event_type = "like";
+                event.track_id = Some(track_id.clone());
+            }
+        }
+        event
+    }
+}
+
+/// UTC time like 2022-05-01T12:30:05Z
+fn iso_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_is_formatted_in_utc() {
+        assert_eq!(iso_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(iso_timestamp(time), "2024-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn stations_are_grouped_by_type() {
+        let station = |station_type: &str, tag: &str| Station {
+            id: StationId {
+                station_type: station_type.to_string(),
+                tag: tag.to_string(),
+            },
+            name: String::new(),
+            id_for_from: String::new(),
+        };
+
+        assert_eq!(Station::my_wave().category(), StationCategory::MyWave);
+        assert_eq!(station("genre", "rock").category(), StationCategory::Genre);
+        assert_eq!(
+            station("epoch", "nineties").category(),
+            StationCategory::Era
+        );
+        assert_eq!(
+            station("local", "moscow").category(),
+            StationCategory::Other(String::from("local"))
+        );
+    }
+}
@@ -0,0 +1,749 @@
+use rodio::Decoder;
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::cache::PARTIAL_SUFFIX;
+use crate::error::{Error, Result};
+
+/// Reading this far past the downloaded bytes waits for them instead of requesting a range
+const JUMP_DISTANCE: u64 = 256 * 1024;
+const CHUNK_SIZE: usize = 16 * 1024;
+/// A connection sending nothing for this long fails, so that a stalled download ends
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Agent for the requests of the service and the downloads, they fail when they stall
+pub(crate) fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout_read(READ_TIMEOUT).build()
+}
+
+/// Audio to play: a downloaded file or one still being downloaded
+#[derive(Clone, Debug)]
+pub enum Media {
+    File(String),
+    Stream(Stream),
+}
+
+impl Media {
+    pub fn path(&self) -> &Path {
+        match self {
+            Media::File(path) => Path::new(path),
+            Media::Stream(stream) => stream.path(),
+        }
+    }
+
+    /// All of the file is on disk
+    pub fn is_complete(&self) -> bool {
+        match self {
+            Media::File(_) => true,
+            Media::Stream(stream) => stream.is_complete(),
+        }
+    }
+
+    pub(crate) fn open(&self) -> Result<MediaReader> {
+        Ok(match self {
+            Media::File(path) => MediaReader::File(BufReader::new(File::open(path)?)),
+            Media::Stream(stream) => MediaReader::Stream(BufReader::new(stream.reader()?)),
+        })
+    }
+
+    /// Decoder of the codec the extension names, the file is probed for other extensions
+    pub(crate) fn decode(&self) -> Result<Decoder<MediaReader>> {
+        let reader = self.open()?;
+        let extension = self.path().extension().and_then(|ext| ext.to_str());
+        Ok(match extension {
+            Some("mp3") => Decoder::new_mp3(reader)?,
+            Some("flac") => Decoder::new_flac(reader)?,
+            #[cfg(feature = "aac")]
+            Some("aac") => Decoder::new_aac(reader)?,
+            #[cfg(not(feature = "aac"))]
+            Some("aac") => {
+                return Err(Error::Decode(String::from(
+                    "aac is not supported, it takes the aac feature",
+                )))
+            }
+            _ => Decoder::new(reader)?,
+        })
+    }
+}
+
+impl From<&str> for Media {
+    fn from(path: &str) -> Self {
+        Media::File(path.to_string())
+    }
+}
+
+impl From<String> for Media {
+    fn from(path: String) -> Self {
+        Media::File(path)
+    }
+}
+
+impl From<Stream> for Media {
+    fn from(stream: Stream) -> Self {
+        Media::Stream(stream)
+    }
+}
+
+pub(crate) enum MediaReader {
+    File(BufReader<File>),
+    Stream(BufReader<StreamReader>),
+}
+
+impl Read for MediaReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MediaReader::File(file) => file.read(buf),
+            MediaReader::Stream(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Seek for MediaReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            MediaReader::File(file) => file.seek(pos),
+            MediaReader::Stream(stream) => stream.seek(pos),
+        }
+    }
+}
+
+type RequestFn = Box<dyn Fn() -> ureq::Request + Send>;
+type RewriteFn = Box<dyn FnOnce(&Path) -> PathBuf + Send>;
+
+struct State {
+    // Downloaded byte ranges, sorted and apart from each other
+    ranges: Vec<(u64, u64)>,
+    total: Option<u64>,
+    // The next byte the download writes
+    offset: u64,
+    // A reader needs the byte far from the download, it is requested with a range
+    wanted: Option<u64>,
+    error: Option<String>,
+    // The file is written here until it is complete
+    path: PathBuf,
+    complete: bool,
+    cancelled: bool,
+}
+
+impl State {
+    /// End of the downloaded range with the byte
+    fn covered(&self, pos: u64) -> Option<u64> {
+        self.ranges
+            .iter()
+            .find(|(start, end)| (*start..*end).contains(&pos))
+            .map(|(_, end)| *end)
+    }
+
+    fn insert(&mut self, start: u64, end: u64) {
+        self.ranges.push((start, end));
+        self.ranges.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.ranges.len());
+        for (start, end) in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    /// First byte that is not downloaded yet
+    fn first_gap(&self) -> Option<u64> {
+        let total = self.total?;
+        let gap = match self.ranges.first() {
+            Some((0, end)) => *end,
+            _ => 0,
+        };
+        (gap < total).then_some(gap)
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// End of the downloaded bytes from the position, the position itself at the end of file
+    fn wait(&self, pos: u64) -> io::Result<u64> {
+        let mut state = self.lock();
+        loop {
+            if let Some(end) = state.covered(pos) {
+                return Ok(end);
+            }
+            // A file that is not kept is not read to its end
+            if let Some(err) = &state.error {
+                return Err(io::Error::other(err.clone()));
+            }
+            if state.total.is_some_and(|total| pos >= total) || state.complete {
+                return Ok(pos);
+            }
+            if pos < state.offset || pos > state.offset + JUMP_DISTANCE {
+                state.wanted = Some(pos);
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    fn wait_total(&self) -> io::Result<u64> {
+        let mut state = self.lock();
+        loop {
+            if let Some(total) = state.total {
+                return Ok(total);
+            }
+            if let Some(err) = &state.error {
+                return Err(io::Error::other(err.clone()));
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
+/// File downloaded in the background that can be read while it grows. Bytes far ahead
+/// of the download are requested with an HTTP range, the skipped ones are fetched after
+#[derive(Clone)]
+pub struct Stream {
+    shared: Arc<Shared>,
+    path: PathBuf,
+}
+
+impl Stream {
+    /// Download the URL to the path, it is written next to it until it is complete
+    pub fn start(url: &str, path: impl Into<PathBuf>) -> Result<Self> {
+        let agent = agent();
+        let url = url.to_string();
+        Self::with_request(move || agent.get(&url), path, Path::to_path_buf, || {})
+    }
+
+    /// Download with the request. Once all of it is there `rewrite` returns the file to
+    /// keep, the downloaded one or a changed copy of it, and `finished` is called after
+    /// it is moved to the path
+    pub(crate) fn with_request(
+        request: impl Fn() -> ureq::Request + Send + 'static,
+        path: impl Into<PathBuf>,
+        rewrite: impl FnOnce(&Path) -> PathBuf + Send + 'static,
+        finished: impl FnOnce() + Send + 'static,
+    ) -> Result<Self> {
+        let path = path.into();
+        let mut partial = path.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+        let file = File::create(&partial)?;
+
+        let stream = Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    ranges: Vec::new(),
+                    total: None,
+                    offset: 0,
+                    wanted: None,
+                    error: None,
+                    path: partial,
+                    complete: false,
+                    cancelled: false,
+                }),
+                changed: Condvar::new(),
+            }),
+            path,
+        };
+        let download = stream.clone();
+        std::thread::spawn(move || {
+            if download.download(Box::new(request), Box::new(rewrite), file) {
+                finished();
+            }
+        });
+        Ok(stream)
+    }
+
+    /// Path of the file once it is complete
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.shared.lock().complete
+    }
+
+    /// Number of bytes downloaded so far
+    pub fn downloaded(&self) -> u64 {
+        let state = self.shared.lock();
+        state.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// Size of the file, None until the server tells it
+    pub fn total(&self) -> Option<u64> {
+        self.shared.lock().total
+    }
+
+    /// Error the download stopped with
+    pub fn error(&self) -> Option<String> {
+        self.shared.lock().error.clone()
+    }
+
+    /// Stop the download, the partial file is removed. Readers get an error for the
+    /// bytes that are not there right away, the download ends with its next bytes
+    pub fn cancel(&self) {
+        let mut state = self.shared.lock();
+        if state.complete || state.cancelled {
+            return;
+        }
+        state.cancelled = true;
+        state.error = Some(String::from("The download is cancelled"));
+        let _ = fs::remove_file(&state.path);
+        self.shared.changed.notify_all();
+    }
+
+    /// Block until the file is complete
+    pub fn wait(&self) -> Result<()> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(err) = &state.error {
+                return Err(Error::Network(err.clone()));
+            }
+            if state.complete {
+                return Ok(());
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Reader of the file from the start, it waits for the bytes that are not there yet
+    pub fn reader(&self) -> Result<StreamReader> {
+        let state = self.shared.lock();
+        Ok(StreamReader {
+            shared: self.shared.clone(),
+            file: File::open(&state.path)?,
+            pos: 0,
+        })
+    }
+
+    /// Download all of the file, false if it failed
+    fn download(&self, request: RequestFn, rewrite: RewriteFn, mut file: File) -> bool {
+        let mut from = Some(0);
+        while let Some(start) = from {
+            match self.fetch(&request, &mut file, start) {
+                Ok(next) => from = next,
+                Err(err) => {
+                    let mut state = self.shared.lock();
+                    // The partial file is removed by `cancel`
+                    if !state.cancelled {
+                        state.error = Some(err.to_string());
+                    }
+                    self.shared.changed.notify_all();
+                    return false;
+                }
+            }
+        }
+
+        // The readers opened before keep reading the downloaded file, the later ones
+        // read the kept one, it may be longer
+        let partial = self.shared.lock().path.clone();
+        let kept = rewrite(&partial);
+        let mut state = self.shared.lock();
+        if state.cancelled {
+            if kept != partial {
+                let _ = fs::remove_file(&kept);
+            }
+            return false;
+        }
+        let renamed = fs::rename(&kept, &self.path).and_then(|()| fs::metadata(&self.path));
+        match &renamed {
+            Ok(metadata) => {
+                state.path = self.path.clone();
+                state.total = Some(metadata.len());
+                state.ranges = vec![(0, metadata.len())];
+                if kept != partial {
+                    let _ = fs::remove_file(&partial);
+                }
+                state.complete = true;
+            }
+            Err(err) => {
+                // The file is not complete without its path
+                if kept != partial {
+                    let _ = fs::remove_file(&kept);
+                }
+                state.error = Some(err.to_string());
+            }
+        }
+        self.shared.changed.notify_all();
+        renamed.is_ok()
+    }
+
+    /// Download from the byte until the end or a downloaded range, return the next byte
+    /// that is missing
+    fn fetch(&self, request: &RequestFn, file: &mut File, from: u64) -> Result<Option<u64>> {
+        let response = request().set("Range", &format!("bytes={}-", from)).call()?;
+        let length = response
+            .header("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok());
+        // The server may send all of the file instead of the range
+        let (mut offset, total) = match response.status() {
+            206 => {
+                let total = response
+                    .header("Content-Range")
+                    .and_then(|range| range.rsplit('/').next())
+                    .and_then(|total| total.parse().ok());
+                (from, total)
+            }
+            _ => (0, length),
+        };
+        {
+            let mut state = self.shared.lock();
+            state.total = state.total.or(total);
+            state.offset = offset;
+        }
+
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = response.into_reader();
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            file.write_all(&buf[..len])?;
+
+            let mut state = self.shared.lock();
+            if state.cancelled {
+                return Err(Error::Network(String::from("The download is cancelled")));
+            }
+            state.insert(offset, offset + len as u64);
+            offset += len as u64;
+            state.offset = offset;
+            self.shared.changed.notify_all();
+            if let Some(wanted) = state.wanted.take() {
+                if state.covered(wanted).is_none() {
+                    return Ok(Some(wanted));
+                }
+            }
+            // The rest is downloaded already
+            if state.covered(offset).is_some() {
+                break;
+            }
+        }
+
+        let mut state = self.shared.lock();
+        state.total = state.total.or(Some(offset));
+        Ok(state.first_gap())
+    }
+}
+
+impl std::fmt::Debug for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stream").field("path", &self.path).finish()
+    }
+}
+
+/// Reader of a stream, it blocks until the bytes it reads are downloaded
+pub struct StreamReader {
+    shared: Arc<Shared>,
+    file: File,
+    pos: u64,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let end = self.shared.wait(self.pos)?;
+        let len = buf.len().min((end - self.pos) as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        let len = self.file.read(&mut buf[..len])?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.shared.wait_total()?.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of file")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::{Cover, Tags};
+    use crate::testing::{serve, TempDir};
+    use rodio::Source;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../tests/fixtures/sine-440.wav"
+    );
+    const FLAC_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../tests/fixtures/sine-440.flac"
+    );
+
+    #[test]
+    fn stream_can_be_read_while_it_is_downloaded() {
+        let body = fs::read(FIXTURE).unwrap();
+        let (url, _) = serve(body.clone(), 8000);
+        let dir = TempDir::new("stream-stream");
+        let path = dir.path().join("track.wav");
+        let stream = Stream::start(&url, &path).unwrap();
+
+        let started = Instant::now();
+        let mut head = vec![0; 1000];
+        stream.reader().unwrap().read_exact(&mut head).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert!(!stream.is_complete());
+        assert_eq!(head, body[..1000]);
+
+        let mut all = Vec::new();
+        stream.reader().unwrap().read_to_end(&mut all).unwrap();
+        assert_eq!(all, body);
+        stream.wait().unwrap();
+        assert_eq!(stream.total(), Some(body.len() as u64));
+        assert_eq!(fs::read(&path).unwrap(), body);
+    }
+
+    #[test]
+    fn stream_is_read_to_the_end_once_it_is_tagged() {
+        let (url, _) = serve(fs::read(FLAC_FIXTURE).unwrap(), 1_000_000);
+        let dir = TempDir::new("stream-tagged");
+        let path = dir.path().join("track.flac");
+        let agent = ureq::AgentBuilder::new().build();
+        let tag = |file: &Path| {
+            let tags = Tags {
+                title: String::from("Tagged"),
+                cover: Some(Cover {
+                    mime_type: String::from("image/png"),
+                    data: vec![0; 100_000],
+                }),
+                ..Tags::default()
+            };
+            tags.write_copy(file, "flac").unwrap().unwrap()
+        };
+        let stream = Stream::with_request(move || agent.get(&url), &path, tag, || {}).unwrap();
+        stream.wait().unwrap();
+        assert_eq!(stream.total(), Some(fs::metadata(&path).unwrap().len()));
+
+        let position = Duration::from_millis(500);
+        let media = Media::Stream(stream);
+        let samples: Vec<i16> = media.decode().unwrap().skip_duration(position).collect();
+        let fixture = BufReader::new(File::open(FLAC_FIXTURE).unwrap());
+        let expected: Vec<i16> = Decoder::new_flac(fixture)
+            .unwrap()
+            .skip_duration(position)
+            .collect();
+        assert_eq!(samples.len(), 4000);
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn stream_fails_when_file_cannot_be_kept() {
+        let body = fs::read(FIXTURE).unwrap();
+        let (url, _) = serve(body, 1_000_000);
+        let dir = TempDir::new("stream-kept");
+        let path = dir.path().join("track.wav");
+        // A file is not moved over a directory
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("file"), b"taken").unwrap();
+        let stream = Stream::start(&url, &path).unwrap();
+
+        assert!(matches!(stream.wait(), Err(Error::Network(_))));
+        assert!(!stream.is_complete());
+        let mut all = Vec::new();
+        assert!(stream.reader().unwrap().read_to_end(&mut all).is_err());
+    }
+
+    #[test]
+    fn stream_requests_range_to_seek_ahead() {
+        let body: Vec<u8> = (0..2_000_000).map(|i| (i % 251) as u8).collect();
+        let (url, requested) = serve(body.clone(), 100_000);
+        let dir = TempDir::new("stream-seek");
+        let path = dir.path().join("track.wav");
+        let stream = Stream::start(&url, &path).unwrap();
+
+        let started = Instant::now();
+        let mut reader = stream.reader().unwrap();
+        assert_eq!(reader.seek(SeekFrom::End(-1000)).unwrap(), 1_999_000);
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, body[1_999_000..]);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(requested.lock().unwrap().contains(&1_999_000));
+
+        // The bytes before it are downloaded after the tail
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut head = vec![0; 100];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(head, body[10..110]);
+        assert!(stream.downloaded() < body.len() as u64);
+    }
+
+    fn crc8(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0, |mut crc, byte| {
+            crc ^= byte;
+            for _ in 0..8 {
+                crc = if crc & 0x80 != 0 {
+                    (crc << 1) ^ 0x07
+                } else {
+                    crc << 1
+                };
+            }
+            crc
+        })
+    }
+
+    fn crc16(bytes: &[u8]) -> u16 {
+        bytes.iter().fold(0, |mut crc, &byte| {
+            crc ^= (byte as u16) << 8;
+            for _ in 0..8 {
+                crc = if crc & 0x8000 != 0 {
+                    (crc << 1) ^ 0x8005
+                } else {
+                    crc << 1
+                };
+            }
+            crc
+        })
+    }
+
+    /// Mono 16 bit FLAC keeping the samples verbatim in frames of 256 samples
+    fn flac(samples: &[i16], rate: u32) -> Vec<u8> {
+        const BLOCK: usize = 256;
+        let mut file = b"fLaC".to_vec();
+        // The last metadata block is the stream info of 34 bytes
+        file.extend([0x80, 0, 0, 34]);
+        file.extend((BLOCK as u16).to_be_bytes());
+        file.extend((BLOCK as u16).to_be_bytes());
+        file.extend([0; 6]);
+        let format = (rate as u64) << 44 | 15 << 36 | samples.len() as u64;
+        file.extend(format.to_be_bytes());
+        file.extend([0; 16]);
+
+        for (number, block) in samples.chunks(BLOCK).enumerate() {
+            // Fixed block size, the size is after the frame number, the rate is in the
+            // stream info, one channel of 16 bits
+            let mut frame = vec![0xff, 0xf8, 0x60, 0x08, number as u8];
+            frame.push((block.len() - 1) as u8);
+            frame.push(crc8(&frame));
+            // Verbatim subframe
+            frame.push(0x02);
+            for sample in block {
+                frame.extend(sample.to_be_bytes());
+            }
+            frame.extend(crc16(&frame).to_be_bytes());
+            file.extend(frame);
+        }
+        file
+    }
+
+    #[test]
+    fn media_is_decoded_with_codec_of_extension() {
+        let samples: Vec<i16> = (0..1000).map(|i| (i * 37 % 2000 - 1000) as i16).collect();
+        let dir = TempDir::new("stream-codec");
+        let path = dir.path().join("track.flac");
+        fs::write(&path, flac(&samples, 8000)).unwrap();
+
+        let decoder = Media::from(path.to_string_lossy().into_owned())
+            .decode()
+            .unwrap();
+        assert_eq!(decoder.channels(), 1);
+        assert_eq!(decoder.sample_rate(), 8000);
+        assert_eq!(decoder.collect::<Vec<i16>>(), samples);
+
+        // It is not probed for another format
+        let aac = path.with_extension("aac");
+        fs::rename(&path, &aac).unwrap();
+        let media = Media::from(aac.to_string_lossy().into_owned());
+        if !cfg!(feature = "aac") {
+            assert!(matches!(media.decode(), Err(Error::Decode(_))));
+        }
+    }
+
+    #[test]
+    fn stream_can_be_cancelled() {
+        let body = fs::read(FIXTURE).unwrap();
+        let (url, _) = serve(body, 8000);
+        let dir = TempDir::new("stream-cancel");
+        let path = dir.path().join("track.wav");
+        let stream = Stream::start(&url, &path).unwrap();
+        let mut partial = path.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        assert!(Path::new(&partial).exists());
+
+        stream.cancel();
+        assert!(matches!(stream.wait(), Err(Error::Network(_))));
+        assert!(stream.error().is_some());
+        assert!(!Path::new(&partial).exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn stalled_stream_is_cancelled_at_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/track.mp3", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let mut connection = listener.incoming().next().unwrap().unwrap();
+            for line in BufReader::new(&connection).lines() {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            let header = "HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n";
+            connection.write_all(header.as_bytes()).unwrap();
+            connection.write_all(&[0; 10]).unwrap();
+            // Nothing more is sent while the test runs
+            std::thread::sleep(Duration::from_secs(10));
+        });
+        let dir = TempDir::new("stream-stalled");
+        let path = dir.path().join("track.wav");
+        let stream = Stream::start(&url, &path).unwrap();
+        let reader = stream.clone();
+        let read = std::thread::spawn(move || {
+            let mut all = Vec::new();
+            reader.reader().unwrap().read_to_end(&mut all)
+        });
+        std::thread::sleep(Duration::from_millis(200));
+
+        let started = Instant::now();
+        stream.cancel();
+        assert!(read.join().unwrap().is_err());
+        assert!(matches!(stream.wait(), Err(Error::Network(_))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(fs::read_dir(dir.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn streamed_track_is_played_before_it_is_downloaded() {
+        let body = fs::read(FIXTURE).unwrap();
+        let (url, _) = serve(body, 8000);
+        let dir = TempDir::new("stream-play");
+        let path = dir.path().join("track.wav");
+        let stream = Stream::start(&url, &path).unwrap();
+
+        let mut player = crate::Player::headless();
+        player.append(stream.clone()).unwrap();
+        player.play().unwrap();
+        assert!(!stream.is_complete());
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(matches!(player.status(), Ok(crate::Status::Playing(..))));
+        drop(player);
+
+        stream.wait().unwrap();
+    }
+}
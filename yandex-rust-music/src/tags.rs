@@ -0,0 +1,476 @@
+use id3::frame::{ExtendedText, Picture, PictureType};
+use id3::{TagLike, Version};
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::PARTIAL_SUFFIX;
+use crate::error::{Error, Result};
+use crate::track::Track;
+
+/// Name of the tag keeping the id of the track on Yandex Music
+const TRACK_ID_TAG: &str = "YANDEX_TRACK_ID";
+const VENDOR: &str = "yandex-rust-music";
+
+const FLAC_MARKER: &[u8] = b"fLaC";
+const PADDING_BLOCK: u8 = 1;
+const VORBIS_COMMENT_BLOCK: u8 = 4;
+const PICTURE_BLOCK: u8 = 6;
+/// Type of the front cover among the pictures of FLAC and ID3
+const FRONT_COVER: u32 = 3;
+/// FLAC metadata blocks are at most this long
+const MAX_BLOCK_SIZE: usize = (1 << 24) - 1;
+
+/// Writes the tagged copy of the file to the second path
+type WriteFn = fn(&Tags, &Path, &Path) -> Result<()>;
+
+/// Picture embedded into the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cover {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Details of the track written into its file, so that other players show them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub album_artists: Vec<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    /// Id of the track on Yandex Music
+    pub track_id: Option<String>,
+    pub cover: Option<Cover>,
+}
+
+impl Tags {
+    /// Tags of the track without the cover, it is downloaded separately
+    pub fn of(track: &Track) -> Self {
+        let names = |artists: &[crate::artist::Artist]| {
+            artists.iter().map(|artist| artist.name.clone()).collect()
+        };
+        Self {
+            title: track.title.clone(),
+            artists: names(&track.artists),
+            album: track.album_title.clone(),
+            album_artists: names(&track.album_artists),
+            track_number: track.track_number,
+            disc_number: track.disc_number,
+            year: track.year,
+            genre: track.genre.clone(),
+            track_id: Some(track.id.clone()),
+            cover: None,
+        }
+    }
+
+    /// Replace the tags of the file: ID3v2.4 for mp3, Vorbis comments for FLAC. AAC files
+    /// are not tagged, they are raw ADTS streams without a place for tags, and are left as
+    /// they are like the files of other codecs. The tagged file is written next to the path
+    /// and moved over it, so that the file is not changed under its readers
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let extension = extension(path).unwrap_or_default();
+        if let Some(temp) = self.write_copy(path, &extension)? {
+            if let Err(err) = fs::rename(&temp, path) {
+                let _ = fs::remove_file(&temp);
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Files of the codec the extension names are tagged by `write`
+    pub fn can_tag(extension: &str) -> bool {
+        Self::writer(extension).is_some()
+    }
+
+    /// Copy of the file of the codec the extension names with these tags, written next to
+    /// it. None for the codecs that are not tagged
+    pub(crate) fn write_copy(&self, path: &Path, extension: &str) -> Result<Option<PathBuf>> {
+        let write = match Self::writer(extension) {
+            Some(write) => write,
+            None => return Ok(None),
+        };
+        let mut temp = OsString::from(path.as_os_str());
+        temp.push(PARTIAL_SUFFIX);
+        let temp = PathBuf::from(temp);
+        match write(self, path, &temp) {
+            Ok(()) => Ok(Some(temp)),
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                Err(err)
+            }
+        }
+    }
+
+    fn writer(extension: &str) -> Option<WriteFn> {
+        match extension {
+            "mp3" => Some(Self::write_id3),
+            "flac" => Some(Self::write_flac),
+            _ => None,
+        }
+    }
+
+    /// Tags of the file as `write` puts them
+    pub fn read(path: &Path) -> Result<Self> {
+        match extension(path).as_deref() {
+            Some("mp3") => Self::read_id3(path),
+            Some("flac") => Self::read_flac(path),
+            _ => Err(Error::Tag(format!("{} has no known tags", path.display()))),
+        }
+    }
+
+    fn write_id3(&self, path: &Path, temp: &Path) -> Result<()> {
+        let mut tag = id3::Tag::new();
+        tag.set_title(&self.title);
+        if !self.artists.is_empty() {
+            tag.set_text_values("TPE1", &self.artists);
+        }
+        if let Some(album) = &self.album {
+            tag.set_album(album);
+        }
+        if !self.album_artists.is_empty() {
+            tag.set_text_values("TPE2", &self.album_artists);
+        }
+        if let Some(number) = self.track_number {
+            tag.set_track(number);
+        }
+        if let Some(number) = self.disc_number {
+            tag.set_disc(number);
+        }
+        if let Some(year) = self.year {
+            tag.set_text("TDRC", format!("{:04}", year));
+        }
+        if let Some(genre) = &self.genre {
+            tag.set_genre(genre);
+        }
+        if let Some(id) = &self.track_id {
+            tag.add_frame(ExtendedText {
+                description: TRACK_ID_TAG.to_string(),
+                value: id.clone(),
+            });
+        }
+        if let Some(cover) = &self.cover {
+            tag.add_frame(Picture {
+                mime_type: cover.mime_type.clone(),
+                picture_type: PictureType::CoverFront,
+                description: String::new(),
+                data: cover.data.clone(),
+            });
+        }
+        fs::copy(path, temp)?;
+        tag.write_to_path(temp, Version::Id3v24)?;
+        Ok(())
+    }
+
+    fn read_id3(path: &Path) -> Result<Self> {
+        let tag = id3::Tag::read_from_path(path)?;
+        let values = |id: &str| -> Vec<String> {
+            tag.get(id)
+                .and_then(|frame| frame.content().text_values())
+                .map(|values| values.map(String::from).collect())
+                .unwrap_or_default()
+        };
+        let track_id = tag
+            .extended_texts()
+            .find(|text| text.description == TRACK_ID_TAG)
+            .map(|text| text.value.clone());
+        let cover = tag
+            .pictures()
+            .find(|picture| picture.picture_type == PictureType::CoverFront)
+            .map(|picture| Cover {
+                mime_type: picture.mime_type.clone(),
+                data: picture.data.clone(),
+            });
+        Ok(Self {
+            title: tag.title().unwrap_or_default().to_string(),
+            artists: values("TPE1"),
+            album: tag.album().map(String::from),
+            album_artists: values("TPE2"),
+            track_number: tag.track(),
+            disc_number: tag.disc(),
+            year: tag.date_recorded().map(|date| date.year as u32),
+            genre: tag.genre().map(String::from),
+            track_id,
+            cover,
+        })
+    }
+
+    /// Vorbis comments, a field is repeated for several values
+    fn comments(&self) -> Vec<(&str, String)> {
+        let mut comments = vec![("TITLE", self.title.clone())];
+        comments.extend(self.artists.iter().map(|name| ("ARTIST", name.clone())));
+        comments.extend(self.album.iter().map(|album| ("ALBUM", album.clone())));
+        comments.extend(
+            self.album_artists
+                .iter()
+                .map(|name| ("ALBUMARTIST", name.clone())),
+        );
+        comments.extend(self.track_number.map(|n| ("TRACKNUMBER", n.to_string())));
+        comments.extend(self.disc_number.map(|n| ("DISCNUMBER", n.to_string())));
+        comments.extend(self.year.map(|year| ("DATE", year.to_string())));
+        comments.extend(self.genre.iter().map(|genre| ("GENRE", genre.clone())));
+        comments.extend(self.track_id.iter().map(|id| (TRACK_ID_TAG, id.clone())));
+        comments
+    }
+
+    /// The metadata blocks of the tags and the padding are replaced, the others and the
+    /// audio frames are kept
+    fn write_flac(&self, path: &Path, temp: &Path) -> Result<()> {
+        let data = fs::read(path)?;
+        let (blocks, audio) = flac_blocks(&data)?;
+        let mut blocks: Vec<(u8, Vec<u8>)> = blocks
+            .into_iter()
+            .filter(|(kind, _)| {
+                ![PADDING_BLOCK, VORBIS_COMMENT_BLOCK, PICTURE_BLOCK].contains(kind)
+            })
+            .map(|(kind, block)| (kind, block.to_vec()))
+            .collect();
+
+        let mut comment = Vec::new();
+        push_le(&mut comment, VENDOR.as_bytes());
+        let comments = self.comments();
+        comment.extend((comments.len() as u32).to_le_bytes());
+        for (field, value) in comments {
+            push_le(&mut comment, format!("{}={}", field, value).as_bytes());
+        }
+        blocks.push((VORBIS_COMMENT_BLOCK, comment));
+        if let Some(cover) = &self.cover {
+            let mut picture = FRONT_COVER.to_be_bytes().to_vec();
+            push_be(&mut picture, cover.mime_type.as_bytes());
+            push_be(&mut picture, b"");
+            // Width, height, color depth and the number of colors are unknown
+            picture.extend([0; 16]);
+            push_be(&mut picture, &cover.data);
+            blocks.push((PICTURE_BLOCK, picture));
+        }
+
+        let mut tagged = FLAC_MARKER.to_vec();
+        let count = blocks.len();
+        for (i, (kind, block)) in blocks.into_iter().enumerate() {
+            if block.len() > MAX_BLOCK_SIZE {
+                return Err(Error::Tag(String::from("The cover is too large for FLAC")));
+            }
+            let last = if i + 1 == count { 0x80 } else { 0 };
+            tagged.push(last | kind);
+            tagged.extend(&(block.len() as u32).to_be_bytes()[1..]);
+            tagged.extend(block);
+        }
+        tagged.extend(&data[audio..]);
+        fs::write(temp, tagged)?;
+        Ok(())
+    }
+
+    fn read_flac(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        let (blocks, _) = flac_blocks(&data)?;
+        let mut tags = Self::default();
+        for (kind, block) in blocks {
+            let mut reader = BlockReader { block, pos: 0 };
+            match kind {
+                VORBIS_COMMENT_BLOCK => {
+                    reader.le_bytes()?;
+                    for _ in 0..reader.le_u32()? {
+                        let comment = String::from_utf8_lossy(reader.le_bytes()?);
+                        if let Some((field, value)) = comment.split_once('=') {
+                            tags.set_comment(field, value.to_string());
+                        }
+                    }
+                }
+                PICTURE_BLOCK if reader.be_u32()? == FRONT_COVER => {
+                    let mime_type = String::from_utf8_lossy(reader.be_bytes()?).into_owned();
+                    reader.be_bytes()?;
+                    reader.take(16)?;
+                    let data = reader.be_bytes()?.to_vec();
+                    tags.cover = Some(Cover { mime_type, data });
+                }
+                _ => {}
+            }
+        }
+        Ok(tags)
+    }
+
+    fn set_comment(&mut self, field: &str, value: String) {
+        match field.to_uppercase().as_str() {
+            "TITLE" => self.title = value,
+            "ARTIST" => self.artists.push(value),
+            "ALBUM" => self.album = Some(value),
+            "ALBUMARTIST" => self.album_artists.push(value),
+            "TRACKNUMBER" => self.track_number = value.parse().ok(),
+            "DISCNUMBER" => self.disc_number = value.parse().ok(),
+            "DATE" => self.year = value.get(..4).and_then(|year| year.parse().ok()),
+            "GENRE" => self.genre = Some(value),
+            TRACK_ID_TAG => self.track_id = Some(value),
+            _ => {}
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+fn push_le(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend((bytes.len() as u32).to_le_bytes());
+    buf.extend(bytes);
+}
+
+fn push_be(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend((bytes.len() as u32).to_be_bytes());
+    buf.extend(bytes);
+}
+
+fn not_flac() -> Error {
+    Error::Tag(String::from("The file is not a valid FLAC"))
+}
+
+/// Metadata block of a FLAC file with its type
+type Block<'a> = (u8, &'a [u8]);
+
+/// Metadata blocks of the FLAC file, and where its audio frames start
+fn flac_blocks(data: &[u8]) -> Result<(Vec<Block<'_>>, usize)> {
+    if !data.starts_with(FLAC_MARKER) {
+        return Err(not_flac());
+    }
+    let mut blocks = Vec::new();
+    let mut pos = FLAC_MARKER.len();
+    loop {
+        let header = data.get(pos..pos + 4).ok_or_else(not_flac)?;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let block = data.get(pos + 4..pos + 4 + size).ok_or_else(not_flac)?;
+        blocks.push((header[0] & 0x7f, block));
+        pos += 4 + size;
+        if header[0] & 0x80 != 0 {
+            return Ok((blocks, pos));
+        }
+    }
+}
+
+/// Fields of a metadata block, Vorbis comments are little endian unlike the rest of FLAC
+struct BlockReader<'a> {
+    block: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BlockReader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .block
+            .get(self.pos..self.pos + size)
+            .ok_or_else(not_flac)?;
+        self.pos += size;
+        Ok(bytes)
+    }
+
+    fn le_u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn be_u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn le_bytes(&mut self) -> Result<&'a [u8]> {
+        let size = self.le_u32()? as usize;
+        self.take(size)
+    }
+
+    fn be_bytes(&mut self) -> Result<&'a [u8]> {
+        let size = self.be_u32()? as usize;
+        self.take(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::Media;
+    use crate::testing::TempDir;
+    use rodio::Source;
+
+    const FLAC_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../tests/fixtures/sine-440.flac"
+    );
+
+    fn tags() -> Tags {
+        Tags {
+            title: String::from("Harder, Better, Faster, Stronger"),
+            artists: vec![String::from("Daft Punk"), String::from("Kanye West")],
+            album: Some(String::from("Discovery")),
+            album_artists: vec![String::from("Daft Punk")],
+            track_number: Some(4),
+            disc_number: Some(1),
+            year: Some(2001),
+            genre: Some(String::from("dance")),
+            track_id: Some(String::from("1710811")),
+            cover: Some(Cover {
+                mime_type: String::from("image/jpeg"),
+                data: vec![0xff, 0xd8, 0xff, 0xe0, 1, 2, 3, 0xff, 0xd9],
+            }),
+        }
+    }
+
+    #[test]
+    fn mp3_tags_are_read_back() {
+        let dir = TempDir::new("tags-mp3");
+        let path = dir.path().join("track.mp3");
+        let payload = b"\xff\xfb\x90\x00 fake mp3 frames";
+        fs::write(&path, payload).unwrap();
+        let tags = tags();
+        tags.write(&path).unwrap();
+        assert_eq!(Tags::read(&path).unwrap(), tags);
+
+        // Tagging again replaces the tags
+        let retagged = Tags {
+            title: String::from("One More Time"),
+            ..Tags::default()
+        };
+        retagged.write(&path).unwrap();
+        assert_eq!(Tags::read(&path).unwrap(), retagged);
+        assert!(fs::read(&path).unwrap().ends_with(payload));
+    }
+
+    #[test]
+    fn flac_tags_are_read_back() {
+        let dir = TempDir::new("tags-flac");
+        let path = dir.path().join("track.flac");
+        fs::copy(FLAC_FIXTURE, &path).unwrap();
+        let tags = tags();
+        tags.write(&path).unwrap();
+        assert_eq!(Tags::read(&path).unwrap(), tags);
+        tags.write(&path).unwrap();
+        assert_eq!(Tags::read(&path).unwrap(), tags);
+
+        // The audio stays the same
+        let decoded = |path: &Path| -> Vec<i16> {
+            let media = Media::File(path.to_string_lossy().into_owned());
+            let decoder = media.decode().unwrap();
+            assert_eq!(decoder.sample_rate(), 8000);
+            decoder.collect()
+        };
+        assert_eq!(decoded(&path), decoded(Path::new(FLAC_FIXTURE)));
+    }
+
+    #[test]
+    fn files_of_other_codecs_are_not_tagged() {
+        let dir = TempDir::new("tags-other");
+        let path = dir.path().join("track.aac");
+        fs::write(&path, b"aac frames").unwrap();
+        tags().write(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"aac frames");
+        assert!(Tags::read(&path).is_err());
+
+        let broken = dir.path().join("broken.flac");
+        fs::write(&broken, b"fLaC\x80\x00\x00").unwrap();
+        assert!(matches!(tags().write(&broken), Err(Error::Tag(_))));
+        assert_eq!(fs::read(&broken).unwrap(), b"fLaC\x80\x00\x00");
+    }
+}
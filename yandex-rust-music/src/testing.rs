@@ -0,0 +1,94 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::track::Track;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty directory for the files of a test, it is removed with them when it is dropped,
+/// also when the test panics
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// The name tells the directories of the tests apart, they are unique anyway
+    pub fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "yandex-music-{}-{}-{}",
+            name,
+            std::process::id(),
+            id
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // The files are left behind if they are still open, it is only the temp dir
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Track of a second without an album, the title is its id too
+pub fn track(title: &str) -> Track {
+    Track::local(title.to_string(), title, Duration::from_secs(1), None)
+}
+
+/// Serve the file at the returned URL, sending `rate` bytes a second.
+/// The first bytes of the requested ranges are noted
+pub fn serve(body: Vec<u8>, rate: usize) -> (String, Arc<Mutex<Vec<u64>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/track.mp3", listener.local_addr().unwrap());
+    let requested = Arc::new(Mutex::new(Vec::new()));
+    let body = Arc::new(body);
+    let ranges = requested.clone();
+    std::thread::spawn(move || {
+        for connection in listener.incoming() {
+            let (body, ranges) = (body.clone(), ranges.clone());
+            std::thread::spawn(move || {
+                let mut connection = connection.unwrap();
+                let mut from = 0;
+                for line in BufReader::new(&connection).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(range) = line.strip_prefix("Range: bytes=") {
+                        from = range.trim_end_matches('-').parse().unwrap();
+                    }
+                }
+                ranges.lock().unwrap().push(from as u64);
+                let header = format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+                     Content-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                    body.len() - from,
+                    from,
+                    body.len() - 1,
+                    body.len()
+                );
+                connection.write_all(header.as_bytes()).unwrap();
+                // Chunks of 10ms
+                let chunk = (rate / 100).max(1);
+                for bytes in body[from..].chunks(chunk) {
+                    if connection.write_all(bytes).is_err() {
+                        return;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            });
+        }
+    });
+    (url, requested)
+}
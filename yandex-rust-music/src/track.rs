@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use crate::album::Album;
+use crate::artist::Artist;
+use crate::client::{string_or_number, Client};
+use crate::error::{Error, Result};
+use crate::stream::Media;
+
+/// Track as it is described by the API
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrackInfo {
+    #[serde(deserialize_with = "string_or_number")]
+    id: String,
+    title: String,
+    #[serde(default)]
+    duration_ms: u64,
+    #[serde(default)]
+    artists: Vec<Artist>,
+    #[serde(default)]
+    albums: Vec<TrackAlbum>,
+    cover_uri: Option<String>,
+    content_warning: Option<String>,
+    #[serde(default = "default_available")]
+    available: bool,
+    #[serde(default)]
+    regions: Vec<String>,
+    #[serde(default)]
+    lyrics_available: bool,
+    lyrics_info: Option<LyricsInfo>,
+    r128: Option<R128>,
+}
+
+/// Album listing the track, with the place of the track on it
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrackAlbum {
+    #[serde(flatten)]
+    album: Album,
+    track_position: Option<TrackPosition>,
+}
+
+#[derive(Deserialize)]
+struct TrackPosition {
+    /// Number of the disc from 1
+    volume: u32,
+    /// Number of the track on the disc from 1
+    index: u32,
+}
+
+/// Loudness measured by the service
+#[derive(Deserialize)]
+struct R128 {
+    /// Integrated loudness in LUFS
+    i: f32,
+    /// True peak in dBTP
+    tp: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LyricsInfo {
+    #[serde(default)]
+    has_available_text_lyrics: bool,
+    #[serde(default)]
+    has_available_sync_lyrics: bool,
+}
+
+fn default_available() -> bool {
+    true
+}
+
+/// Codec and bitrate of the track file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quality {
+    pub codec: String,
+    pub bitrate: u32,
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Self {
+            codec: String::from("mp3"),
+            bitrate: 320,
+        }
+    }
+}
+
+impl Quality {
+    pub fn extension(&self) -> &str {
+        match self.codec.as_str() {
+            "aac" | "he-aac" => "aac",
+            codec => codec,
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        self.codec == "flac"
+    }
+
+    /// The player can decode files of the codec
+    pub fn is_supported(&self) -> bool {
+        match self.codec.as_str() {
+            "mp3" | "flac" => true,
+            "aac" | "he-aac" => cfg!(feature = "aac"),
+            _ => false,
+        }
+    }
+}
+
+/// Which of the variants of the track file is downloaded
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityPreference {
+    /// Take the lossless file when the service has it
+    pub lossless: bool,
+    /// Highest bitrate in kbps, to save the traffic of metered links. Lossless files
+    /// are not taken with it
+    pub max_bitrate: Option<u32>,
+}
+
+impl QualityPreference {
+    fn fits(&self, quality: &Quality) -> bool {
+        match self.max_bitrate {
+            Some(max_bitrate) => !quality.is_lossless() && quality.bitrate <= max_bitrate,
+            None => true,
+        }
+    }
+
+    /// The best of the variants the player can decode: the lossless one if it is
+    /// preferred, then the one of the highest bitrate, mp3 over other codecs of the
+    /// same bitrate. The one of the lowest bitrate if all of them are above the limit
+    pub fn choose<'a>(&self, variants: &'a [Quality]) -> Option<&'a Quality> {
+        self.choose_index(variants).map(|index| &variants[index])
+    }
+
+    /// Position of the variant `choose` takes
+    pub(crate) fn choose_index(&self, variants: &[Quality]) -> Option<usize> {
+        let supported = variants
+            .iter()
+            .enumerate()
+            .filter(|(_, quality)| quality.is_supported());
+        let best = supported
+            .clone()
+            .filter(|(_, quality)| self.fits(quality))
+            .max_by_key(|(_, quality)| {
+                (
+                    quality.is_lossless() == self.lossless,
+                    quality.bitrate,
+                    quality.codec == "mp3",
+                )
+            });
+        best.or_else(|| supported.min_by_key(|(_, quality)| quality.bitrate))
+            .map(|(index, _)| index)
+    }
+}
+
+impl Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.codec, self.bitrate)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Track {
+    pub id: String,
+    pub title: String,
+    pub duration_ms: u64,
+    pub artists: Vec<Artist>,
+    /// The album the track was released on first
+    pub album_id: Option<String>,
+    pub album_title: Option<String>,
+    #[serde(default)]
+    pub album_artists: Vec<Artist>,
+    /// Number of the track on its disc of the album
+    #[serde(default)]
+    pub track_number: Option<u32>,
+    #[serde(default)]
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    /// Cover template like `avatars.yandex.net/get-music-content/.../%%`
+    pub cover_uri: Option<String>,
+    pub explicit: bool,
+    pub available: bool,
+    /// Regions the track is restricted to, empty if it is available everywhere
+    pub regions: Vec<String>,
+    pub lyrics_available: bool,
+    /// Integrated loudness in LUFS if the service measured it
+    #[serde(default)]
+    pub loudness: Option<f32>,
+    /// True peak in dBTP if the service measured it
+    #[serde(default)]
+    pub peak: Option<f32>,
+    // None for tracks that are not served by Yandex Music
+    #[serde(skip)]
+    client: Option<Client>,
+}
+
+impl Track {
+    pub(crate) fn new(info: TrackInfo, client: Client) -> Self {
+        let (album, position) = match info.albums.into_iter().next() {
+            Some(TrackAlbum {
+                album,
+                track_position,
+            }) => (Some(album), track_position),
+            None => (None, None),
+        };
+        let explicit = info.content_warning.as_deref() == Some("explicit");
+        let lyrics_available = info.lyrics_available
+            || info.lyrics_info.is_some_and(|lyrics| {
+                lyrics.has_available_text_lyrics || lyrics.has_available_sync_lyrics
+            });
+        let mut track = Self::with_album(info.id, &info.title, info.duration_ms, album.as_ref());
+        track.artists = info.artists;
+        track.track_number = position.as_ref().map(|position| position.index);
+        track.disc_number = position.map(|position| position.volume);
+        track.cover_uri = info
+            .cover_uri
+            .or_else(|| album.and_then(|album| album.cover_uri));
+        track.explicit = explicit;
+        track.available = info.available;
+        track.regions = info.regions;
+        track.lyrics_available = lyrics_available;
+        track.loudness = info.r128.as_ref().map(|r128| r128.i);
+        track.peak = info.r128.and_then(|r128| r128.tp);
+        track.client = Some(client);
+        track
+    }
+
+    pub(crate) fn local(
+        id: String,
+        title: &str,
+        total_duration: Duration,
+        album: Option<&Album>,
+    ) -> Self {
+        Self::with_album(id, title, total_duration.as_millis() as u64, album)
+    }
+
+    fn with_album(id: String, title: &str, duration_ms: u64, album: Option<&Album>) -> Self {
+        Self {
+            id,
+            title: title.to_string(),
+            duration_ms,
+            artists: album.map(|album| album.artists.clone()).unwrap_or_default(),
+            album_id: album.map(|album| album.id.clone()),
+            album_title: album.map(|album| album.title.clone()),
+            album_artists: album.map(|album| album.artists.clone()).unwrap_or_default(),
+            track_number: None,
+            disc_number: None,
+            year: album.and_then(|album| album.year),
+            genre: album.and_then(|album| album.genre.clone()),
+            cover_uri: album.and_then(|album| album.cover_uri.clone()),
+            explicit: false,
+            available: true,
+            regions: Vec::new(),
+            lyrics_available: false,
+            loudness: None,
+            peak: None,
+            client: None,
+        }
+    }
+
+    fn client(&self) -> Result<&Client> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| Error::NotFound(format!("Track {} is not on Yandex Music", self.title)))
+    }
+
+    /// Codecs and bitrates the file of the track can be downloaded in
+    pub fn download_variants(&self) -> Result<Vec<Quality>> {
+        self.client()?.download_variants(&self.id)
+    }
+
+    /// Download the file of the preferred quality unless it is cached, return its path
+    pub fn download(&self) -> Result<String> {
+        let client = self.client()?;
+        let path = match client.cached_file(&self.id) {
+            Some(path) => path,
+            None => {
+                let (quality, url) = client.download_url(&self.id)?;
+                client.download_to(&url, self, &quality)?
+            }
+        };
+
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// Complete file of the preferred quality downloaded before, no request is made
+    pub fn cached_file(&self) -> Option<String> {
+        let path = self.client.as_ref()?.cached_file(&self.id)?;
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    /// Play the file while it is downloaded, the cached one is played if it is there
+    pub fn stream(&self) -> Result<Media> {
+        let client = self.client()?;
+        if let Some(path) = client.cached_file(&self.id) {
+            return Ok(Media::File(path.to_string_lossy().into_owned()));
+        }
+        let (quality, url) = client.download_url(&self.id)?;
+        Ok(Media::Stream(client.stream_to(&url, self, &quality)?))
+    }
+
+    pub fn total_duration(&self) -> Option<Duration> {
+        let total_duration = Duration::from_millis(self.duration_ms);
+        Some(total_duration)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    /// Names of all artists separated by commas
+    pub fn artist_names(&self) -> String {
+        self.artists
+            .iter()
+            .map(|artist| artist.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Link to the cover of the given size (e.g. "200x200")
+    pub fn cover_url(&self, size: &str) -> Option<String> {
+        self.cover_uri
+            .as_ref()
+            .map(|uri| format!("https://{}", uri.replace("%%", size)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn track_json() -> serde_json::Value {
+        json!({
+            "id": "1710808", "title": "One More Time", "durationMs": 320357,
+            "available": true, "contentWarning": "explicit", "regions": ["RUSSIA"],
+            "lyricsInfo": {"hasAvailableTextLyrics": true, "hasAvailableSyncLyrics": false},
+            "r128": {"i": -7.84, "tp": 0.62},
+            "artists": [{"id": 27003, "name": "Daft Punk"}],
+            "albums": [{"id": 175233, "title": "Discovery", "year": 2001, "genre": "dance",
+                        "artists": [{"id": 27003, "name": "Daft Punk"}],
+                        "trackPosition": {"volume": 1, "index": 1},
+                        "coverUri": "avatars.yandex.net/get-music-content/38044/b1f0b3b4.a.175233-1/%%"}]
+        })
+    }
+
+    #[test]
+    fn track_metadata_is_taken_from_api() {
+        let info: TrackInfo = serde_json::from_value(track_json()).unwrap();
+        let client = Client::unauthorized("http://localhost");
+        let track = Track::new(info, client);
+
+        assert_eq!(track.artist_names(), "Daft Punk");
+        assert_eq!(track.album_id.as_deref(), Some("175233"));
+        assert_eq!(track.album_title.as_deref(), Some("Discovery"));
+        assert_eq!(track.album_artists[0].name, "Daft Punk");
+        assert_eq!((track.disc_number, track.track_number), (Some(1), Some(1)));
+        assert_eq!(track.year, Some(2001));
+        assert_eq!(track.genre.as_deref(), Some("dance"));
+        assert!(track.explicit);
+        assert!(track.available);
+        assert_eq!(track.regions, vec!["RUSSIA"]);
+        assert!(track.lyrics_available);
+        assert_eq!(track.loudness, Some(-7.84));
+        assert_eq!(track.peak, Some(0.62));
+        assert_eq!(
+            track.cover_url("200x200").as_deref(),
+            Some("https://avatars.yandex.net/get-music-content/38044/b1f0b3b4.a.175233-1/200x200")
+        );
+    }
+
+    #[test]
+    fn track_survives_serialization() {
+        let info: TrackInfo = serde_json::from_value(track_json()).unwrap();
+        let track = Track::new(info, Client::unauthorized("http://localhost"));
+
+        let json = serde_json::to_string(&track).unwrap();
+        let restored: Track = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.id, "1710808");
+        assert_eq!(restored.artists[0].id, "27003");
+        assert_eq!(
+            restored.total_duration(),
+            Some(Duration::from_millis(320357))
+        );
+        assert!(restored.explicit);
+        assert!(restored.client.is_none());
+    }
+
+    fn quality(codec: &str, bitrate: u32) -> Quality {
+        Quality {
+            codec: codec.to_string(),
+            bitrate,
+        }
+    }
+
+    #[test]
+    fn preference_chooses_variant_to_download() {
+        let variants = [
+            quality("mp3", 192),
+            quality("aac", 320),
+            quality("mp3", 320),
+            quality("flac", 1411),
+            quality("mp3", 128),
+        ];
+        let choose = |lossless, max_bitrate| {
+            QualityPreference {
+                lossless,
+                max_bitrate,
+            }
+            .choose(&variants)
+            .cloned()
+        };
+
+        assert_eq!(choose(true, None), Some(quality("flac", 1411)));
+        assert_eq!(choose(false, None), Some(quality("mp3", 320)));
+        assert_eq!(choose(true, Some(192)), Some(quality("mp3", 192)));
+        assert_eq!(choose(false, Some(64)), Some(quality("mp3", 128)));
+        assert_eq!(
+            QualityPreference::default().choose(&[quality("flac", 1411)]),
+            Some(&quality("flac", 1411))
+        );
+        assert_eq!(
+            QualityPreference::default().choose(&[quality("opus", 128)]),
+            None
+        );
+    }
+}